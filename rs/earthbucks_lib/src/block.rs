@@ -5,6 +5,7 @@ use crate::header::Header;
 use crate::tx::Tx;
 use crate::var_int::VarInt;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Block {
     pub header: Header,
     pub txs: Vec<Tx>,
@@ -16,15 +17,17 @@ impl Block {
     }
 
     pub fn from_buf_reader(br: &mut BufReader) -> Result<Self, EbxError> {
-        let header = Header::from_buf_reader(br)?;
-        let tx_count_varint = VarInt::from_buf_reader(br)?;
-        if !tx_count_varint.is_minimal() {
-            return Err(EbxError::NonMinimalEncodingError { source: None });
-        }
-        let tx_count = tx_count_varint.to_u64()? as usize;
+        let header = br.read_field("header", Header::from_buf_reader)?;
+        let tx_count = br.read_field("tx_count", |br| {
+            let tx_count_varint = VarInt::from_buf_reader(br)?;
+            if !tx_count_varint.is_minimal() {
+                return Err(EbxError::NonMinimalEncodingError { source: None });
+            }
+            tx_count_varint.to_u64()
+        })? as usize;
         let mut txs = vec![];
-        for _ in 0..tx_count {
-            let tx = Tx::from_buf_reader(br)?;
+        for i in 0..tx_count {
+            let tx = br.read_indexed_field("txs", i, Tx::from_buf_reader)?;
             txs.push(tx);
         }
         Ok(Self { header, txs })
@@ -61,6 +64,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
@@ -82,6 +86,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
@@ -105,6 +110,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
@@ -129,6 +135,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
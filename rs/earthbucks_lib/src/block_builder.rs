@@ -1,6 +1,7 @@
 use crate::block::Block;
 use crate::header::Header;
 use crate::merkle_txs::MerkleTxs;
+use crate::network_params::NetworkParams;
 use crate::script::Script;
 use crate::tx::Tx;
 use crate::tx_in::TxIn;
@@ -28,8 +29,13 @@ impl BlockBuilder {
         Self::new(header, txs, merkle_txs)
     }
 
-    pub fn from_genesis(output_script: Script, output_amount: u64, new_timestamp: u64) -> Self {
-        let mut header = Header::from_genesis(new_timestamp);
+    pub fn from_genesis(
+        output_script: Script,
+        output_amount: u64,
+        new_timestamp: u64,
+        params: &NetworkParams,
+    ) -> Self {
+        let mut header = Header::from_genesis(new_timestamp, params);
         let tx_input = TxIn::from_coinbase(output_script.clone());
         let tx_output = TxOut::new(output_amount, output_script.clone());
         let coinbase_tx = Tx::new(0, vec![tx_input], vec![tx_output], 0);
@@ -37,6 +43,7 @@ impl BlockBuilder {
         let merkle_txs = MerkleTxs::new(txs.clone());
         let root: [u8; 32] = merkle_txs.root;
         header.merkle_root = root;
+        header.n_transactions = txs.len() as u64;
         Self::new(header, txs, merkle_txs)
     }
 }
@@ -55,6 +62,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
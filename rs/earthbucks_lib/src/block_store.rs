@@ -0,0 +1,426 @@
+use crate::block::Block;
+use crate::buf_reader::BufReader;
+use crate::buf_writer::BufWriter;
+use crate::error::EbxError;
+use crate::header::Header;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockLocation {
+    pub segment_num: u32,
+    pub offset: u64, // offset of the block record in the segment
+    pub len: u32,    // length of the serialized block
+}
+
+impl BlockLocation {
+    pub const SIZE: usize = 4 + 8 + 4;
+}
+
+// blocks are appended to numbered segment files (blk00000.dat, ...), each
+// block as a record of: block len (u32) | block. once a segment reaches its
+// max size a new one is started. the index from block id to location is
+// appended to index.dat after each block is written, so on open the index is
+// loaded and only the tail of the last segment (blocks written after the last
+// index entry, e.g. because of a crash) needs to be rescanned.
+pub struct BlockStore {
+    dir: PathBuf,
+    max_segment_size: u64,
+    segments: Vec<Mutex<File>>, // readers share a segment, and each seeks before reading
+    last_segment_len: u64,
+    index_file: File,
+    by_id: HashMap<[u8; 32], BlockLocation>,
+    by_block_num: HashMap<u32, Vec<[u8; 32]>>,
+}
+
+impl BlockStore {
+    pub const DEFAULT_MAX_SEGMENT_SIZE: u64 = 128 * 1024 * 1024;
+    const INDEX_ENTRY_SIZE: usize = 32 + 4 + BlockLocation::SIZE;
+
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, EbxError> {
+        Self::open_with_max_segment_size(dir, BlockStore::DEFAULT_MAX_SEGMENT_SIZE)
+    }
+
+    pub fn open_with_max_segment_size(
+        dir: impl AsRef<Path>,
+        max_segment_size: u64,
+    ) -> Result<Self, EbxError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let index_file = BlockStore::open_file(&dir.join("index.dat"))?;
+        let mut store = Self {
+            dir,
+            max_segment_size,
+            segments: Vec::new(),
+            last_segment_len: 0,
+            index_file,
+            by_id: HashMap::new(),
+            by_block_num: HashMap::new(),
+        };
+        let mut segment_num = 0;
+        while store.segment_path(segment_num).exists() {
+            let file = BlockStore::open_file(&store.segment_path(segment_num))?;
+            store.segments.push(Mutex::new(file));
+            segment_num += 1;
+        }
+        if store.segments.is_empty() {
+            let file = BlockStore::open_file(&store.segment_path(0))?;
+            store.segments.push(Mutex::new(file));
+        }
+        store.load_index()?;
+        store.recover_last_segment()?;
+        Ok(store)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_id.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_id.is_empty()
+    }
+
+    pub fn contains(&self, id: &[u8; 32]) -> bool {
+        self.by_id.contains_key(id)
+    }
+
+    pub fn get_location(&self, id: &[u8; 32]) -> Option<BlockLocation> {
+        self.by_id.get(id).copied()
+    }
+
+    // there can be more than one block with the same number if there are forks
+    pub fn get_ids_by_block_num(&self, block_num: u32) -> Vec<[u8; 32]> {
+        self.by_block_num
+            .get(&block_num)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn put_block(&mut self, block: &Block) -> Result<BlockLocation, EbxError> {
+        let id = block.header.id();
+        if let Some(location) = self.by_id.get(&id) {
+            return Ok(*location);
+        }
+        let block_buf = block.to_buf();
+        let record_len = 4 + block_buf.len() as u64;
+        if self.last_segment_len > 0 && self.last_segment_len + record_len > self.max_segment_size {
+            let segment_num = self.segments.len() as u32;
+            let file = BlockStore::open_file(&self.segment_path(segment_num))?;
+            self.segments.push(Mutex::new(file));
+            self.last_segment_len = 0;
+        }
+        let location = BlockLocation {
+            segment_num: self.segments.len() as u32 - 1,
+            offset: self.last_segment_len,
+            len: block_buf.len() as u32,
+        };
+        let mut record = BufWriter::new();
+        record.write_u32_be(location.len);
+        record.write(block_buf);
+        let segment = BlockStore::segment_mut(self.segments.last_mut().unwrap());
+        segment.seek(SeekFrom::Start(location.offset))?;
+        segment.write_all(&record.to_buf())?;
+        self.last_segment_len += record_len;
+
+        self.write_index_entry(&id, block.header.block_num, &location)?;
+        self.insert_index_entry(id, block.header.block_num, location);
+        Ok(location)
+    }
+
+    pub fn get_header(&self, id: &[u8; 32]) -> Result<Option<Header>, EbxError> {
+        let location = match self.by_id.get(id) {
+            Some(location) => *location,
+            None => return Ok(None),
+        };
+        let buf = self.read_at(&location, Header::SIZE)?;
+        Ok(Some(Header::from_buf(buf.try_into().unwrap())?))
+    }
+
+    pub fn get_block(&self, id: &[u8; 32]) -> Result<Option<Block>, EbxError> {
+        let location = match self.by_id.get(id) {
+            Some(location) => *location,
+            None => return Ok(None),
+        };
+        let buf = self.read_at(&location, location.len as usize)?;
+        Ok(Some(Block::from_buf(buf)?))
+    }
+
+    pub fn sync(&self) -> Result<(), EbxError> {
+        BlockStore::lock_segment(self.segments.last().unwrap()).sync_all()?;
+        self.index_file.sync_all()?;
+        Ok(())
+    }
+
+    fn segment_path(&self, segment_num: u32) -> PathBuf {
+        self.dir.join(format!("blk{:05}.dat", segment_num))
+    }
+
+    fn open_file(path: &Path) -> Result<File, EbxError> {
+        Ok(OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?)
+    }
+
+    fn read_at(&self, location: &BlockLocation, len: usize) -> Result<Vec<u8>, EbxError> {
+        let mut segment = BlockStore::lock_segment(&self.segments[location.segment_num as usize]);
+        segment.seek(SeekFrom::Start(location.offset + 4))?;
+        let mut buf = vec![0u8; len];
+        segment.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    // a poisoned lock is fine, because every read seeks first
+    fn lock_segment(segment: &Mutex<File>) -> MutexGuard<'_, File> {
+        segment.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn segment_mut(segment: &mut Mutex<File>) -> &mut File {
+        segment.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn insert_index_entry(&mut self, id: [u8; 32], block_num: u32, location: BlockLocation) {
+        self.by_id.insert(id, location);
+        self.by_block_num.entry(block_num).or_default().push(id);
+    }
+
+    fn write_index_entry(
+        &mut self,
+        id: &[u8; 32],
+        block_num: u32,
+        location: &BlockLocation,
+    ) -> Result<(), EbxError> {
+        let mut bw = BufWriter::new();
+        bw.write(id.to_vec());
+        bw.write_u32_be(block_num);
+        bw.write_u32_be(location.segment_num);
+        bw.write_u64_be(location.offset);
+        bw.write_u32_be(location.len);
+        self.index_file.seek(SeekFrom::End(0))?;
+        self.index_file.write_all(&bw.to_buf())?;
+        Ok(())
+    }
+
+    fn load_index(&mut self) -> Result<(), EbxError> {
+        let mut buf = Vec::new();
+        self.index_file.seek(SeekFrom::Start(0))?;
+        self.index_file.read_to_end(&mut buf)?;
+        // a partially written entry at the end is dropped
+        let entry_count = buf.len() / BlockStore::INDEX_ENTRY_SIZE;
+        let valid_len = entry_count * BlockStore::INDEX_ENTRY_SIZE;
+        buf.truncate(valid_len);
+        let mut br = BufReader::new(buf);
+        let mut index_len = 0;
+        for _ in 0..entry_count {
+            let id: [u8; 32] = br.read(32)?.try_into().unwrap();
+            let block_num = br.read_u32_be()?;
+            let location = BlockLocation {
+                segment_num: br.read_u32_be()?,
+                offset: br.read_u64_be()?,
+                len: br.read_u32_be()?,
+            };
+            // entries for blocks that never made it to disk are dropped
+            let segment = match self.segments.get_mut(location.segment_num as usize) {
+                Some(segment) => BlockStore::segment_mut(segment),
+                None => break,
+            };
+            if location.offset + 4 + location.len as u64 > segment.metadata()?.len() {
+                break;
+            }
+            self.insert_index_entry(id, block_num, location);
+            index_len += BlockStore::INDEX_ENTRY_SIZE;
+        }
+        self.index_file.set_len(index_len as u64)?;
+        Ok(())
+    }
+
+    fn recover_last_segment(&mut self) -> Result<(), EbxError> {
+        let last_segment_num = self.segments.len() as u32 - 1;
+        let mut offset = self
+            .by_id
+            .values()
+            .filter(|location| location.segment_num == last_segment_num)
+            .map(|location| location.offset + 4 + location.len as u64)
+            .max()
+            .unwrap_or(0);
+        let segment = BlockStore::segment_mut(&mut self.segments[last_segment_num as usize]);
+        let mut tail = Vec::new();
+        segment.seek(SeekFrom::Start(offset))?;
+        segment.read_to_end(&mut tail)?;
+
+        let mut recovered = Vec::new();
+        let mut br = BufReader::new(tail);
+        while br.remainder_len() >= 4 {
+            let len = br.read_u32_be()?;
+            if br.remainder_len() < len as usize {
+                break;
+            }
+            let block = match Block::from_buf(br.read(len as usize)?) {
+                Ok(block) => block,
+                Err(_) => break,
+            };
+            let location = BlockLocation {
+                segment_num: last_segment_num,
+                offset,
+                len,
+            };
+            recovered.push((block.header.id(), block.header.block_num, location));
+            offset += 4 + len as u64;
+        }
+        // anything after the last complete block is a partial write
+        segment.set_len(offset)?;
+        self.last_segment_len = offset;
+
+        for (id, block_num, location) in recovered {
+            self.write_index_entry(&id, block_num, &location)?;
+            self.insert_index_entry(id, block_num, location);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buf::EbxBuf;
+    use crate::network_params::NetworkParams;
+    use crate::tx::Tx;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "earthbucks-blocks-{}",
+            <[u8; 8]>::from_random().to_strict_hex()
+        ))
+    }
+
+    fn block(block_num: u32, timestamp: u64) -> Block {
+        let mut header = Header::from_genesis(timestamp, &NetworkParams::MAINNET);
+        header.block_num = block_num;
+        let tx = Tx::new(0, vec![], vec![], block_num);
+        Block::new(header, vec![tx])
+    }
+
+    #[test]
+    fn test_put_and_get() {
+        let dir = temp_dir();
+        let mut store = BlockStore::open(&dir).unwrap();
+        let block0 = block(0, 0);
+        let block1 = block(1, 1);
+        let block1b = block(1, 2);
+        store.put_block(&block0).unwrap();
+        store.put_block(&block1).unwrap();
+        store.put_block(&block1b).unwrap();
+        // putting the same block twice is a no-op
+        let location = store.put_block(&block1).unwrap();
+        assert_eq!(store.get_location(&block1.header.id()), Some(location));
+        assert_eq!(store.len(), 3);
+
+        let header = store.get_header(&block1.header.id()).unwrap().unwrap();
+        assert_eq!(header, block1.header);
+        let block = store.get_block(&block1b.header.id()).unwrap().unwrap();
+        assert_eq!(block.to_buf(), block1b.to_buf());
+        assert_eq!(
+            store.get_ids_by_block_num(1),
+            vec![block1.header.id(), block1b.header.id()]
+        );
+        assert!(store.get_block(&[0; 32]).unwrap().is_none());
+
+        drop(store);
+        let store = BlockStore::open(&dir).unwrap();
+        assert_eq!(store.len(), 3);
+        let block = store.get_block(&block0.header.id()).unwrap().unwrap();
+        assert_eq!(block.to_buf(), block0.to_buf());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_reads() {
+        let dir = temp_dir();
+        let mut store = BlockStore::open(&dir).unwrap();
+        let blocks: Vec<Block> = (0..16).map(|i| block(i, i as u64)).collect();
+        for block in &blocks {
+            store.put_block(block).unwrap();
+        }
+        let store = &store;
+        std::thread::scope(|scope| {
+            for block in &blocks {
+                scope.spawn(move || {
+                    for _ in 0..100 {
+                        let header = store.get_header(&block.header.id()).unwrap().unwrap();
+                        assert_eq!(header, block.header);
+                    }
+                });
+            }
+        });
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_segments() {
+        let dir = temp_dir();
+        let block_size = 4 + block(0, 0).to_buf().len() as u64;
+        let mut store = BlockStore::open_with_max_segment_size(&dir, block_size * 2).unwrap();
+        for i in 0..5 {
+            store.put_block(&block(i, i as u64)).unwrap();
+        }
+        let location = store.get_location(&block(4, 4).header.id()).unwrap();
+        assert_eq!(location.segment_num, 2);
+        assert_eq!(location.offset, 0);
+
+        drop(store);
+        let mut store = BlockStore::open_with_max_segment_size(&dir, block_size * 2).unwrap();
+        let location = store.put_block(&block(5, 5)).unwrap();
+        assert_eq!(location.segment_num, 2);
+        assert_eq!(location.offset, block_size);
+        for i in 0..6 {
+            let id = block(i, i as u64).header.id();
+            assert_eq!(store.get_header(&id).unwrap().unwrap().block_num, i);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_after_crash() {
+        let dir = temp_dir();
+        let mut store = BlockStore::open(&dir).unwrap();
+        store.put_block(&block(0, 0)).unwrap();
+        store.put_block(&block(1, 1)).unwrap();
+        drop(store);
+
+        // crash after the second block was written but before it was indexed,
+        // and in the middle of writing a third block
+        let index_path = dir.join("index.dat");
+        let index_len = fs::metadata(&index_path).unwrap().len();
+        let index_file = OpenOptions::new().write(true).open(&index_path).unwrap();
+        index_file
+            .set_len(index_len - BlockStore::INDEX_ENTRY_SIZE as u64 + 3)
+            .unwrap();
+        let segment_path = dir.join("blk00000.dat");
+        let segment_len = fs::metadata(&segment_path).unwrap().len();
+        let mut segment = OpenOptions::new().append(true).open(&segment_path).unwrap();
+        let partial_block = block(2, 2).to_buf();
+        segment
+            .write_all(&(partial_block.len() as u32).to_be_bytes())
+            .unwrap();
+        segment.write_all(&partial_block[..10]).unwrap();
+        drop(segment);
+
+        let mut store = BlockStore::open(&dir).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(fs::metadata(&segment_path).unwrap().len(), segment_len);
+        let header = store.get_header(&block(1, 1).header.id()).unwrap().unwrap();
+        assert_eq!(header.block_num, 1);
+
+        store.put_block(&block(2, 2)).unwrap();
+        drop(store);
+        let store = BlockStore::open(&dir).unwrap();
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get_ids_by_block_num(2), vec![block(2, 2).header.id()]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,263 @@
+use crate::block::Block;
+use crate::domain::Domain;
+use crate::error::EbxError;
+use crate::header::Header;
+use crate::header_chain::HeaderChain;
+use crate::mempool::Mempool;
+use crate::merkle_txs::MerkleTxs;
+use crate::pkh::Pkh;
+use crate::utxo_store::UtxoStore;
+use crate::var_int::VarInt;
+
+// assembles the next block on top of the longest chain: a coinbase paying the
+// miner, followed by as many mempool txs as fit. the block is valid except
+// for PoW.
+pub struct BlockTemplate<'a, S: UtxoStore> {
+    pub lch: &'a HeaderChain,
+    pub mempool: &'a Mempool,
+    pub utxo_store: &'a S, // the utxo set at the tip of lch
+    pub pkh: Pkh,
+    pub domain: String,
+    pub max_block_size: usize,
+}
+
+impl<'a, S: UtxoStore> BlockTemplate<'a, S> {
+    pub const DEFAULT_MAX_BLOCK_SIZE: usize = 1_000_000;
+
+    pub fn new(
+        lch: &'a HeaderChain,
+        mempool: &'a Mempool,
+        utxo_store: &'a S,
+        pkh: Pkh,
+        domain: String,
+    ) -> Self {
+        Self {
+            lch,
+            mempool,
+            utxo_store,
+            pkh,
+            domain,
+            max_block_size: Self::DEFAULT_MAX_BLOCK_SIZE,
+        }
+    }
+
+    pub fn build_at(&self, timestamp: u64) -> Result<Block, EbxError> {
+        if !Domain::is_valid_domain(&self.domain) {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "invalid domain".to_string(),
+            });
+        }
+        let block_num = self.lch.len() as u32;
+        let coinbase_tx = self.lch.get_next_coinbase_tx(&self.pkh, &self.domain);
+        let mut txs_size = coinbase_tx.to_buf().len();
+        if Header::SIZE + 1 + txs_size > self.max_block_size {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "max block size too small for coinbase".to_string(),
+            });
+        }
+
+        // the mempool's txs are re-added one by one to a fresh pool, which
+        // checks them against the utxo set at this block num. a tx that does
+        // not fit or is no longer valid is skipped, and then so are its
+        // children because their inputs are missing.
+        let mut selected = Mempool::new();
+        for tx in self.mempool.get_txs() {
+            let tx_size = tx.to_buf().len();
+            let tx_count = selected.len() as u64 + 2;
+            let block_size =
+                Header::SIZE + VarInt::from_u64(tx_count).to_buf().len() + txs_size + tx_size;
+            if block_size > self.max_block_size {
+                continue;
+            }
+            if selected
+                .add_tx(tx.clone(), self.utxo_store, block_num, &self.lch.params)
+                .is_ok()
+            {
+                txs_size += tx_size;
+            }
+        }
+
+        let mut txs = vec![coinbase_tx];
+        txs.extend(selected.get_txs().into_iter().cloned());
+        let merkle_txs = MerkleTxs::new(txs);
+        let n_transactions = merkle_txs.txs.len() as u64;
+        let header = self
+            .lch
+            .get_next_header(merkle_txs.root, n_transactions, timestamp)?;
+        Ok(Block::new(header, merkle_txs.txs))
+    }
+
+    pub fn build_now(&self) -> Result<Block, EbxError> {
+        self.build_at(Header::get_new_timestamp())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_verifier::BlockVerifier;
+    use crate::key_pair::KeyPair;
+    use crate::network_params::NetworkParams;
+    use crate::out_point::OutPoint;
+    use crate::pkh_key_map::PkhKeyMap;
+    use crate::script::Script;
+    use crate::tx::Tx;
+    use crate::tx_in::TxIn;
+    use crate::tx_out::TxOut;
+    use crate::tx_out_bn_map::TxOutBnMap;
+    use crate::tx_signer::TxSigner;
+    use crate::utxo_set::UtxoSet;
+
+    const DOMAIN: &str = "example.com";
+
+    struct Setup {
+        lch: HeaderChain,
+        utxo_set: UtxoSet,
+        mempool: Mempool,
+        pkh: Pkh,
+        txs: Vec<Tx>,
+    }
+
+    // a genesis block paying to a key, and a chain of three txs in the
+    // mempool, each spending the previous one
+    fn setup() -> Setup {
+        let key = KeyPair::from_random();
+        let pkh = Pkh::from_pub_key_buffer(key.pub_key.buf.to_vec());
+        let mut pkh_key_map = PkhKeyMap::new();
+        pkh_key_map.add(key, &pkh.buf);
+
+        let mut lch = HeaderChain::new();
+        let coinbase_tx = lch.get_next_coinbase_tx(&pkh, &DOMAIN.to_string());
+        let merkle_root = MerkleTxs::new(vec![coinbase_tx.clone()]).root;
+        let mut genesis = Header::from_genesis(0, &NetworkParams::MAINNET);
+        genesis.merkle_root = merkle_root;
+        let mut utxo_set: UtxoSet = UtxoSet::default();
+        utxo_set
+            .connect_block(&Block::new(genesis.clone(), vec![coinbase_tx.clone()]))
+            .unwrap();
+        lch.add(genesis);
+
+        let mut signing_map = utxo_set.utxo_store.clone();
+        let mut mempool = Mempool::new();
+        let mut txs = Vec::new();
+        let mut out_point = OutPoint::new(coinbase_tx.id(), 0);
+        let value = coinbase_tx.outputs[0].value;
+        for _ in 0..3 {
+            let tx_in = TxIn::new(
+                out_point.tx_id,
+                out_point.tx_out_num,
+                Script::from_pkh_input_placeholder(),
+                0,
+            );
+            let tx_out = TxOut::new(value, Script::from_pkh_output(&pkh.buf));
+            let tx = Tx::new(0, vec![tx_in], vec![tx_out], 0);
+            let mut tx_signer = TxSigner::new(tx, &signing_map, &pkh_key_map, 1);
+            tx_signer.sign_input(0).unwrap();
+            let tx = tx_signer.tx;
+            signing_map.add_tx_outputs(&tx, 1);
+            mempool
+                .add_tx(tx.clone(), &utxo_set.utxo_store, 1, &NetworkParams::MAINNET)
+                .unwrap();
+            out_point = OutPoint::new(tx.id(), 0);
+            txs.push(tx);
+        }
+        Setup {
+            lch,
+            utxo_set,
+            mempool,
+            pkh,
+            txs,
+        }
+    }
+
+    fn is_valid_except_pow(block: Block, utxo_store: &TxOutBnMap, lch: &HeaderChain) -> bool {
+        let mut block_verifier = BlockVerifier::new(block, utxo_store.clone(), lch);
+        block_verifier.verify_n_transactions().is_ok()
+            && block_verifier.verify_merkle_root().is_ok()
+            && block_verifier.verify_txs().is_ok()
+    }
+
+    #[test]
+    fn test_build() {
+        let s = setup();
+        let template = BlockTemplate::new(
+            &s.lch,
+            &s.mempool,
+            &s.utxo_set.utxo_store,
+            s.pkh.clone(),
+            DOMAIN.to_string(),
+        );
+        let block = template.build_at(600_000).unwrap();
+        assert_eq!(block.header.block_num, 1);
+        assert_eq!(block.header.prev_block_id, s.lch.get_tip().unwrap().id());
+        assert!(block.txs[0].is_coinbase());
+        assert_eq!(block.txs[1..], s.txs[..]);
+        assert!(block.header.is_target_valid(s.lch.headers(), &s.lch.params));
+        assert!(is_valid_except_pow(block, &s.utxo_set.utxo_store, &s.lch));
+    }
+
+    #[test]
+    fn test_build_with_size_limit() {
+        let s = setup();
+        let mut template = BlockTemplate::new(
+            &s.lch,
+            &s.mempool,
+            &s.utxo_set.utxo_store,
+            s.pkh.clone(),
+            DOMAIN.to_string(),
+        );
+        let full_block = template.build_at(600_000).unwrap();
+        let tx_size = s.txs[0].to_buf().len();
+        let coinbase_block_size = full_block.to_buf().len() - 3 * tx_size;
+
+        // room for two txs: the third is left out
+        template.max_block_size = coinbase_block_size + 2 * tx_size;
+        let block = template.build_at(600_000).unwrap();
+        assert_eq!(block.txs[1..], s.txs[..2]);
+        assert_eq!(block.to_buf().len(), template.max_block_size);
+        assert!(is_valid_except_pow(block, &s.utxo_set.utxo_store, &s.lch));
+
+        // room for no txs
+        template.max_block_size = coinbase_block_size;
+        let block = template.build_at(600_000).unwrap();
+        assert_eq!(block.txs.len(), 1);
+
+        template.max_block_size = Header::SIZE;
+        assert!(template.build_at(600_000).is_err());
+    }
+
+    #[test]
+    fn test_build_skips_children_of_invalid_txs() {
+        let mut s = setup();
+        // the output spent by the first tx is gone from the utxo set (e.g.
+        // spent by a conflicting tx in another block), so the first tx is
+        // invalid and the rest of the chain has no inputs
+        s.utxo_set
+            .utxo_store
+            .remove(&s.txs[0].inputs[0].input_tx_id, 0);
+        let template = BlockTemplate::new(
+            &s.lch,
+            &s.mempool,
+            &s.utxo_set.utxo_store,
+            s.pkh.clone(),
+            DOMAIN.to_string(),
+        );
+        let block = template.build_at(600_000).unwrap();
+        assert_eq!(block.txs.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_domain() {
+        let s = setup();
+        let template = BlockTemplate::new(
+            &s.lch,
+            &s.mempool,
+            &s.utxo_set.utxo_store,
+            s.pkh.clone(),
+            "invalid".to_string(),
+        );
+        assert!(template.build_at(600_000).is_err());
+    }
+}
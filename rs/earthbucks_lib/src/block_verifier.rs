@@ -1,142 +1,301 @@
 use crate::block::Block;
 use crate::domain::Domain;
+use crate::error::{BlockError, TxError};
 use crate::header::Header;
 use crate::header_chain::HeaderChain;
+use crate::merkle_proof::MerkleProof;
 use crate::merkle_txs::MerkleTxs;
-use crate::tx_out_bn_map::TxOutBnMap;
+use crate::out_point::OutPoint;
 use crate::tx_verifier::TxVerifier;
+use crate::utxo_store::UtxoStore;
 
-pub struct BlockVerifier<'a> {
+pub struct BlockVerifier<'a, S: UtxoStore> {
     pub block: Block,
-    pub tx_out_bn_map: TxOutBnMap, // from earlier blocks
-    pub lch: &'a HeaderChain,      // longest chain
+    pub utxo_store: S,        // from earlier blocks
+    pub lch: &'a HeaderChain, // longest chain
 }
 
-impl<'a> BlockVerifier<'a> {
-    pub fn new(block: Block, tx_out_bn_map: TxOutBnMap, lch: &'a HeaderChain) -> Self {
+impl<'a, S: UtxoStore> BlockVerifier<'a, S> {
+    pub fn new(block: Block, utxo_store: S, lch: &'a HeaderChain) -> Self {
         Self {
             block,
-            tx_out_bn_map,
+            utxo_store,
             lch,
         }
     }
 
-    pub fn header_is_valid_at(&mut self, timestamp: u64) -> bool {
-        let header = &self.block.header;
-        let lch = &self.lch;
-        lch.new_header_is_valid_at(header, timestamp)
+    pub fn verify_header_at(&self, timestamp: u64) -> Result<(), BlockError> {
+        self.lch
+            .verify_new_header_at(&self.block.header, timestamp)
+            .map_err(BlockError::Header)
     }
 
-    pub fn merkle_root_is_valid(&self) -> bool {
+    pub fn verify_merkle_root(&self) -> Result<(), BlockError> {
         let txs = &self.block.txs;
         let merkle_root = self.block.header.merkle_root;
         // TODO: Eliminate clone of txs
         let merkle_txs = MerkleTxs::new(txs.clone());
-        merkle_txs.root == merkle_root
+        if merkle_txs.root != merkle_root {
+            return Err(BlockError::InvalidMerkleRoot);
+        }
+        Ok(())
     }
 
-    pub fn has_valid_coinbase(&self) -> bool {
+    pub fn verify_n_transactions(&self) -> Result<(), BlockError> {
+        if self.block.header.n_transactions != self.block.txs.len() as u64 {
+            return Err(BlockError::InvalidNTransactions);
+        }
+        Ok(())
+    }
+
+    // a block with a tx repeated at the end has the same merkle root as
+    // without it, so duplicates are rejected before the merkle root is checked
+    pub fn verify_unique_tx_ids(&self) -> Result<(), BlockError> {
+        let tx_ids: Vec<[u8; 32]> = self.block.txs.iter().map(|tx| tx.id()).collect();
+        match MerkleProof::find_duplicate_leaf(&tx_ids) {
+            Some(n_tx) => Err(BlockError::DuplicateTx { n_tx }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn verify_coinbase(&self) -> Result<(), BlockError> {
         // 1. coinbase tx is first tx
         let txs = &self.block.txs;
         if txs.is_empty() {
-            return false;
+            return Err(BlockError::MissingCoinbase);
         }
         let coinbase_tx = &txs[0];
         if !coinbase_tx.is_coinbase() {
-            return false;
+            return Err(BlockError::MissingCoinbase);
         }
         // 2. lockNum equals block number
         if coinbase_tx.lock_abs != self.block.header.block_num {
-            return false;
+            return Err(BlockError::InvalidCoinbaseLockAbs);
         }
         // 3. version is 1
         if coinbase_tx.version != 1 {
-            return false;
+            return Err(BlockError::InvalidCoinbaseVersion);
         }
         // 4. all outputs are pkh
-        for tx_output in &coinbase_tx.outputs {
+        for (n_out, tx_output) in coinbase_tx.outputs.iter().enumerate() {
             if !tx_output.script.is_pkh_output() {
-                return false;
+                return Err(BlockError::InvalidCoinbaseOutput { n_out });
             }
         }
         // 5. output amount is correct
         let total_output_value: u64 = coinbase_tx.outputs.iter().map(|output| output.value).sum();
-        let expected_coinbase_amount = Header::coinbase_amount(self.block.header.block_num);
+        let expected_coinbase_amount =
+            Header::coinbase_amount(self.block.header.block_num, &self.lch.params);
         if total_output_value != expected_coinbase_amount {
-            return false;
+            return Err(BlockError::InvalidCoinbaseAmount);
         }
         // 5. coinbase script is valid (push only)
         let coinbase_input = &coinbase_tx.inputs[0];
         let coinbase_script = &coinbase_input.script;
         if !coinbase_script.is_push_only() {
-            return false;
+            return Err(BlockError::InvalidCoinbaseScript);
         }
         // 6. domain name, top of the stack, is valid
         let mut script_chunks = coinbase_script.chunks.clone();
         if script_chunks.is_empty() {
-            return false;
+            return Err(BlockError::InvalidCoinbaseScript);
         }
         let domain_chunk = script_chunks.pop().unwrap();
         let domain_buf = domain_chunk.buffer.clone().unwrap();
-        let res_domain_str = String::from_utf8(domain_buf);
-        if res_domain_str.is_err() {
-            return false;
-        }
-        let domain_str = res_domain_str.unwrap();
+        let domain_str =
+            String::from_utf8(domain_buf).map_err(|_| BlockError::InvalidCoinbaseDomain)?;
         if !Domain::is_valid_domain(&domain_str) {
-            return false;
+            return Err(BlockError::InvalidCoinbaseDomain);
         }
         // note that we do not verify whether domain is actually responsive and
         // delivers this block. that would require pinging the domain name,
         // which is done elsewhere.
-        true
+        Ok(())
     }
 
-    pub fn txs_are_valid(&mut self) -> bool {
-        if !self.has_valid_coinbase() {
-            return false;
-        }
-        let txs = &self.block.txs[1..];
+    pub fn verify_txs(&mut self) -> Result<(), BlockError> {
+        self.verify_coinbase()?;
         // iterate through all transactions except the first (coinbase tx)
         // verify with verifier
-        // if invalid, return false
+        // if invalid, return the error and the index of the tx
         // if valid, add outputs to tx_output_map and remove used outputs
-        for tx in txs {
-            let mut tx_verifier =
-                TxVerifier::new(tx.clone(), &self.tx_out_bn_map, self.block.header.block_num);
-            if !tx_verifier.verify() {
-                return false;
-            }
-            let header = &self.block.header;
-            let block_num = header.block_num;
-            self.tx_out_bn_map.add_tx_outputs(tx, block_num);
+        let block_num = self.block.header.block_num;
+        for (n_tx, tx) in self.block.txs.iter().enumerate().skip(1) {
+            let mut tx_verifier = TxVerifier::new(tx.clone(), &self.utxo_store, block_num);
+            tx_verifier.script_limits = self.lch.params.script_limits.clone();
+            tx_verifier.verify().map_err(|error| match error {
+                TxError::UtxoStore { message, .. } => BlockError::UtxoStore { n_tx, message },
+                error => BlockError::InvalidTx { n_tx, error },
+            })?;
+            self.utxo_store
+                .add_tx_outputs(tx, block_num)
+                .map_err(|e| BlockError::UtxoStore {
+                    n_tx,
+                    message: e.to_string(),
+                })?;
             // remove used outputs to prevent double spending
             for tx_input in &tx.inputs {
-                self.tx_out_bn_map
-                    .remove(&tx_input.input_tx_id.clone(), tx_input.input_tx_out_num);
+                let out_point = OutPoint::new(tx_input.input_tx_id, tx_input.input_tx_out_num);
+                self.utxo_store.remove_tx_out_bn(&out_point).map_err(|e| {
+                    BlockError::UtxoStore {
+                        n_tx,
+                        message: e.to_string(),
+                    }
+                })?;
             }
         }
-        true
+        Ok(())
+    }
+
+    pub fn verify_at(&mut self, timestamp: u64) -> Result<(), BlockError> {
+        self.verify_header_at(timestamp)?;
+        self.verify_n_transactions()?;
+        self.verify_unique_tx_ids()?;
+        self.verify_merkle_root()?;
+        self.verify_txs()?;
+        Ok(())
     }
 
-    pub fn is_valid_at(&mut self, timestamp: u64) -> bool {
-        if timestamp < self.block.header.timestamp {
-            return false;
+    pub fn verify_now(&mut self) -> Result<(), BlockError> {
+        let timestamp = Header::get_new_timestamp();
+        self.verify_at(timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::error::{EbxError, HeaderError};
+    use crate::key_pair::KeyPair;
+    use crate::network_params::NetworkParams;
+    use crate::pkh::Pkh;
+    use crate::script::Script;
+    use crate::tx::Tx;
+    use crate::tx_in::TxIn;
+    use crate::tx_out::TxOut;
+    use crate::tx_out_bn::TxOutBn;
+    use crate::tx_out_bn_map::TxOutBnMap;
+
+    fn verify(header: &Header, txs: &[Tx], lch: &HeaderChain) -> Result<(), BlockError> {
+        let block = Block::new(header.clone(), txs.to_vec());
+        let mut block_verifier = BlockVerifier::new(block, TxOutBnMap::new(), lch);
+        block_verifier.verify_at(header.timestamp)
+    }
+
+    fn header_with_txs(header: &Header, txs: &[Tx]) -> Header {
+        let mut header = header.clone();
+        header.merkle_root = MerkleTxs::new(txs.to_vec()).root;
+        header.n_transactions = txs.len() as u64;
+        header
+    }
+
+    #[test]
+    fn test_verify_at() {
+        // regtest, so that the genesis header needs no work
+        let lch = HeaderChain::from_params(NetworkParams::REGTEST);
+        let key = KeyPair::from_random();
+        let pkh = Pkh::from_pub_key_buffer(key.pub_key.buf.to_vec());
+        let coinbase_tx = lch.get_next_coinbase_tx(&pkh, &"example.com".to_string());
+        let txs = vec![coinbase_tx.clone()];
+        let header = header_with_txs(&Header::from_genesis(0, &lch.params), &txs);
+        assert_eq!(verify(&header, &txs, &lch), Ok(()));
+
+        let mut bad_header = header.clone();
+        bad_header.version = 1;
+        assert_eq!(
+            verify(&bad_header, &txs, &lch),
+            Err(BlockError::Header(HeaderError::InvalidVersion))
+        );
+
+        let mut bad_header = header.clone();
+        bad_header.n_transactions = 2;
+        assert_eq!(
+            verify(&bad_header, &txs, &lch),
+            Err(BlockError::InvalidNTransactions)
+        );
+
+        let mut bad_header = header.clone();
+        bad_header.merkle_root = [0; 32];
+        assert_eq!(
+            verify(&bad_header, &txs, &lch),
+            Err(BlockError::InvalidMerkleRoot)
+        );
+
+        let mut bad_coinbase_tx = coinbase_tx.clone();
+        bad_coinbase_tx.outputs[0].value += 1;
+        let bad_txs = vec![bad_coinbase_tx];
+        assert_eq!(
+            verify(&header_with_txs(&header, &bad_txs), &bad_txs, &lch),
+            Err(BlockError::InvalidCoinbaseAmount)
+        );
+
+        // the coinbase tx twice, with a header that commits to both
+        let mutated_txs = vec![coinbase_tx.clone(), coinbase_tx.clone()];
+        let error = verify(&header_with_txs(&header, &mutated_txs), &mutated_txs, &lch);
+        assert_eq!(error, Err(BlockError::DuplicateTx { n_tx: 1 }));
+        assert!(error.unwrap_err().is_mutation());
+
+        // the second tx spends an output that does not exist
+        let tx_in = TxIn::new([1; 32], 0, Script::from_pkh_input_placeholder(), 0);
+        let tx_out = TxOut::new(1, Script::from_pkh_output(&pkh.buf));
+        let bad_txs = vec![coinbase_tx, Tx::new(0, vec![tx_in], vec![tx_out], 0)];
+        assert_eq!(
+            verify(&header_with_txs(&header, &bad_txs), &bad_txs, &lch),
+            Err(BlockError::InvalidTx {
+                n_tx: 1,
+                error: TxError::MissingInput { n_in: 0 },
+            })
+        );
+        assert!(!verify(&header_with_txs(&header, &bad_txs), &bad_txs, &lch)
+            .unwrap_err()
+            .is_mutation());
+    }
+
+    // a store that fails to read, like a disk with an i/o error
+    struct FailingUtxoStore;
+
+    impl UtxoStore for FailingUtxoStore {
+        fn get_tx_out_bn(&self, _: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+            Err(EbxError::GenericError {
+                source: None,
+                message: "disk error".to_string(),
+            })
         }
-        if !self.header_is_valid_at(timestamp) {
-            return false;
+
+        fn add_tx_out_bn(&mut self, _: OutPoint, _: TxOutBn) -> Result<(), EbxError> {
+            Ok(())
         }
-        if !self.merkle_root_is_valid() {
-            return false;
+
+        fn remove_tx_out_bn(&mut self, _: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+            Ok(None)
         }
-        if !self.txs_are_valid() {
-            return false;
+
+        fn get_all_tx_out_bns(&self) -> Result<Vec<(OutPoint, TxOutBn)>, EbxError> {
+            Ok(vec![])
         }
-        true
     }
 
-    pub fn is_valid_now(&mut self) -> bool {
-        let timestamp = Header::get_new_timestamp();
-        self.is_valid_at(timestamp)
+    #[test]
+    fn test_utxo_store_error_is_not_invalid_tx() {
+        let lch = HeaderChain::from_params(NetworkParams::REGTEST);
+        let pkh = Pkh::from_pub_key_buffer(KeyPair::from_random().pub_key.buf.to_vec());
+        let coinbase_tx = lch.get_next_coinbase_tx(&pkh, &"example.com".to_string());
+        let tx_in = TxIn::new([1; 32], 0, Script::from_pkh_input_placeholder(), 0);
+        let tx_out = TxOut::new(1, Script::from_pkh_output(&pkh.buf));
+        let txs = vec![coinbase_tx, Tx::new(0, vec![tx_in], vec![tx_out], 0)];
+        let header = header_with_txs(&Header::from_genesis(0, &lch.params), &txs);
+        let block = Block::new(header.clone(), txs);
+        let mut block_verifier = BlockVerifier::new(block, FailingUtxoStore, &lch);
+        let error = block_verifier.verify_at(header.timestamp).unwrap_err();
+        assert_eq!(
+            error,
+            BlockError::UtxoStore {
+                n_tx: 1,
+                message: "ebx error: disk error".to_string(),
+            }
+        );
+        assert!(error.is_utxo_store());
+        assert!(!error.is_mutation());
     }
 }
@@ -9,6 +9,7 @@ lazy_static! {
     static ref RE: Regex = Regex::new(r"^[0-9a-f]*$").unwrap();
 }
 
+#[allow(clippy::manual_is_multiple_of)]
 pub fn is_valid(hex: &str) -> bool {
     RE.is_match(hex) && hex.len() % 2 == 0
 }
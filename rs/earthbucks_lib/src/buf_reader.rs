@@ -22,6 +22,41 @@ impl BufReader {
         self.buf.get_ref().len() - self.buf.position() as usize
     }
 
+    pub fn pos(&self) -> usize {
+        self.buf.position() as usize
+    }
+
+    // reads a field with the given read function. if it fails, the error is
+    // wrapped with the field name and the offset where the field starts.
+    pub fn read_field<T>(
+        &mut self,
+        field: &str,
+        read: impl FnOnce(&mut BufReader) -> Result<T, EbxError>,
+    ) -> Result<T, EbxError> {
+        let offset = self.pos();
+        read(self).map_err(|e| EbxError::ParseError {
+            source: Some(Box::new(e)),
+            field: field.to_string(),
+            offset,
+        })
+    }
+
+    // the same for an item of a list, e.g. inputs[2]. the name is only
+    // formatted if the read fails.
+    pub fn read_indexed_field<T>(
+        &mut self,
+        field: &str,
+        index: usize,
+        read: impl FnOnce(&mut BufReader) -> Result<T, EbxError>,
+    ) -> Result<T, EbxError> {
+        let offset = self.pos();
+        read(self).map_err(|e| EbxError::ParseError {
+            source: Some(Box::new(e)),
+            field: format!("{}[{}]", field, index),
+            offset,
+        })
+    }
+
     pub fn read(&mut self, len: usize) -> Result<Vec<u8>, EbxError> {
         let pos = self.buf.position() as usize;
         if pos + len > self.buf.get_ref().len() {
@@ -148,6 +183,35 @@ mod tests {
         assert_eq!(reader.read(2).unwrap(), vec![4, 5]);
     }
 
+    #[test]
+    fn test_read_field() {
+        let mut reader = BufReader::new(vec![1, 2, 3]);
+        assert_eq!(
+            reader.read_field("a", BufReader::read_u16_be).unwrap(),
+            0x0102
+        );
+        assert_eq!(reader.pos(), 2);
+        match reader.read_field("b", BufReader::read_u16_be) {
+            Err(EbxError::ParseError {
+                source: Some(source),
+                field,
+                offset,
+            }) => {
+                assert_eq!(field, "b");
+                assert_eq!(offset, 2);
+                assert!(matches!(*source, EbxError::NotEnoughDataError { .. }));
+            }
+            _ => panic!("expected a parse error"),
+        }
+        match reader.read_indexed_field("items", 3, BufReader::read_u16_be) {
+            Err(EbxError::ParseError { field, offset, .. }) => {
+                assert_eq!(field, "items[3]");
+                assert_eq!(offset, 3);
+            }
+            _ => panic!("expected a parse error"),
+        }
+    }
+
     #[test]
     fn test_read_u8() {
         let mut reader = BufReader::new(vec![1, 2, 3, 4, 5]);
@@ -0,0 +1,434 @@
+use crate::chain_work::ChainWork;
+use crate::error::EbxError;
+use crate::header::Header;
+use crate::header_chain::HeaderChain;
+use crate::network_params::NetworkParams;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChainEvent {
+    Connect(Header),
+    Disconnect(Header),
+}
+
+#[derive(Debug, Clone)]
+pub struct ChainStateEntry {
+    pub header: Header,
+    pub chain_work: ChainWork, // cumulative work from genesis up to and including this header
+}
+
+// the chain state is a tree of every valid header we have seen. the branch
+// with the most cumulative work is the longest chain (lch), which is kept as a
+// HeaderChain so that it can be used for validation and block building.
+#[derive(Default, Clone)]
+pub struct ChainState {
+    entries: HashMap<[u8; 32], ChainStateEntry>,
+    lch: HeaderChain,
+}
+
+impl ChainState {
+    pub fn new() -> Self {
+        Self::from_params(NetworkParams::MAINNET)
+    }
+
+    pub fn from_params(params: NetworkParams) -> Self {
+        Self {
+            entries: HashMap::new(),
+            lch: HeaderChain::from_params(params),
+        }
+    }
+
+    // the tree and the lch only change with add_header_at, which keeps the
+    // lch on the branch with the most work
+    pub fn entries(&self) -> &HashMap<[u8; 32], ChainStateEntry> {
+        &self.entries
+    }
+
+    pub fn lch(&self) -> &HeaderChain {
+        &self.lch
+    }
+
+    pub fn contains(&self, id: &[u8; 32]) -> bool {
+        self.entries.contains_key(id)
+    }
+
+    pub fn get(&self, id: &[u8; 32]) -> Option<&ChainStateEntry> {
+        self.entries.get(id)
+    }
+
+    pub fn get_tip(&self) -> Option<&Header> {
+        self.lch.get_tip()
+    }
+
+    pub fn get_tip_work(&self) -> ChainWork {
+        match self.get_tip() {
+            Some(tip) => self.entries[&tip.id()].chain_work.clone(),
+            None => ChainWork::default(),
+        }
+    }
+
+    pub fn is_in_lch(&self, header: &Header) -> bool {
+        self.lch.get_index(&header.id()) == Some(header.block_num as usize)
+    }
+
+    // all headers from genesis up to and including the header with this id
+    pub fn get_branch(&self, tip_id: &[u8; 32]) -> Result<Vec<Header>, EbxError> {
+        self.get_branch_tail(tip_id, usize::MAX)
+    }
+
+    // the last len headers of get_branch, or all of them if there are fewer
+    pub fn get_branch_tail(&self, tip_id: &[u8; 32], len: usize) -> Result<Vec<Header>, EbxError> {
+        let mut branch = Vec::new();
+        let mut id = *tip_id;
+        while branch.len() < len {
+            let entry = self.entries.get(&id).ok_or(EbxError::GenericError {
+                source: None,
+                message: "header not found in chain state".to_string(),
+            })?;
+            branch.push(entry.header.clone());
+            if entry.header.is_genesis() {
+                break;
+            }
+            id = entry.header.prev_block_id;
+        }
+        branch.reverse();
+        Ok(branch)
+    }
+
+    // ids for a peer to find where our chains fork: the 10 most recent headers
+    // up to tip_id, then exponentially further apart, and always genesis
+    pub fn get_block_locator(&self, tip_id: &[u8; 32]) -> Vec<[u8; 32]> {
+        let mut locator = Vec::new();
+        let mut id = *tip_id;
+        let mut step = 1;
+        while let Some(entry) = self.entries.get(&id) {
+            locator.push(id);
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            let mut header = &entry.header;
+            for _ in 0..step {
+                if header.is_genesis() {
+                    break;
+                }
+                id = header.prev_block_id;
+                header = &self.entries[&id].header;
+            }
+            if entry.header.is_genesis() {
+                break;
+            }
+        }
+        locator
+    }
+
+    // how we answer a peer's locator: the lch headers after the first locator
+    // id in the lch (or from genesis if there is none), up to and including
+    // stop_id, at most max_headers
+    pub fn get_headers_after_locator(
+        &self,
+        locator: &[[u8; 32]],
+        stop_id: Option<&[u8; 32]>,
+        max_headers: usize,
+    ) -> Vec<Header> {
+        let start = locator
+            .iter()
+            .filter_map(|id| self.entries.get(id))
+            .find(|entry| self.is_in_lch(&entry.header))
+            .map_or(0, |entry| entry.header.block_num as usize + 1);
+        let mut headers = Vec::new();
+        for header in self.lch.headers().iter().skip(start).take(max_headers) {
+            headers.push(header.clone());
+            if Some(&header.id()) == stop_id {
+                break;
+            }
+        }
+        headers
+    }
+
+    pub fn add_header_at(
+        &mut self,
+        header: Header,
+        timestamp: u64,
+    ) -> Result<Vec<ChainEvent>, EbxError> {
+        let id = header.id();
+        if self.entries.contains_key(&id) {
+            return Ok(vec![]);
+        }
+
+        let prev_chain_work = if header.is_genesis() {
+            if !self.entries.is_empty() {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "chain state already has a genesis header".to_string(),
+                });
+            }
+            header.verify_at(&[], timestamp, &self.lch.params)?;
+            ChainWork::default()
+        } else {
+            let prev_entry =
+                self.entries
+                    .get(&header.prev_block_id)
+                    .ok_or(EbxError::GenericError {
+                        source: None,
+                        message: "previous header not found in chain state".to_string(),
+                    })?;
+            let prev_chain_work = prev_entry.chain_work.clone();
+            if self.get_tip().map(|tip| tip.id()) == Some(header.prev_block_id) {
+                self.lch.verify_new_header_at(&header, timestamp)?;
+            } else {
+                // the target and median time past only depend on the last
+                // headers of the branch, so a side branch is not rebuilt from
+                // genesis for every header
+                let params = &self.lch.params;
+                let len = (params.blocks_per_target_adj_period as usize)
+                    .max(params.median_time_past_len as usize);
+                let branch = self.get_branch_tail(&header.prev_block_id, len)?;
+                header.verify_at(&branch, timestamp, params)?;
+            }
+            prev_chain_work
+        };
+
+        let chain_work = prev_chain_work + header.work();
+        let is_new_best = chain_work.is_more_work_than(&self.get_tip_work());
+        self.entries
+            .insert(id, ChainStateEntry { header, chain_work });
+
+        if is_new_best {
+            self.set_tip(&id)
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    pub fn add_header_now(&mut self, header: Header) -> Result<Vec<ChainEvent>, EbxError> {
+        self.add_header_at(header, Header::get_new_timestamp())
+    }
+
+    fn set_tip(&mut self, tip_id: &[u8; 32]) -> Result<Vec<ChainEvent>, EbxError> {
+        // walk back from the new tip until we reach the fork point, which is
+        // the most recent header the new branch shares with the current lch.
+        let mut to_connect: Vec<Header> = Vec::new();
+        let mut id = *tip_id;
+        loop {
+            let header = &self.entries[&id].header;
+            if self.is_in_lch(header) {
+                break;
+            }
+            to_connect.push(header.clone());
+            if header.is_genesis() {
+                break;
+            }
+            id = header.prev_block_id;
+        }
+        let fork_len = match to_connect.last() {
+            Some(header) => header.block_num as usize,
+            None => self.lch.len(),
+        };
+
+        let mut events = Vec::new();
+        while self.lch.len() > fork_len {
+            let header = self.lch.pop().unwrap();
+            events.push(ChainEvent::Disconnect(header));
+        }
+        for header in to_connect.into_iter().rev() {
+            self.lch.add(header.clone());
+            events.push(ChainEvent::Connect(header));
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next_header(chain_state: &ChainState, prev_id: &[u8; 32], timestamp: u64) -> Header {
+        let branch = chain_state.get_branch(prev_id).unwrap();
+        Header::from_lch(&branch, timestamp, &NetworkParams::MAINNET)
+            .unwrap()
+            .mine_pow5()
+    }
+
+    #[test]
+    fn test_extend_lch() {
+        let mut chain_state = ChainState::new();
+        let genesis = Header::from_genesis(0, &NetworkParams::MAINNET).mine_pow5();
+        let genesis_id = genesis.id();
+        let events = chain_state.add_header_at(genesis.clone(), 0).unwrap();
+        assert_eq!(events, vec![ChainEvent::Connect(genesis)]);
+
+        let header1 = next_header(&chain_state, &genesis_id, 600_000);
+        let events = chain_state.add_header_at(header1.clone(), 600_000).unwrap();
+        assert_eq!(events, vec![ChainEvent::Connect(header1.clone())]);
+        assert_eq!(chain_state.get_tip().unwrap().id(), header1.id());
+        assert_eq!(chain_state.lch().len(), 2);
+        assert_eq!(
+            chain_state.get_tip_work(),
+            ChainWork::from_headers(chain_state.lch().headers())
+        );
+
+        // adding the same header again is a no-op
+        let events = chain_state.add_header_at(header1, 600_000).unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_reorg_to_more_work() {
+        let mut chain_state = ChainState::new();
+        let genesis = Header::from_genesis(0, &NetworkParams::MAINNET).mine_pow5();
+        let genesis_id = genesis.id();
+        chain_state.add_header_at(genesis, 0).unwrap();
+
+        // branch a: one header at the intended interval
+        let a1 = next_header(&chain_state, &genesis_id, 600_000);
+        chain_state.add_header_at(a1.clone(), 600_000).unwrap();
+        let a2 = next_header(&chain_state, &a1.id(), 1_200_000);
+        chain_state.add_header_at(a2.clone(), 1_200_000).unwrap();
+        assert_eq!(chain_state.get_tip().unwrap().id(), a2.id());
+
+        // branch b: one header found twice as fast, so with twice the work
+        let b1 = next_header(&chain_state, &genesis_id, 300_000);
+        let events = chain_state.add_header_at(b1.clone(), 1_200_000).unwrap();
+        assert!(events.is_empty());
+        assert_eq!(chain_state.get_tip().unwrap().id(), a2.id());
+
+        let b2 = next_header(&chain_state, &b1.id(), 600_000);
+        let events = chain_state.add_header_at(b2.clone(), 1_200_000).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                ChainEvent::Disconnect(a2),
+                ChainEvent::Disconnect(a1),
+                ChainEvent::Connect(b1.clone()),
+                ChainEvent::Connect(b2.clone()),
+            ]
+        );
+        assert_eq!(chain_state.get_tip().unwrap().id(), b2.id());
+        assert_eq!(chain_state.lch().headers()[1].id(), b1.id());
+        assert_eq!(chain_state.entries().len(), 5);
+    }
+
+    #[test]
+    fn test_equal_work_keeps_first_seen() {
+        let mut chain_state = ChainState::new();
+        let genesis = Header::from_genesis(0, &NetworkParams::MAINNET).mine_pow5();
+        let genesis_id = genesis.id();
+        chain_state.add_header_at(genesis, 0).unwrap();
+
+        let a1 = next_header(&chain_state, &genesis_id, 600_000);
+        chain_state.add_header_at(a1.clone(), 600_000).unwrap();
+        let mut b1 = Header::from_lch(
+            &chain_state.get_branch(&genesis_id).unwrap(),
+            600_000,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
+        b1.merkle_root = [1; 32];
+        let b1 = b1.mine_pow5();
+        let events = chain_state.add_header_at(b1, 600_000).unwrap();
+        assert!(events.is_empty());
+        assert_eq!(chain_state.get_tip().unwrap().id(), a1.id());
+    }
+
+    #[test]
+    fn test_side_branch_longer_than_target_adj_period() {
+        let params = NetworkParams {
+            blocks_per_target_adj_period: 4,
+            ..NetworkParams::MAINNET
+        };
+        let mut chain_state = ChainState::from_params(params.clone());
+        let genesis = Header::from_genesis(0, &params).mine_pow5();
+        let genesis_id = genesis.id();
+        chain_state.add_header_at(genesis, 0).unwrap();
+        let mut prev_id = genesis_id;
+        for i in 1..=8 {
+            let branch = chain_state.get_branch(&prev_id).unwrap();
+            let header = Header::from_lch(&branch, i * 600_000, &params)
+                .unwrap()
+                .mine_pow5();
+            prev_id = header.id();
+            chain_state.add_header_at(header, i * 600_000).unwrap();
+        }
+        let a_tip_id = prev_id;
+
+        // headers found twice as fast have more work, and overtake the lch
+        // once the branch is past several target adjustment periods
+        let mut prev_id = genesis_id;
+        for i in 1..=12 {
+            let branch = chain_state.get_branch(&prev_id).unwrap();
+            let header = Header::from_lch(&branch, i * 300_000, &params)
+                .unwrap()
+                .mine_pow5();
+            assert_eq!(chain_state.get_branch_tail(&prev_id, 4).unwrap(), {
+                let start = branch.len().saturating_sub(4);
+                branch[start..].to_vec()
+            });
+            prev_id = header.id();
+            chain_state.add_header_at(header, 4_800_000).unwrap();
+        }
+        assert_eq!(chain_state.get_tip().unwrap().id(), prev_id);
+        assert_eq!(
+            chain_state.lch().headers(),
+            chain_state.get_branch(&prev_id).unwrap()
+        );
+
+        // the target of a side branch header is still checked
+        let branch = chain_state.get_branch(&a_tip_id).unwrap();
+        let mut bad_target = Header::from_lch(&branch, 5_400_000, &params).unwrap();
+        bad_target.target >>= 1;
+        let bad_target = bad_target.mine_pow5();
+        assert!(matches!(
+            chain_state.add_header_at(bad_target, 5_400_000),
+            Err(EbxError::InvalidHeaderError {
+                error: crate::error::HeaderError::InvalidTarget
+            })
+        ));
+    }
+
+    #[test]
+    fn test_block_locator() {
+        let mut chain_state = ChainState::from_params(NetworkParams::REGTEST);
+        for i in 0..30 {
+            let header = chain_state
+                .lch()
+                .get_next_header([0; 32], 1, i * 1_000)
+                .unwrap();
+            chain_state.add_header_at(header, i * 1_000).unwrap();
+        }
+        let ids: Vec<[u8; 32]> = chain_state.lch().headers().iter().map(|h| h.id()).collect();
+        let locator = chain_state.get_block_locator(&ids[29]);
+        let expected: Vec<[u8; 32]> = [29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 18, 14, 6, 0]
+            .iter()
+            .map(|&n| ids[n])
+            .collect();
+        assert_eq!(locator, expected);
+
+        // the first locator id we have is 20
+        let headers = chain_state.get_headers_after_locator(&[[1; 32], ids[20], ids[5]], None, 5);
+        assert_eq!(headers, chain_state.lch().headers()[21..26]);
+        let headers = chain_state.get_headers_after_locator(&[ids[20]], Some(&ids[22]), 5);
+        assert_eq!(headers, chain_state.lch().headers()[21..23]);
+        let headers = chain_state.get_headers_after_locator(&[], None, 3);
+        assert_eq!(headers, chain_state.lch().headers()[0..3]);
+    }
+
+    #[test]
+    fn test_reject_orphan_and_invalid() {
+        let mut chain_state = ChainState::new();
+        let genesis = Header::from_genesis(0, &NetworkParams::MAINNET).mine_pow5();
+        let genesis_id = genesis.id();
+        chain_state.add_header_at(genesis.clone(), 0).unwrap();
+
+        let mut orphan = next_header(&chain_state, &genesis_id, 600_000);
+        orphan.prev_block_id = [1; 32];
+        assert!(chain_state.add_header_at(orphan, 600_000).is_err());
+
+        let mut bad_target = next_header(&chain_state, &genesis_id, 600_000);
+        bad_target.target = crate::numbers::u256::ONE;
+        assert!(chain_state.add_header_at(bad_target, 600_000).is_err());
+
+        let mut second_genesis = genesis;
+        second_genesis.timestamp = 1;
+        assert!(chain_state.add_header_at(second_genesis, 1).is_err());
+    }
+}
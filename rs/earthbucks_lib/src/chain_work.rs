@@ -0,0 +1,150 @@
+use crate::buf_writer::BufWriter;
+use crate::error::EbxError;
+use crate::header::Header;
+use crate::numbers::u256;
+use num_bigint::BigUint;
+use num_traits::{One, ToPrimitive};
+use std::ops::{Add, AddAssign};
+
+// chain work is the expected number of hashes required to produce a header or
+// a chain of headers. the sum can exceed 256 bits in principle, so it is kept
+// as a BigUint rather than a u256.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ChainWork {
+    pub work: BigUint,
+}
+
+impl ChainWork {
+    pub fn new(work: BigUint) -> Self {
+        Self { work }
+    }
+
+    pub fn from_target(target: u256) -> Self {
+        // 2^256 / (target + 1)
+        let target = BigUint::from_bytes_be(&BufWriter::new().write_u256_be(target).to_buf());
+        Self::new((BigUint::one() << 256) / (target + BigUint::one()))
+    }
+
+    pub fn from_header(header: &Header) -> Self {
+        Self::from_target(header.target)
+    }
+
+    pub fn from_headers(headers: &[Header]) -> Self {
+        let mut chain_work = Self::default();
+        for header in headers {
+            chain_work.add_header(header);
+        }
+        chain_work
+    }
+
+    pub fn add_header(&mut self, header: &Header) -> &mut Self {
+        self.work += Self::from_header(header).work;
+        self
+    }
+
+    pub fn is_more_work_than(&self, other: &ChainWork) -> bool {
+        self.work > other.work
+    }
+
+    // fraction of total work that this amount of work represents, e.g. for
+    // reporting sync progress against a peer's announced chain work
+    pub fn progress(&self, total: &ChainWork) -> f64 {
+        if total.work == BigUint::ZERO {
+            return 1.0;
+        }
+        let progress = self.work.to_f64().unwrap() / total.work.to_f64().unwrap();
+        progress.min(1.0)
+    }
+
+    pub fn to_u256(&self) -> Result<u256, EbxError> {
+        if self.work.bits() > 256 {
+            return Err(EbxError::InsufficientPrecisionError { source: None });
+        }
+        Ok(u256::from_be_slice(&self.work.to_bytes_be()).unwrap())
+    }
+
+    pub fn to_strict_hex(&self) -> String {
+        self.work.to_str_radix(16)
+    }
+}
+
+impl Add for ChainWork {
+    type Output = ChainWork;
+
+    fn add(self, other: ChainWork) -> ChainWork {
+        ChainWork::new(self.work + other.work)
+    }
+}
+
+impl AddAssign<&ChainWork> for ChainWork {
+    fn add_assign(&mut self, other: &ChainWork) {
+        self.work += &other.work;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buf::EbxBuf;
+    use crate::buf_reader::BufReader;
+    use crate::network_params::NetworkParams;
+
+    fn target_from_hex(hex: &str) -> u256 {
+        BufReader::new(Vec::<u8>::from_strict_hex(hex).unwrap())
+            .read_u256_be()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_from_target() {
+        let max_target =
+            target_from_hex("ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+        assert_eq!(ChainWork::from_target(max_target).work, BigUint::one());
+
+        let half_target =
+            target_from_hex("7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+        assert_eq!(ChainWork::from_target(half_target).work, BigUint::from(2u8));
+
+        let target =
+            target_from_hex("00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+        assert_eq!(ChainWork::from_target(target).work, BigUint::from(256u16));
+
+        let zero_target = u256::from(0u8);
+        assert_eq!(
+            ChainWork::from_target(zero_target).work,
+            BigUint::one() << 256
+        );
+        assert!(ChainWork::from_target(zero_target).to_u256().is_err());
+    }
+
+    #[test]
+    fn test_from_headers() {
+        let mut header1 = Header::from_genesis(0, &NetworkParams::MAINNET);
+        let mut header2 = Header::from_genesis(0, &NetworkParams::MAINNET);
+        header1.target =
+            target_from_hex("7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+        header2.target =
+            target_from_hex("0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+        let chain_work = ChainWork::from_headers(&[header1.clone(), header2.clone()]);
+        assert_eq!(chain_work.work, BigUint::from(18u8));
+        assert_eq!(chain_work.to_u256().unwrap(), u256::from(18u8));
+        assert_eq!(chain_work.to_strict_hex(), "12");
+        assert_eq!(
+            chain_work,
+            ChainWork::from_header(&header1) + ChainWork::from_header(&header2)
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let less = ChainWork::new(BigUint::from(2u8));
+        let more = ChainWork::new(BigUint::from(3u8));
+        assert!(more.is_more_work_than(&less));
+        assert!(!less.is_more_work_than(&more));
+        assert!(!less.is_more_work_than(&less.clone()));
+        assert!(less < more);
+        assert_eq!(ChainWork::default().progress(&more), 0.0);
+        assert_eq!(more.progress(&more), 1.0);
+        assert_eq!(ChainWork::new(BigUint::from(1u8)).progress(&less), 0.5);
+    }
+}
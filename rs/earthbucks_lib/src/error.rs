@@ -1,4 +1,6 @@
+use std::error::Error;
 use std::fmt;
+use std::io;
 
 #[derive(Debug)]
 pub enum EbxError {
@@ -36,6 +38,20 @@ pub enum EbxError {
     InvalidChecksumError {
         source: Option<Box<EbxError>>,
     },
+    // a field of a larger structure failed to parse. offset is the position
+    // in the buffer where the field starts, and source is why it failed.
+    ParseError {
+        source: Option<Box<EbxError>>,
+        field: String,
+        offset: usize,
+    },
+    // a header breaks a consensus rule, which is the source
+    InvalidHeaderError {
+        error: HeaderError,
+    },
+    IoError {
+        source: io::Error,
+    },
 }
 
 impl fmt::Display for EbxError {
@@ -74,6 +90,330 @@ impl fmt::Display for EbxError {
             EbxError::InvalidChecksumError { .. } => {
                 write!(f, "invalid checksum")
             }
+            EbxError::ParseError { field, offset, .. } => {
+                write!(f, "failed to parse {} at byte {}", field, offset)
+            }
+            EbxError::InvalidHeaderError { error } => {
+                write!(f, "invalid header: {}", error)
+            }
+            EbxError::IoError { source } => {
+                write!(f, "io error: {}", source)
+            }
         }
     }
 }
+
+impl Error for EbxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        let source = match self {
+            EbxError::GenericError { source, .. }
+            | EbxError::InvalidSizeError { source }
+            | EbxError::NotEnoughDataError { source }
+            | EbxError::TooMuchDataError { source }
+            | EbxError::NonMinimalEncodingError { source }
+            | EbxError::InsufficientPrecisionError { source }
+            | EbxError::InvalidOpcodeError { source }
+            | EbxError::InvalidHexError { source }
+            | EbxError::InvalidEncodingError { source }
+            | EbxError::InvalidKeyError { source }
+            | EbxError::InvalidChecksumError { source }
+            | EbxError::ParseError { source, .. } => source,
+            EbxError::InvalidHeaderError { error } => return Some(error),
+            EbxError::IoError { source } => return Some(source),
+        };
+        source.as_deref().map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+impl From<io::Error> for EbxError {
+    fn from(source: io::Error) -> Self {
+        EbxError::IoError { source }
+    }
+}
+
+impl From<HeaderError> for EbxError {
+    fn from(error: HeaderError) -> Self {
+        EbxError::InvalidHeaderError { error }
+    }
+}
+
+// the consensus rule a header breaks
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderError {
+    InvalidVersion,
+    InvalidGenesis,
+    InvalidBlockNum,
+    InvalidPrevBlockId,
+    TimestampTooEarly,
+    TimestampInFuture,
+    InvalidTarget,
+    InvalidWork,
+    InvalidId,
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeaderError::InvalidVersion => write!(f, "invalid version"),
+            HeaderError::InvalidGenesis => write!(f, "invalid genesis header"),
+            HeaderError::InvalidBlockNum => write!(f, "invalid block number"),
+            HeaderError::InvalidPrevBlockId => write!(f, "invalid previous block id"),
+            HeaderError::TimestampTooEarly => {
+                write!(f, "timestamp not after median time past")
+            }
+            HeaderError::TimestampInFuture => write!(f, "timestamp too far in the future"),
+            HeaderError::InvalidTarget => write!(f, "invalid target"),
+            HeaderError::InvalidWork => write!(f, "invalid work"),
+            HeaderError::InvalidId => write!(f, "id does not meet target"),
+        }
+    }
+}
+
+// the consensus rule a tx breaks, with the input it was found on
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxError {
+    LockAbsNotReached,
+    Coinbase,
+    MissingInput {
+        n_in: usize,
+    },
+    DuplicateInput {
+        n_in: usize,
+    },
+    NonPushOnlyInput {
+        n_in: usize,
+    },
+    NonMinimalInput {
+        n_in: usize,
+    },
+    ScriptFailed {
+        n_in: usize,
+        message: String,
+    },
+    ScriptLimitExceeded {
+        n_in: usize,
+        error: ScriptLimitError,
+    },
+    LockRelNotReached {
+        n_in: usize,
+    },
+    ValueMismatch {
+        input_value: u64,
+        output_value: u64,
+    },
+    // not a consensus rule: the utxo store failed to read the output
+    UtxoStore {
+        n_in: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxError::LockAbsNotReached => write!(f, "lock_abs not reached"),
+            TxError::Coinbase => write!(f, "unexpected coinbase tx"),
+            TxError::MissingInput { n_in } => write!(f, "input {}: output not found", n_in),
+            TxError::DuplicateInput { n_in } => {
+                write!(f, "input {}: output spent twice", n_in)
+            }
+            TxError::NonPushOnlyInput { n_in } => {
+                write!(f, "input {}: script is not push only", n_in)
+            }
+            TxError::NonMinimalInput { n_in } => {
+                write!(f, "input {}: script has a non-minimal push", n_in)
+            }
+            TxError::ScriptFailed { n_in, message } => {
+                write!(f, "input {}: script failed: {}", n_in, message)
+            }
+            TxError::ScriptLimitExceeded { n_in, error } => {
+                write!(f, "input {}: {}", n_in, error)
+            }
+            TxError::LockRelNotReached { n_in } => {
+                write!(f, "input {}: lock_rel not reached", n_in)
+            }
+            TxError::ValueMismatch {
+                input_value,
+                output_value,
+            } => write!(
+                f,
+                "input value {} does not equal output value {}",
+                input_value, output_value
+            ),
+            TxError::UtxoStore { n_in, message } => {
+                write!(f, "input {}: utxo store error: {}", n_in, message)
+            }
+        }
+    }
+}
+
+// the consensus limit on script resources that a script exceeds, see
+// ScriptLimits
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptLimitError {
+    ScriptSize,
+    OpCount,
+    StackSize,
+    ElementSize,
+}
+
+impl fmt::Display for ScriptLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptLimitError::ScriptSize => write!(f, "script too large"),
+            ScriptLimitError::OpCount => write!(f, "too many ops"),
+            ScriptLimitError::StackSize => write!(f, "stack too large"),
+            ScriptLimitError::ElementSize => write!(f, "stack element too large"),
+        }
+    }
+}
+
+// the consensus rule a block breaks. n_tx is the index of the tx in the block,
+// and the header or tx error is the source.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockError {
+    Header(HeaderError),
+    InvalidNTransactions,
+    DuplicateTx { n_tx: usize },
+    InvalidMerkleRoot,
+    MissingCoinbase,
+    InvalidCoinbaseLockAbs,
+    InvalidCoinbaseVersion,
+    InvalidCoinbaseOutput { n_out: usize },
+    InvalidCoinbaseAmount,
+    InvalidCoinbaseScript,
+    InvalidCoinbaseDomain,
+    InvalidTx { n_tx: usize, error: TxError },
+    UtxoStore { n_tx: usize, message: String },
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockError::Header(_) => write!(f, "invalid header"),
+            BlockError::InvalidNTransactions => write!(f, "invalid number of transactions"),
+            BlockError::DuplicateTx { n_tx } => write!(f, "tx {}: duplicate tx id", n_tx),
+            BlockError::InvalidMerkleRoot => write!(f, "invalid merkle root"),
+            BlockError::MissingCoinbase => write!(f, "missing coinbase tx"),
+            BlockError::InvalidCoinbaseLockAbs => write!(f, "invalid coinbase lock_abs"),
+            BlockError::InvalidCoinbaseVersion => write!(f, "invalid coinbase version"),
+            BlockError::InvalidCoinbaseOutput { n_out } => {
+                write!(f, "coinbase output {}: not a pkh output", n_out)
+            }
+            BlockError::InvalidCoinbaseAmount => write!(f, "invalid coinbase amount"),
+            BlockError::InvalidCoinbaseScript => write!(f, "invalid coinbase script"),
+            BlockError::InvalidCoinbaseDomain => write!(f, "invalid coinbase domain"),
+            BlockError::InvalidTx { n_tx, .. } => write!(f, "invalid tx {}", n_tx),
+            BlockError::UtxoStore { n_tx, message } => {
+                write!(f, "tx {}: utxo store error: {}", n_tx, message)
+            }
+        }
+    }
+}
+
+// why a merkle proof does not show that a tx is in a block of the lch
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpvError {
+    BlockNotFound,
+    InvalidProofLength,
+    InvalidPosition,
+    InvalidProof,
+    MerkleRootMismatch,
+}
+
+impl fmt::Display for SpvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpvError::BlockNotFound => write!(f, "block not found in longest chain"),
+            SpvError::InvalidProofLength => {
+                write!(f, "proof length does not match number of transactions")
+            }
+            SpvError::InvalidPosition => write!(f, "proof position past last transaction"),
+            SpvError::InvalidProof => write!(f, "proof does not hash to its root"),
+            SpvError::MerkleRootMismatch => write!(f, "proof root is not the block's merkle root"),
+        }
+    }
+}
+
+// why a tx is not added to the mempool. n_in is the input that spends an
+// output another tx in the pool already spends.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MempoolError {
+    AlreadyInMempool,
+    DoubleSpend { n_in: usize },
+    InvalidTx(TxError),
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MempoolError::AlreadyInMempool => write!(f, "tx already in mempool"),
+            MempoolError::DoubleSpend { n_in } => {
+                write!(f, "input {}: output already spent in mempool", n_in)
+            }
+            MempoolError::InvalidTx(_) => write!(f, "invalid tx"),
+        }
+    }
+}
+
+impl Error for HeaderError {}
+
+impl Error for ScriptLimitError {}
+
+impl Error for TxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TxError::ScriptLimitExceeded { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Error for SpvError {}
+
+impl Error for MempoolError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MempoolError::InvalidTx(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Error for BlockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BlockError::Header(error) => Some(error),
+            BlockError::InvalidTx { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl BlockError {
+    // the txs do not match the header, which could be a mutation of a valid
+    // block by whoever relayed it. the block id must not be remembered as
+    // invalid for these, because the same header with the right txs may be
+    // valid.
+    pub fn is_mutation(&self) -> bool {
+        matches!(
+            self,
+            BlockError::InvalidNTransactions
+                | BlockError::DuplicateTx { .. }
+                | BlockError::InvalidMerkleRoot
+        )
+    }
+
+    // the block could not be verified because the utxo store failed. this
+    // says nothing about whether the block is valid, so it must not be
+    // remembered as invalid either.
+    pub fn is_utxo_store(&self) -> bool {
+        matches!(self, BlockError::UtxoStore { .. })
+    }
+}
+
+impl From<HeaderError> for BlockError {
+    fn from(error: HeaderError) -> Self {
+        BlockError::Header(error)
+    }
+}
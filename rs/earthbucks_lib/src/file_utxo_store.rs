@@ -0,0 +1,409 @@
+use crate::buf_reader::BufReader;
+use crate::buf_writer::BufWriter;
+use crate::error::EbxError;
+use crate::out_point::OutPoint;
+use crate::tx_out::TxOut;
+use crate::tx_out_bn::TxOutBn;
+use crate::utxo_store::UtxoStore;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+// an append-only log of add and remove records. only the index from out point
+// to the position of the tx output in the file is kept in memory. the log is
+// replayed on open, and a partially written record at the end of the file
+// (e.g. after a crash) is truncated away.
+//
+// record: kind (u8) | out point (36 bytes) | for adds: payload len (u32) and
+// payload, where payload = block num (u32) | tx out
+pub struct FileUtxoStore {
+    path: PathBuf,
+    file: Mutex<File>, // readers share the file, and each seeks before reading
+    index: HashMap<OutPoint, (u64, u32)>, // payload offset and length
+    file_len: u64,
+}
+
+impl FileUtxoStore {
+    const RECORD_ADD: u8 = 1;
+    const RECORD_REMOVE: u8 = 0;
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, EbxError> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let mut store = Self {
+            path,
+            file: Mutex::new(file),
+            index: HashMap::new(),
+            file_len: 0,
+        };
+        store.replay()?;
+        Ok(store)
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn sync(&self) -> Result<(), EbxError> {
+        self.lock_file().sync_all()?;
+        Ok(())
+    }
+
+    // rewrite the log so that it only contains the live outputs
+    pub fn compact(&mut self) -> Result<(), EbxError> {
+        let tmp_path = self.path.with_extension("compact");
+        let mut tmp_store = FileUtxoStore::open(&tmp_path)?;
+        tmp_store.file_mut().set_len(0)?;
+        tmp_store.index.clear();
+        tmp_store.file_len = 0;
+        for (out_point, tx_out_bn) in self.get_all_tx_out_bns()? {
+            tmp_store.add_tx_out_bn(out_point, tx_out_bn)?;
+        }
+        tmp_store.sync()?;
+        fs::rename(&tmp_path, &self.path)?;
+        tmp_store.path = self.path.clone();
+        *self = tmp_store;
+        Ok(())
+    }
+
+    // a poisoned lock is fine, because every read seeks first
+    fn lock_file(&self) -> MutexGuard<'_, File> {
+        self.file.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn file_mut(&mut self) -> &mut File {
+        self.file.get_mut().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn replay(&mut self) -> Result<(), EbxError> {
+        let file = self.file.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let file_len = file.metadata()?.len();
+        let mut reader = io::BufReader::new(&*file);
+        reader.seek(SeekFrom::Start(0))?;
+        let mut offset: u64 = 0;
+        loop {
+            let mut head = [0u8; 1 + OutPoint::SIZE];
+            match read_record_part(&mut reader, &mut head)? {
+                ReadResult::Eof => break,
+                ReadResult::Partial => {
+                    file.set_len(offset)?;
+                    break;
+                }
+                ReadResult::Complete => (),
+            }
+            let out_point = OutPoint::from_buf(head[1..].try_into().unwrap())?;
+            match head[0] {
+                FileUtxoStore::RECORD_REMOVE => {
+                    self.index.remove(&out_point);
+                    offset += head.len() as u64;
+                }
+                FileUtxoStore::RECORD_ADD => {
+                    let mut len_buf = [0u8; 4];
+                    if read_record_part(&mut reader, &mut len_buf)? != ReadResult::Complete {
+                        file.set_len(offset)?;
+                        break;
+                    }
+                    let payload_len = u32::from_be_bytes(len_buf);
+                    // a torn length can be anything, so check it before
+                    // allocating the payload
+                    let payload_offset = offset + head.len() as u64 + 4;
+                    if payload_offset + payload_len as u64 > file_len {
+                        file.set_len(offset)?;
+                        break;
+                    }
+                    let mut payload = vec![0u8; payload_len as usize];
+                    if read_record_part(&mut reader, &mut payload)? != ReadResult::Complete {
+                        file.set_len(offset)?;
+                        break;
+                    }
+                    self.index.insert(out_point, (payload_offset, payload_len));
+                    offset = payload_offset + payload_len as u64;
+                }
+                _ => {
+                    return Err(EbxError::GenericError {
+                        source: None,
+                        message: "invalid utxo store record".to_string(),
+                    })
+                }
+            }
+        }
+        self.file_len = offset;
+        Ok(())
+    }
+
+    fn append(&mut self, record: &[u8]) -> Result<u64, EbxError> {
+        let offset = self.file_len;
+        let file = self.file_mut();
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(record)?;
+        self.file_len += record.len() as u64;
+        Ok(offset)
+    }
+}
+
+impl UtxoStore for FileUtxoStore {
+    fn get_tx_out_bn(&self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+        let (offset, len) = match self.index.get(out_point) {
+            Some(entry) => *entry,
+            None => return Ok(None),
+        };
+        let mut payload = vec![0u8; len as usize];
+        {
+            let mut file = self.lock_file();
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut payload)?;
+        }
+        let mut br = BufReader::new(payload);
+        let block_num = br.read_u32_be()?;
+        let tx_out = TxOut::from_buf_reader(&mut br)?;
+        Ok(Some(TxOutBn { tx_out, block_num }))
+    }
+
+    fn add_tx_out_bn(&mut self, out_point: OutPoint, tx_out_bn: TxOutBn) -> Result<(), EbxError> {
+        let mut payload = BufWriter::new();
+        payload.write_u32_be(tx_out_bn.block_num);
+        payload.write(tx_out_bn.tx_out.to_buf());
+        let payload = payload.to_buf();
+
+        let mut record = BufWriter::new();
+        record.write_u8(FileUtxoStore::RECORD_ADD);
+        record.write(out_point.to_buf().to_vec());
+        record.write_u32_be(payload.len() as u32);
+        let head_len = record.to_buf().len() as u64;
+        record.write(payload.clone());
+
+        let offset = self.append(&record.to_buf())?;
+        self.index
+            .insert(out_point, (offset + head_len, payload.len() as u32));
+        Ok(())
+    }
+
+    fn remove_tx_out_bn(&mut self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+        let tx_out_bn = match self.get_tx_out_bn(out_point)? {
+            Some(tx_out_bn) => tx_out_bn,
+            None => return Ok(None),
+        };
+        let mut record = BufWriter::new();
+        record.write_u8(FileUtxoStore::RECORD_REMOVE);
+        record.write(out_point.to_buf().to_vec());
+        self.append(&record.to_buf())?;
+        self.index.remove(out_point);
+        Ok(Some(tx_out_bn))
+    }
+
+    fn get_all_tx_out_bns(&self) -> Result<Vec<(OutPoint, TxOutBn)>, EbxError> {
+        let mut tx_out_bns = Vec::new();
+        for out_point in self.index.keys() {
+            let tx_out_bn = self.get_tx_out_bn(out_point)?.unwrap();
+            tx_out_bns.push((*out_point, tx_out_bn));
+        }
+        Ok(tx_out_bns)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ReadResult {
+    Complete,
+    Partial,
+    Eof,
+}
+
+fn read_record_part(reader: &mut impl Read, buf: &mut [u8]) -> Result<ReadResult, EbxError> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if read == buf.len() {
+        Ok(ReadResult::Complete)
+    } else if read == 0 {
+        Ok(ReadResult::Eof)
+    } else {
+        Ok(ReadResult::Partial)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buf::EbxBuf;
+    use crate::script::Script;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "earthbucks-utxo-{}.dat",
+            <[u8; 8]>::from_random().to_strict_hex()
+        ))
+    }
+
+    fn tx_out_bn(value: u64, block_num: u32) -> TxOutBn {
+        TxOutBn {
+            tx_out: TxOut::new(value, Script::from_pkh_output(&[7; 32])),
+            block_num,
+        }
+    }
+
+    #[test]
+    fn test_add_get_remove() {
+        let path = temp_path();
+        let mut store = FileUtxoStore::open(&path).unwrap();
+        let out_point1 = OutPoint::new([1; 32], 0);
+        let out_point2 = OutPoint::new([2; 32], 1);
+        store.add_tx_out_bn(out_point1, tx_out_bn(100, 1)).unwrap();
+        store.add_tx_out_bn(out_point2, tx_out_bn(200, 2)).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(
+            store.get_tx_out_bn(&out_point2).unwrap(),
+            Some(tx_out_bn(200, 2))
+        );
+        assert_eq!(
+            store.remove_tx_out_bn(&out_point1).unwrap(),
+            Some(tx_out_bn(100, 1))
+        );
+        assert_eq!(store.remove_tx_out_bn(&out_point1).unwrap(), None);
+        assert_eq!(store.get_tx_out_bn(&out_point1).unwrap(), None);
+        assert_eq!(store.len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_reopen_and_recover_partial_record() {
+        let path = temp_path();
+        let out_point1 = OutPoint::new([1; 32], 0);
+        let out_point2 = OutPoint::new([2; 32], 1);
+        {
+            let mut store = FileUtxoStore::open(&path).unwrap();
+            store.add_tx_out_bn(out_point1, tx_out_bn(100, 1)).unwrap();
+            store.add_tx_out_bn(out_point2, tx_out_bn(200, 2)).unwrap();
+            store.remove_tx_out_bn(&out_point2).unwrap();
+            store.sync().unwrap();
+        }
+        // simulate a crash in the middle of writing a record
+        let len_before = fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[FileUtxoStore::RECORD_ADD, 3, 3, 3])
+            .unwrap();
+        drop(file);
+
+        let mut store = FileUtxoStore::open(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), len_before);
+        assert_eq!(store.len(), 1);
+        assert_eq!(
+            store.get_tx_out_bn(&out_point1).unwrap(),
+            Some(tx_out_bn(100, 1))
+        );
+        assert_eq!(store.get_tx_out_bn(&out_point2).unwrap(), None);
+
+        store.add_tx_out_bn(out_point2, tx_out_bn(300, 3)).unwrap();
+        assert_eq!(
+            store.get_tx_out_bn(&out_point2).unwrap(),
+            Some(tx_out_bn(300, 3))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_recover_record_with_torn_len() {
+        let path = temp_path();
+        let out_point1 = OutPoint::new([1; 32], 0);
+        {
+            let mut store = FileUtxoStore::open(&path).unwrap();
+            store.add_tx_out_bn(out_point1, tx_out_bn(100, 1)).unwrap();
+            store.sync().unwrap();
+        }
+        // a length far past the end of the file
+        let len_before = fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[FileUtxoStore::RECORD_ADD]).unwrap();
+        file.write_all(&[2; OutPoint::SIZE]).unwrap();
+        file.write_all(&u32::MAX.to_be_bytes()).unwrap();
+        file.write_all(&[0; 8]).unwrap();
+        drop(file);
+
+        let store = FileUtxoStore::open(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), len_before);
+        assert_eq!(store.len(), 1);
+        assert_eq!(
+            store.get_tx_out_bn(&out_point1).unwrap(),
+            Some(tx_out_bn(100, 1))
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_reads() {
+        let path = temp_path();
+        let mut store = FileUtxoStore::open(&path).unwrap();
+        for i in 0..16 {
+            store
+                .add_tx_out_bn(OutPoint::new([i; 32], 0), tx_out_bn(i as u64, i as u32))
+                .unwrap();
+        }
+        let store = &store;
+        std::thread::scope(|scope| {
+            for i in 0..16 {
+                scope.spawn(move || {
+                    for _ in 0..100 {
+                        assert_eq!(
+                            store.get_tx_out_bn(&OutPoint::new([i; 32], 0)).unwrap(),
+                            Some(tx_out_bn(i as u64, i as u32))
+                        );
+                    }
+                });
+            }
+        });
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_io_error_source() {
+        // a directory can't be opened as the log
+        let err = match FileUtxoStore::open(std::env::temp_dir()) {
+            Ok(_) => panic!("opened a directory"),
+            Err(err) => err,
+        };
+        assert!(matches!(err, EbxError::IoError { .. }));
+        let source = std::error::Error::source(&err).unwrap();
+        assert!(source.downcast_ref::<io::Error>().is_some());
+    }
+
+    #[test]
+    fn test_compact() {
+        let path = temp_path();
+        let mut store = FileUtxoStore::open(&path).unwrap();
+        for i in 0..10 {
+            store
+                .add_tx_out_bn(OutPoint::new([i; 32], 0), tx_out_bn(i as u64, 0))
+                .unwrap();
+        }
+        for i in 0..9 {
+            store.remove_tx_out_bn(&OutPoint::new([i; 32], 0)).unwrap();
+        }
+        let len_before = fs::metadata(&path).unwrap().len();
+        store.compact().unwrap();
+        assert!(fs::metadata(&path).unwrap().len() < len_before);
+        assert_eq!(
+            store.get_all_tx_out_bns().unwrap(),
+            vec![(OutPoint::new([9; 32], 0), tx_out_bn(9, 0))]
+        );
+
+        let store = FileUtxoStore::open(&path).unwrap();
+        assert_eq!(store.len(), 1);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,17 +1,20 @@
 use crate::buf::EbxBuf;
 use crate::buf_reader::BufReader;
 use crate::buf_writer::BufWriter;
-use crate::error::EbxError;
+use crate::chain_work::ChainWork;
+use crate::error::{EbxError, HeaderError};
 use crate::hash::{blake3_hash, double_blake3_hash};
+use crate::network_params::NetworkParams;
 use crate::numbers::u256;
 use num_bigint::BigUint;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Header {
     pub version: u8,
     pub prev_block_id: [u8; 32],
     pub merkle_root: [u8; 32],
+    pub n_transactions: u64,
     pub timestamp: u64, // milliseconds
     pub block_num: u32,
     pub target: u256,
@@ -23,14 +26,12 @@ pub struct Header {
 }
 
 impl Header {
-    // exactly two weeks if block interval is 10 minutes
-    pub const BLOCKS_PER_TARGET_ADJ_PERIOD: u32 = 2016;
+    pub const SIZE: usize = 1 + 32 + 32 + 8 + 8 + 4 + 32 + 32 + 2 + 32 + 2 + 32;
 
-    // 600_000 milliseconds = 600 seconds = 10 minutes
-    pub const BLOCK_INTERVAL: u64 = 600_000;
-
-    pub const SIZE: usize = 1 + 32 + 32 + 8 + 4 + 32 + 32 + 2 + 32 + 2 + 32;
-    pub const MAX_TARGET_BYTES: [u8; 32] = [0xff; 32];
+    // work_par_algo ids
+    pub const WORK_PAR_ALGO_NULL: u16 = 0;
+    pub const WORK_PAR_ALGO_ALGO1627: u16 = 1;
+    pub const WORK_PAR_ALGO_POW5: u16 = 2;
 
     pub fn to_buf(&self) -> [u8; Header::SIZE] {
         self.to_buf_writer().to_buf().try_into().unwrap()
@@ -42,24 +43,35 @@ impl Header {
     }
 
     pub fn from_buf_reader(br: &mut BufReader) -> Result<Header, EbxError> {
-        if br.remainder_len() < Header::SIZE {
-            panic!("Invalid block header size");
-        }
-        let version = br.read_u8()?;
-        let prev_block_id: [u8; 32] = br.read(32)?.try_into().unwrap();
-        let merkle_root: [u8; 32] = br.read(32)?.try_into().unwrap();
-        let timestamp = br.read_u64_be()?;
-        let block_num = br.read_u32_be()?;
-        let target: u256 = br.read_u256_be()?;
-        let nonce: u256 = br.read_u256_be()?;
-        let work_ser_algo = br.read_u16_be()?;
-        let work_ser_hash: [u8; 32] = br.read(32)?.try_into().unwrap();
-        let work_par_algo = br.read_u16_be()?;
-        let work_par_hash: [u8; 32] = br.read(32)?.try_into().unwrap();
+        let version = br.read_field("version", BufReader::read_u8)?;
+        let prev_block_id: [u8; 32] = br
+            .read_field("prev_block_id", |br| br.read(32))?
+            .try_into()
+            .unwrap();
+        let merkle_root: [u8; 32] = br
+            .read_field("merkle_root", |br| br.read(32))?
+            .try_into()
+            .unwrap();
+        let n_transactions = br.read_field("n_transactions", BufReader::read_u64_be)?;
+        let timestamp = br.read_field("timestamp", BufReader::read_u64_be)?;
+        let block_num = br.read_field("block_num", BufReader::read_u32_be)?;
+        let target: u256 = br.read_field("target", BufReader::read_u256_be)?;
+        let nonce: u256 = br.read_field("nonce", BufReader::read_u256_be)?;
+        let work_ser_algo = br.read_field("work_ser_algo", BufReader::read_u16_be)?;
+        let work_ser_hash: [u8; 32] = br
+            .read_field("work_ser_hash", |br| br.read(32))?
+            .try_into()
+            .unwrap();
+        let work_par_algo = br.read_field("work_par_algo", BufReader::read_u16_be)?;
+        let work_par_hash: [u8; 32] = br
+            .read_field("work_par_hash", |br| br.read(32))?
+            .try_into()
+            .unwrap();
         Ok(Self {
             version,
             prev_block_id,
             merkle_root,
+            n_transactions,
             timestamp,
             block_num,
             target,
@@ -76,6 +88,7 @@ impl Header {
         bw.write_u8(self.version);
         bw.write(self.prev_block_id.to_vec());
         bw.write(self.merkle_root.to_vec());
+        bw.write_u64_be(self.n_transactions);
         bw.write_u64_be(self.timestamp);
         bw.write_u32_be(self.block_num);
         bw.write_u256_be(self.target);
@@ -107,8 +120,8 @@ impl Header {
         Header::from_strict_hex(hex)
     }
 
-    pub fn is_target_valid(&self, lch: &[Header]) -> bool {
-        let new_target_res = Header::new_target_from_lch(lch, self.timestamp);
+    pub fn is_target_valid(&self, lch: &[Header], params: &NetworkParams) -> bool {
+        let new_target_res = Header::new_target_from_lch(lch, self.timestamp, params);
         if new_target_res.is_err() {
             return false;
         }
@@ -122,63 +135,149 @@ impl Header {
         id_num < self.target
     }
 
+    // pow5 miners only search the first 4 bytes of the nonce
+    pub fn set_first_4_bytes_of_nonce(&mut self, nonce: u32) {
+        let low_bytes_mask = (u256::ONE << 224) - u256::ONE;
+        self.nonce = (self.nonce & low_bytes_mask) | (u256::from(nonce) << 224);
+    }
+
+    // the parallel work is computed over the header with work_par_hash zeroed,
+    // and is then put into work_par_hash, which makes it part of the id
+    pub fn get_work_par(&self) -> Result<[u8; 32], EbxError> {
+        let mut header = self.clone();
+        header.work_par_hash = [0; 32];
+        match self.work_par_algo {
+            Header::WORK_PAR_ALGO_POW5 => {
+                let work_par =
+                    earthbucks_pow5::get_work_par(header.to_buf().to_vec()).map_err(|message| {
+                        EbxError::GenericError {
+                            source: None,
+                            message,
+                        }
+                    })?;
+                Ok(work_par.try_into().unwrap())
+            }
+            _ => Err(EbxError::GenericError {
+                source: None,
+                message: "unsupported work_par_algo".to_string(),
+            }),
+        }
+    }
+
+    pub fn is_work_valid(&self, params: &NetworkParams) -> bool {
+        match self.work_par_algo {
+            Header::WORK_PAR_ALGO_NULL => {
+                params.allow_null_work_par && self.work_par_hash == [0; 32]
+            }
+            Header::WORK_PAR_ALGO_POW5 => match self.get_work_par() {
+                Ok(work_par) => work_par == self.work_par_hash,
+                Err(_) => false,
+            },
+            _ => false,
+        }
+    }
+
     pub fn is_version_valid(&self) -> bool {
         self.version == 0
     }
 
-    pub fn is_timestamp_valid_at(&self, timestamp: u64) -> bool {
-        self.timestamp <= timestamp
+    pub fn is_timestamp_valid_at(&self, timestamp: u64, params: &NetworkParams) -> bool {
+        self.timestamp <= timestamp.saturating_add(params.max_future_drift)
+    }
+
+    // the median timestamp of the last params.median_time_past_len headers.
+    // unlike the timestamp of the tip, this can't be moved much by one miner.
+    pub fn median_time_past(lch: &[Header], params: &NetworkParams) -> u64 {
+        let len = lch.len().min(params.median_time_past_len as usize);
+        let mut timestamps: Vec<u64> = lch[lch.len() - len..]
+            .iter()
+            .map(|header| header.timestamp)
+            .collect();
+        if timestamps.is_empty() {
+            return 0;
+        }
+        timestamps.sort_unstable();
+        timestamps[timestamps.len() / 2]
+    }
+
+    pub fn verify_in_lch(&self, lch: &[Header], params: &NetworkParams) -> Result<(), HeaderError> {
+        let target = Header::new_target_from_lch(lch, self.timestamp, params).ok();
+        self.verify_in_lch_with_target(lch, target, params)
     }
 
-    pub fn is_valid_in_lch(&self, lch: &[Header]) -> bool {
+    // same as verify_in_lch, but with the target this header must have already
+    // computed, e.g. by HeaderChain. none if no target is valid at this
+    // header's timestamp.
+    pub fn verify_in_lch_with_target(
+        &self,
+        lch: &[Header],
+        target: Option<u256>,
+        params: &NetworkParams,
+    ) -> Result<(), HeaderError> {
         if !self.is_version_valid() {
-            return false;
+            return Err(HeaderError::InvalidVersion);
         }
         if self.block_num == 0 {
-            return self.is_genesis();
+            if !self.is_genesis() {
+                return Err(HeaderError::InvalidGenesis);
+            }
+            return Ok(());
         }
-        if lch.is_empty() {
-            return false;
+        // lch may be just the last headers of the chain, as long as there are
+        // enough for the target and median time past
+        let prev_header = match lch.last() {
+            Some(prev_header) => prev_header,
+            None => return Err(HeaderError::InvalidBlockNum),
+        };
+        if prev_header.block_num.checked_add(1) != Some(self.block_num) {
+            return Err(HeaderError::InvalidBlockNum);
         }
-        if self.block_num != lch.len() as u32 {
-            return false;
+        if self.prev_block_id != prev_header.id() {
+            return Err(HeaderError::InvalidPrevBlockId);
         }
-        if self.prev_block_id != lch.last().unwrap().id() {
-            return false;
+        if self.timestamp <= Header::median_time_past(lch, params) {
+            return Err(HeaderError::TimestampTooEarly);
         }
-        if self.timestamp <= lch.last().unwrap().timestamp {
-            return false;
+        if target != Some(self.target) {
+            return Err(HeaderError::InvalidTarget);
         }
-        if !self.is_target_valid(lch) {
-            return false;
+        if !self.is_work_valid(params) {
+            return Err(HeaderError::InvalidWork);
         }
         if !self.is_id_valid() {
-            return false;
+            return Err(HeaderError::InvalidId);
         }
-        true
+        Ok(())
     }
 
-    pub fn is_valid_at(&self, lch: &[Header], timestamp: u64) -> bool {
-        self.is_timestamp_valid_at(timestamp) && self.is_valid_in_lch(lch)
+    pub fn verify_at(
+        &self,
+        lch: &[Header],
+        timestamp: u64,
+        params: &NetworkParams,
+    ) -> Result<(), HeaderError> {
+        if !self.is_timestamp_valid_at(timestamp, params) {
+            return Err(HeaderError::TimestampInFuture);
+        }
+        self.verify_in_lch(lch, params)
     }
 
-    pub fn is_valid_now(&self, lch: &[Header]) -> bool {
-        self.is_valid_at(lch, Header::get_new_timestamp())
+    pub fn verify_now(&self, lch: &[Header], params: &NetworkParams) -> Result<(), HeaderError> {
+        self.verify_at(lch, Header::get_new_timestamp(), params)
     }
 
     pub fn is_genesis(&self) -> bool {
         self.block_num == 0 && self.prev_block_id.iter().all(|&x| x == 0)
     }
 
-    pub fn from_genesis(now: u64) -> Self {
-        let initial_target = BufReader::new(Header::MAX_TARGET_BYTES.to_vec())
-            .read_u256_be()
-            .unwrap();
+    pub fn from_genesis(now: u64, params: &NetworkParams) -> Self {
+        let initial_target = params.max_target;
         let timestamp = now;
         Self {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp,
             block_num: 0,
             target: initial_target,
@@ -205,24 +304,37 @@ impl Header {
             .as_millis() as u64
     }
 
-    pub fn from_lch(lch: &[Header], new_timestamp: u64) -> Result<Self, EbxError> {
+    pub fn from_lch(
+        lch: &[Header],
+        new_timestamp: u64,
+        params: &NetworkParams,
+    ) -> Result<Self, EbxError> {
         if lch.is_empty() {
-            return Ok(Header::from_genesis(new_timestamp));
+            return Ok(Header::from_genesis(new_timestamp, params));
         }
-        let new_target = Header::new_target_from_lch(lch, new_timestamp)?;
-        let prev_block = lch.last().unwrap();
+        let new_target = Header::new_target_from_lch(lch, new_timestamp, params)?;
+        Ok(Header::from_prev(
+            lch.last().unwrap(),
+            new_target,
+            new_timestamp,
+        ))
+    }
+
+    // the header after prev, valid except for PoW if new_target is right
+    pub fn from_prev(prev_block: &Header, new_target: u256, new_timestamp: u64) -> Self {
         let prev_block_id = prev_block.id();
-        let block_num = lch.len() as u32;
+        let block_num = prev_block.block_num + 1;
         let timestamp = new_timestamp;
         let nonce = u256::from(0u8);
         let work_ser_algo = prev_block.work_ser_algo;
         let work_ser_hash = [0u8; 32];
         let work_par_algo = prev_block.work_par_algo;
         let work_par_hash = [0u8; 32];
-        Ok(Self {
+        Self {
             version: 0,
             prev_block_id,
             merkle_root: [0u8; 32],
+            n_transactions: 0,
             timestamp,
             block_num,
             target: new_target,
@@ -231,20 +343,26 @@ impl Header {
             work_ser_hash,
             work_par_algo,
             work_par_hash,
-        })
+        }
     }
 
-    pub fn new_target_from_lch(lch: &[Header], new_timestamp: u64) -> Result<u256, EbxError> {
-        let adjh: Vec<Header> = if lch.len() > Header::BLOCKS_PER_TARGET_ADJ_PERIOD as usize {
-            lch[lch.len() - Header::BLOCKS_PER_TARGET_ADJ_PERIOD as usize..].to_vec()
+    pub fn new_target_from_lch(
+        lch: &[Header],
+        new_timestamp: u64,
+        params: &NetworkParams,
+    ) -> Result<u256, EbxError> {
+        let period = params.blocks_per_target_adj_period as usize;
+        let adjh: Vec<Header> = if lch.len() > period {
+            lch[lch.len() - period..].to_vec()
         } else {
             lch.to_vec()
         };
         let len: u32 = adjh.len() as u32;
         if len == 0 {
-            return Ok(BufReader::new(Header::MAX_TARGET_BYTES.to_vec())
-                .read_u256_be()
-                .unwrap());
+            return Ok(params.max_target);
+        }
+        if params.pow_no_retargeting {
+            return Ok(lch.last().unwrap().target);
         }
         let first_header = adjh[0].clone();
         let mut targets: Vec<BigUint> = Vec::new();
@@ -261,11 +379,15 @@ impl Header {
             });
         }
         let real_time_diff: u64 = new_timestamp - first_header.timestamp;
-        let new_target: u256 = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
-        Ok(new_target)
+        Header::new_target_from_old_targets(target_sum, real_time_diff, len, params)
     }
 
-    pub fn new_target_from_old_targets(target_sum: BigUint, real_time_diff: u64, len: u32) -> u256 {
+    pub fn new_target_from_old_targets(
+        target_sum: BigUint,
+        real_time_diff: u64,
+        len: u32,
+        params: &NetworkParams,
+    ) -> Result<u256, EbxError> {
         // - target_sum is sum of all targets in the adjustment period
         // - real_time_diff is the time difference between the first block in
         //   the adjustment period and now (the new block)
@@ -275,36 +397,60 @@ impl Header {
         // let new_target = (target_sum * real_time_diff) / len / intended_time_diff;
         // let new_target = (target_sum * real_time_diff) / (len * intended_time_diff);
         // the fewest divisions is the most accurate in integer arithmetic...
-        let intended_time_diff = len as u64 * Header::BLOCK_INTERVAL;
+        let intended_time_diff = len as u64 * params.block_interval;
         let res: BigUint = (target_sum * real_time_diff) / (len as u64 * intended_time_diff);
-        u256::from_be_slice(&res.to_bytes_be()).unwrap()
-        //u256::from_be_bytes(&res.to_bytes_be())
+        // blocks slower than intended can't make the target easier than the
+        // max target, which also keeps it from overflowing
+        let max_target =
+            BigUint::from_bytes_be(&BufWriter::new().write_u256_be(params.max_target).to_buf());
+        let res = res.min(max_target);
+        u256::from_be_slice(&res.to_bytes_be()).ok_or(EbxError::GenericError {
+            source: None,
+            message: "target does not fit in 256 bits".to_string(),
+        })
     }
 
-    pub fn coinbase_amount(block_num: u32) -> u64 {
-        // shift every 210,000 blocks ("halving")
-        let shift_by = block_num / 210_000;
+    pub fn coinbase_amount(block_num: u32, params: &NetworkParams) -> u64 {
+        // shift every 210,000 blocks on mainnet ("halving")
+        let shift_by = block_num / params.halving_interval;
         // BTC: 100_000_000 satoshis = 1 bitcoin
         // 100 bitcoins per block for the first 210,000 blocks
         // 100 million satoshis per block for the first 210,000 blocks
         // EBX: 100_000_000_000 adams = 1 earthbuck
         // 100 earthbucks per block for the first 210,000 blocks
         // 100 billion adams per block for the first 210,000 blocks
-        (100 * 100_000_000_000) >> shift_by
+        if shift_by >= u64::BITS {
+            return 0;
+        }
+        params.initial_coinbase_amount >> shift_by
     }
 
-    pub fn difficulty_from_target(target: u256) -> u256 {
-        let max_target = BufReader::new(Header::MAX_TARGET_BYTES.to_vec())
-            .read_u256_be()
-            .unwrap();
-        max_target / target
+    pub fn difficulty_from_target(target: u256, params: &NetworkParams) -> u256 {
+        params.max_target / target
     }
 
-    pub fn target_from_difficulty(difficulty: u256) -> u256 {
-        let max_target = BufReader::new(Header::MAX_TARGET_BYTES.to_vec())
-            .read_u256_be()
-            .unwrap();
-        max_target / difficulty
+    pub fn work(&self) -> ChainWork {
+        ChainWork::from_target(self.target)
+    }
+
+    pub fn target_from_difficulty(difficulty: u256, params: &NetworkParams) -> u256 {
+        params.max_target / difficulty
+    }
+}
+
+// shared by the tests of the modules that need mainnet headers, which need
+// pow5 work. the miner is faster, but this keeps the tests on one thread.
+#[cfg(test)]
+impl Header {
+    pub fn mine_pow5(mut self) -> Header {
+        self.work_par_algo = Header::WORK_PAR_ALGO_POW5;
+        loop {
+            self.work_par_hash = self.get_work_par().unwrap();
+            if self.is_id_valid() {
+                return self;
+            }
+            self.nonce += u256::ONE;
+        }
     }
 }
 
@@ -318,6 +464,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
@@ -344,6 +491,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
@@ -364,12 +512,26 @@ mod tests {
         assert_eq!(bh1.block_num, bh2.block_num);
     }
 
+    #[test]
+    fn test_from_buf_reader_too_short() {
+        let buf = Header::from_genesis(0, &NetworkParams::MAINNET).to_buf();
+        let mut br = BufReader::new(buf[..Header::SIZE - 1].to_vec());
+        match Header::from_buf_reader(&mut br) {
+            Err(EbxError::ParseError { field, offset, .. }) => {
+                assert_eq!(field, "work_par_hash");
+                assert_eq!(offset, Header::SIZE - 32);
+            }
+            _ => panic!("expected a parse error"),
+        }
+    }
+
     #[test]
     fn test_is_version_valid() {
         let bh1 = Header {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
@@ -388,6 +550,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
@@ -406,6 +569,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
@@ -419,7 +583,7 @@ mod tests {
         let hex = hash.to_strict_hex();
         assert_eq!(
             hex,
-            "c62d5bb11ed250524c2a602a51c865b2a9fc9e3e7fa25958bd9ebf4b080d08eb"
+            "34b859ca751e26920aeb47b8e1e755f87293bda150c8cdc854964b7df7821bcf"
         );
     }
 
@@ -429,6 +593,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
@@ -442,36 +607,217 @@ mod tests {
         let hex = id.to_strict_hex();
         assert_eq!(
             hex,
-            "dd4a2cc754029811082c3bf7316c1ef46e198bd2312020f9c61577d693348434"
+            "f9d4c67f087b979dde480d0eb3bf99871ff09c6960f5aaa2a13a88092e2a0c29"
+        );
+    }
+
+    #[test]
+    fn test_layout_matches_pow5() {
+        // pow5 puts a 4 byte nonce in the first 4 bytes of the nonce and the
+        // work par in work_par_hash, then takes the id of the header
+        let mut header = Header::from_genesis(0, &NetworkParams::MAINNET);
+        header.target = u256::from(0u8);
+        header.nonce = u256::from(376413u32) << 224;
+        let buf = header.to_buf().to_vec();
+        assert_eq!(
+            earthbucks_pow5::insert_nonce(vec![0; Header::SIZE], 376413).unwrap(),
+            buf
+        );
+        header.work_par_hash = earthbucks_pow5::get_work_par(buf)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            header.id().to_strict_hex(),
+            "00000004f0ac89d75f135f184abbf0a82fad1e07fb4a29adb159648d70adf474"
+        );
+    }
+
+    #[test]
+    fn test_set_first_4_bytes_of_nonce() {
+        let mut header = Header::from_genesis(0, &NetworkParams::MAINNET);
+        header.nonce = u256::MAX;
+        header.set_first_4_bytes_of_nonce(0x01020304);
+        let buf = header.to_buf();
+        let nonce_start = 1 + 32 + 32 + 8 + 8 + 4 + 32;
+        assert_eq!(buf[nonce_start..nonce_start + 4], [1, 2, 3, 4]);
+        assert_eq!(buf[nonce_start + 4..nonce_start + 32], [0xff; 28]);
+    }
+
+    #[test]
+    fn test_is_work_valid() {
+        let params = NetworkParams::MAINNET;
+        let mut header = Header::from_genesis(0, &params);
+        header.work_par_algo = Header::WORK_PAR_ALGO_POW5;
+        header.work_par_hash = header.get_work_par().unwrap();
+        assert!(header.is_work_valid(&params));
+        header.work_par_hash[0] ^= 1;
+        assert!(!header.is_work_valid(&params));
+
+        // the work par commits to the rest of the header
+        header.work_par_hash = header.get_work_par().unwrap();
+        header.nonce += u256::from(1u8);
+        assert!(!header.is_work_valid(&params));
+
+        header.work_par_algo = Header::WORK_PAR_ALGO_ALGO1627;
+        assert!(header.get_work_par().is_err());
+        assert!(!header.is_work_valid(&params));
+    }
+
+    #[test]
+    fn test_is_work_valid_null_work_par_algo() {
+        // no parallel work is only allowed on regtest
+        let mut header = Header::from_genesis(0, &NetworkParams::MAINNET);
+        assert_eq!(header.work_par_algo, Header::WORK_PAR_ALGO_NULL);
+        assert!(!header.is_work_valid(&NetworkParams::MAINNET));
+        assert!(!header.is_work_valid(&NetworkParams::TESTNET));
+        assert!(header.is_work_valid(&NetworkParams::REGTEST));
+        header.work_par_hash = [1; 32];
+        assert!(!header.is_work_valid(&NetworkParams::REGTEST));
+
+        let params = NetworkParams::MAINNET;
+        let lch = vec![Header::from_genesis(0, &params)];
+        let header = Header::from_lch(&lch, 600_000, &params).unwrap();
+        assert_eq!(header.work_par_algo, Header::WORK_PAR_ALGO_NULL);
+        assert_eq!(
+            header.verify_in_lch(&lch, &params),
+            Err(HeaderError::InvalidWork)
         );
     }
 
     #[test]
     fn test_coinbase_amount() {
-        assert_eq!(Header::coinbase_amount(0), 10_000_000_000_000);
-        assert_eq!(Header::coinbase_amount(210_000), 5_000_000_000_000);
-        assert_eq!(Header::coinbase_amount(420_000), 2_500_000_000_000);
-        assert_eq!(Header::coinbase_amount(630_000), 1_250_000_000_000);
-        assert_eq!(Header::coinbase_amount(840_000), 625_000_000_000);
-        assert_eq!(Header::coinbase_amount(1_050_000), 312_500_000_000);
-        assert_eq!(Header::coinbase_amount(1_260_000), 156_250_000_000);
+        let params = NetworkParams::MAINNET;
+        assert_eq!(Header::coinbase_amount(0, &params), 10_000_000_000_000);
+        assert_eq!(Header::coinbase_amount(210_000, &params), 5_000_000_000_000);
+        assert_eq!(Header::coinbase_amount(420_000, &params), 2_500_000_000_000);
+        assert_eq!(Header::coinbase_amount(630_000, &params), 1_250_000_000_000);
+        assert_eq!(Header::coinbase_amount(840_000, &params), 625_000_000_000);
+        assert_eq!(Header::coinbase_amount(1_050_000, &params), 312_500_000_000);
+        assert_eq!(Header::coinbase_amount(1_260_000, &params), 156_250_000_000);
 
         let mut sum = 0;
         for i in 0..2_000_000 {
-            sum += Header::coinbase_amount(i);
+            sum += Header::coinbase_amount(i, &params);
         }
         // max u64: 18_446_744_073_709_551_616 - 1
         // max val:  4_193_945_312_500_000_000
         assert_eq!(sum, 4_193_945_312_500_000_000);
     }
 
+    #[test]
+    fn test_verify_in_lch() {
+        // regtest, so that the headers need no work
+        let params = NetworkParams::REGTEST;
+        let genesis = Header::from_genesis(0, &params);
+        assert_eq!(genesis.verify_in_lch(&[], &params), Ok(()));
+        let lch = vec![genesis];
+        let header = Header::from_lch(&lch, 1_000, &params).unwrap();
+        assert_eq!(header.verify_in_lch(&lch, &params), Ok(()));
+        assert_eq!(header.verify_at(&lch, 1_000, &params), Ok(()));
+
+        // up to max_future_drift ahead of our clock
+        assert_eq!(header.verify_at(&lch, 0, &params), Ok(()));
+        let future_header =
+            Header::from_lch(&lch, 1_000 + params.max_future_drift + 1, &params).unwrap();
+        assert_eq!(
+            future_header.verify_at(&lch, 1_000, &params),
+            Err(HeaderError::TimestampInFuture)
+        );
+        assert_eq!(
+            header.verify_in_lch(&[], &params),
+            Err(HeaderError::InvalidBlockNum)
+        );
+        let mut bad_header = header.clone();
+        bad_header.prev_block_id = [1; 32];
+        assert_eq!(
+            bad_header.verify_in_lch(&lch, &params),
+            Err(HeaderError::InvalidPrevBlockId)
+        );
+        let mut bad_header = header.clone();
+        bad_header.timestamp = 0;
+        assert_eq!(
+            bad_header.verify_in_lch(&lch, &params),
+            Err(HeaderError::TimestampTooEarly)
+        );
+        let mut bad_header = header.clone();
+        bad_header.target = u256::ONE;
+        assert_eq!(
+            bad_header.verify_in_lch(&lch, &params),
+            Err(HeaderError::InvalidTarget)
+        );
+        let mut bad_header = header;
+        bad_header.work_par_hash = [1; 32];
+        assert_eq!(
+            bad_header.verify_in_lch(&lch, &params),
+            Err(HeaderError::InvalidWork)
+        );
+    }
+
+    #[test]
+    fn test_median_time_past() {
+        let params = NetworkParams::REGTEST;
+        assert_eq!(Header::median_time_past(&[], &params), 0);
+        let mut lch = vec![Header::from_genesis(0, &params)];
+        for timestamp in [1_000, 2_000, 5_000, 4_000] {
+            lch.push(Header::from_lch(&lch, timestamp, &params).unwrap());
+        }
+        assert_eq!(Header::median_time_past(&lch, &params), 2_000);
+        assert_eq!(Header::median_time_past(&lch[..4], &params), 2_000);
+        assert_eq!(Header::median_time_past(&lch[..2], &params), 1_000);
+
+        // only the last median_time_past_len headers count
+        let short_params = NetworkParams {
+            median_time_past_len: 2,
+            ..NetworkParams::REGTEST
+        };
+        assert_eq!(Header::median_time_past(&lch, &short_params), 5_000);
+
+        // a timestamp before the tip's is fine if it is after the median
+        let header = Header::from_lch(&lch, 3_000, &params).unwrap();
+        assert_eq!(header.verify_in_lch(&lch, &params), Ok(()));
+        let header = Header::from_lch(&lch, 2_000, &params).unwrap();
+        assert_eq!(
+            header.verify_in_lch(&lch, &params),
+            Err(HeaderError::TimestampTooEarly)
+        );
+    }
+
+    #[test]
+    fn test_coinbase_amount_regtest() {
+        let params = NetworkParams::REGTEST;
+        assert_eq!(Header::coinbase_amount(149, &params), 10_000_000_000_000);
+        assert_eq!(Header::coinbase_amount(150, &params), 5_000_000_000_000);
+        assert_eq!(Header::coinbase_amount(150 * 43, &params), 1);
+        assert_eq!(Header::coinbase_amount(150 * 44, &params), 0);
+        assert_eq!(Header::coinbase_amount(u32::MAX, &params), 0);
+    }
+
+    #[test]
+    fn test_new_target_from_lch_no_retargeting() {
+        let params = NetworkParams::REGTEST;
+        let mut lch = vec![Header::from_genesis(0, &params)];
+        for i in 1..10 {
+            // much faster than the block interval
+            lch.push(Header::from_lch(&lch, i, &params).unwrap());
+        }
+        for header in &lch {
+            assert_eq!(header.target, params.max_target);
+            assert!(header.is_target_valid(&lch[..header.block_num as usize], &params));
+        }
+
+        // on mainnet the same chain retargets
+        let target = Header::new_target_from_lch(&lch, 10, &NetworkParams::MAINNET).unwrap();
+        assert!(target < params.max_target);
+    }
+
     #[test]
     fn test_difficulty_from_target_1() {
         let target_1_hex = "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
         let target_1: u256 = BufReader::new(Vec::<u8>::from_strict_hex(target_1_hex).unwrap())
             .read_u256_be()
             .unwrap();
-        let diff_1 = Header::difficulty_from_target(target_1);
+        let diff_1 = Header::difficulty_from_target(target_1, &NetworkParams::MAINNET);
         assert_eq!(diff_1, u256::from(1u8));
     }
 
@@ -481,7 +827,7 @@ mod tests {
         let target_2: u256 = BufReader::new(Vec::<u8>::from_strict_hex(target_2_hex).unwrap())
             .read_u256_be()
             .unwrap();
-        let diff_2 = Header::difficulty_from_target(target_2);
+        let diff_2 = Header::difficulty_from_target(target_2, &NetworkParams::MAINNET);
         assert_eq!(diff_2, u256::from(2u8));
     }
 
@@ -491,7 +837,7 @@ mod tests {
         let target_3: u256 = BufReader::new(Vec::<u8>::from_strict_hex(target_3_hex).unwrap())
             .read_u256_be()
             .unwrap();
-        let diff_3 = Header::difficulty_from_target(target_3);
+        let diff_3 = Header::difficulty_from_target(target_3, &NetworkParams::MAINNET);
         assert_eq!(diff_3, u256::from(16u8));
     }
 
@@ -501,7 +847,7 @@ mod tests {
         let target_4: u256 = BufReader::new(Vec::<u8>::from_strict_hex(target_4_hex).unwrap())
             .read_u256_be()
             .unwrap();
-        let diff_4 = Header::difficulty_from_target(target_4);
+        let diff_4 = Header::difficulty_from_target(target_4, &NetworkParams::MAINNET);
         assert_eq!(diff_4, u256::from(32u8));
     }
 
@@ -511,14 +857,14 @@ mod tests {
         let target_5: u256 = BufReader::new(Vec::<u8>::from_strict_hex(target_5_hex).unwrap())
             .read_u256_be()
             .unwrap();
-        let diff_5 = Header::difficulty_from_target(target_5);
+        let diff_5 = Header::difficulty_from_target(target_5, &NetworkParams::MAINNET);
         assert_eq!(diff_5, u256::from(256u16));
     }
 
     #[test]
     fn test_target_from_difficulty_1() {
         let diff_1 = u256::from(1u8);
-        let target_1 = Header::target_from_difficulty(diff_1);
+        let target_1 = Header::target_from_difficulty(diff_1, &NetworkParams::MAINNET);
         let target_1_hex = BufWriter::new()
             .write_u256_be(target_1)
             .to_buf()
@@ -530,7 +876,7 @@ mod tests {
     #[test]
     fn test_target_from_difficulty_2() {
         let diff_2 = u256::from(2u8);
-        let target_2 = Header::target_from_difficulty(diff_2);
+        let target_2 = Header::target_from_difficulty(diff_2, &NetworkParams::MAINNET);
         let target_2_hex = BufWriter::new()
             .write_u256_be(target_2)
             .to_buf()
@@ -542,7 +888,7 @@ mod tests {
     #[test]
     fn test_target_from_difficulty_3() {
         let diff_3 = u256::from(16u8);
-        let target_3 = Header::target_from_difficulty(diff_3);
+        let target_3 = Header::target_from_difficulty(diff_3, &NetworkParams::MAINNET);
         let target_3_hex = BufWriter::new()
             .write_u256_be(target_3)
             .to_buf()
@@ -554,7 +900,7 @@ mod tests {
     #[test]
     fn test_target_from_difficulty_4() {
         let diff_4 = u256::from(32u8);
-        let target_4 = Header::target_from_difficulty(diff_4);
+        let target_4 = Header::target_from_difficulty(diff_4, &NetworkParams::MAINNET);
         let target_4_hex = BufWriter::new()
             .write_u256_be(target_4)
             .to_buf()
@@ -566,7 +912,7 @@ mod tests {
     #[test]
     fn test_target_from_difficulty_5() {
         let diff_5 = u256::from(256u16);
-        let target_5 = Header::target_from_difficulty(diff_5);
+        let target_5 = Header::target_from_difficulty(diff_5, &NetworkParams::MAINNET);
         let target_5_hex = BufWriter::new()
             .write_u256_be(target_5)
             .to_buf()
@@ -584,7 +930,13 @@ mod tests {
         let target_sum = target_1;
         let real_time_diff: u64 = 600_000;
         let len: u32 = 1;
-        let new_target = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
+        let new_target = Header::new_target_from_old_targets(
+            target_sum,
+            real_time_diff,
+            len,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
         let new_target_hex = hex::encode(BufWriter::new().write_u256_be(new_target).to_buf());
         let expected_hex = "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
         assert_eq!(new_target_hex, expected_hex);
@@ -599,7 +951,13 @@ mod tests {
         let target_sum = target_1;
         let real_time_diff: u64 = 300_000;
         let len: u32 = 1;
-        let new_target = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
+        let new_target = Header::new_target_from_old_targets(
+            target_sum,
+            real_time_diff,
+            len,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
         let new_target_hex = hex::encode(BufWriter::new().write_u256_be(new_target).to_buf());
         let expected_hex = "7fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
         assert_eq!(new_target_hex, expected_hex);
@@ -614,7 +972,13 @@ mod tests {
         let target_sum = target_1;
         let real_time_diff: u64 = 600_000;
         let len: u32 = 1;
-        let new_target = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
+        let new_target = Header::new_target_from_old_targets(
+            target_sum,
+            real_time_diff,
+            len,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
         let new_target_hex = hex::encode(BufWriter::new().write_u256_be(new_target).to_buf());
         let expected_hex = "8000000000000000000000000000000000000000000000000000000000000000";
         assert_eq!(new_target_hex, expected_hex);
@@ -629,7 +993,13 @@ mod tests {
         let target_sum = target_1;
         let real_time_diff: u64 = 300_000;
         let len: u32 = 1;
-        let new_target = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
+        let new_target = Header::new_target_from_old_targets(
+            target_sum,
+            real_time_diff,
+            len,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
         let new_target_hex = hex::encode(BufWriter::new().write_u256_be(new_target).to_buf());
         let expected_hex = "4000000000000000000000000000000000000000000000000000000000000000";
         assert_eq!(new_target_hex, expected_hex);
@@ -644,7 +1014,13 @@ mod tests {
         let target_sum = target_1;
         let real_time_diff: u64 = 1_200_000;
         let len: u32 = 1;
-        let new_target = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
+        let new_target = Header::new_target_from_old_targets(
+            target_sum,
+            real_time_diff,
+            len,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
         let new_target_hex = hex::encode(BufWriter::new().write_u256_be(new_target).to_buf());
         let expected_hex = "0100000000000000000000000000000000000000000000000000000000000000";
         assert_eq!(new_target_hex, expected_hex);
@@ -661,7 +1037,13 @@ mod tests {
         let target_sum = target_1 + target_2;
         let real_time_diff: u64 = 600_000 + 600_000;
         let len: u32 = 2;
-        let new_target = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
+        let new_target = Header::new_target_from_old_targets(
+            target_sum,
+            real_time_diff,
+            len,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
         let new_target_hex = hex::encode(BufWriter::new().write_u256_be(new_target).to_buf());
         let expected_hex = "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
         assert_eq!(new_target_hex, expected_hex);
@@ -678,7 +1060,13 @@ mod tests {
         let target_sum = target_1 + target_2;
         let real_time_diff: u64 = 600_000 + 300_000;
         let len: u32 = 2;
-        let new_target = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
+        let new_target = Header::new_target_from_old_targets(
+            target_sum,
+            real_time_diff,
+            len,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
         let new_target_hex: String = format!(
             "{:0>64}",
             hex::encode(BufWriter::new().write_u256_be(new_target).to_buf())
@@ -698,7 +1086,13 @@ mod tests {
         let target_sum = target_1 + target_2;
         let real_time_diff: u64 = 600_000 + 1_200_000;
         let len: u32 = 2;
-        let new_target = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
+        let new_target = Header::new_target_from_old_targets(
+            target_sum,
+            real_time_diff,
+            len,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
         let new_target_hex: String = format!(
             "{:0>64}",
             hex::encode(BufWriter::new().write_u256_be(new_target).to_buf())
@@ -721,7 +1115,13 @@ mod tests {
         let target_sum = target_1 + target_2 + target_3;
         let real_time_diff: u64 = 600_000 + 600_000 + 600_000;
         let len: u32 = 3;
-        let new_target = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
+        let new_target = Header::new_target_from_old_targets(
+            target_sum,
+            real_time_diff,
+            len,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
         let new_target_hex = hex::encode(BufWriter::new().write_u256_be(new_target).to_buf());
         let expected_hex = "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
         assert_eq!(new_target_hex, expected_hex);
@@ -741,7 +1141,13 @@ mod tests {
         let target_sum = target_1 + target_2 + target_3;
         let real_time_diff: u64 = 600_000 + 600_000 + 601_000;
         let len: u32 = 3;
-        let new_target = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
+        let new_target = Header::new_target_from_old_targets(
+            target_sum,
+            real_time_diff,
+            len,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
         let new_target_hex: String = format!(
             "{:0>64}",
             hex::encode(BufWriter::new().write_u256_be(new_target).to_buf())
@@ -764,7 +1170,13 @@ mod tests {
         let target_sum = target_1 + target_2 + target_3;
         let real_time_diff: u64 = 600_000 + 600_000 + 599_000;
         let len: u32 = 3;
-        let new_target = Header::new_target_from_old_targets(target_sum, real_time_diff, len);
+        let new_target = Header::new_target_from_old_targets(
+            target_sum,
+            real_time_diff,
+            len,
+            &NetworkParams::MAINNET,
+        )
+        .unwrap();
         let new_target_hex: String = format!(
             "{:0>64}",
             hex::encode(BufWriter::new().write_u256_be(new_target).to_buf())
@@ -1,42 +1,157 @@
-use crate::error::EbxError;
+use crate::buf_writer::BufWriter;
+use crate::chain_work::ChainWork;
+use crate::error::{EbxError, HeaderError};
 use crate::header::Header;
+use crate::network_params::NetworkParams;
+use crate::numbers::u256;
 use crate::pkh::Pkh;
 use crate::script::Script;
 use crate::script_chunk::ScriptChunk;
 use crate::tx::Tx;
+use num_bigint::BigUint;
+use std::collections::HashMap;
 
-// add Default
+// headers are only changed with add and pop, which keep the sum of the targets
+// in the current target adjustment period up to date. this makes the
+// next target O(1) instead of summing up to 2016 targets for every header.
+// they also keep an index from id to position, so a header can be found
+// without hashing the whole chain.
 #[derive(Default, Clone)]
 pub struct HeaderChain {
-    pub headers: Vec<Header>,
+    headers: Vec<Header>,
+    pub params: NetworkParams,
+    target_sum: BigUint,
+    indexes: HashMap<[u8; 32], usize>,
 }
 
 impl HeaderChain {
-    pub const LENGTH_TARGET_ADJ_PERIOD: u32 = Header::BLOCKS_PER_TARGET_ADJ_PERIOD;
-    pub const LENGTH_EXPIRY_PERIOD: u32 = Script::PKHXR_90D_60D_X_LOCK_REL;
-    pub const LENGTH_SAFETY_PERIOD: u32 = HeaderChain::LENGTH_EXPIRY_PERIOD * 2;
-
     pub fn new() -> Self {
+        Self::from_params(NetworkParams::MAINNET)
+    }
+
+    pub fn from_params(params: NetworkParams) -> Self {
         Self {
             headers: Vec::new(),
+            params,
+            target_sum: BigUint::default(),
+            indexes: HashMap::new(),
         }
     }
 
+    pub fn length_target_adj_period(&self) -> u32 {
+        self.params.blocks_per_target_adj_period
+    }
+
+    pub fn length_expiry_period(&self) -> u32 {
+        self.params.pkhxr_90d_60d_x_lock_rel
+    }
+
+    pub fn length_safety_period(&self) -> u32 {
+        self.length_expiry_period() * 2
+    }
+
+    fn target_to_big_uint(target: u256) -> BigUint {
+        BigUint::from_bytes_be(&BufWriter::new().write_u256_be(target).to_buf())
+    }
+
     pub fn add(&mut self, header: Header) -> &mut Self {
+        self.target_sum += HeaderChain::target_to_big_uint(header.target);
+        self.indexes.insert(header.id(), self.headers.len());
         self.headers.push(header);
+        // the first header of the period is no longer in it
+        let period = self.params.blocks_per_target_adj_period as usize;
+        if self.headers.len() > period {
+            let removed = &self.headers[self.headers.len() - period - 1];
+            self.target_sum -= HeaderChain::target_to_big_uint(removed.target);
+        }
         self
     }
 
+    pub fn pop(&mut self) -> Option<Header> {
+        let header = self.headers.pop()?;
+        self.target_sum -= HeaderChain::target_to_big_uint(header.target);
+        let id = header.id();
+        if self.indexes.get(&id) == Some(&self.headers.len()) {
+            self.indexes.remove(&id);
+        }
+        // the header before the period is back in it
+        let period = self.params.blocks_per_target_adj_period as usize;
+        if self.headers.len() >= period {
+            let restored = &self.headers[self.headers.len() - period];
+            self.target_sum += HeaderChain::target_to_big_uint(restored.target);
+        }
+        Some(header)
+    }
+
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    pub fn get(&self, block_num: usize) -> Option<&Header> {
+        self.headers.get(block_num)
+    }
+
+    // the position of the header with this id, which is its block number
+    pub fn get_index(&self, id: &[u8; 32]) -> Option<usize> {
+        self.indexes.get(id).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
     pub fn get_tip(&self) -> Option<&Header> {
         self.headers.last()
     }
 
-    pub fn new_header_is_valid_at(&self, header: &Header, timestamp: u64) -> bool {
-        header.is_valid_at(&self.headers, timestamp)
+    pub fn median_time_past(&self) -> u64 {
+        Header::median_time_past(&self.headers, &self.params)
     }
 
-    pub fn new_header_is_valid_now(&self, header: &Header) -> bool {
-        header.is_valid_now(&self.headers)
+    pub fn get_chain_work(&self) -> ChainWork {
+        ChainWork::from_headers(&self.headers)
+    }
+
+    // the same as Header::new_target_from_lch on the headers
+    pub fn get_next_target(&self, new_timestamp: u64) -> Result<u256, EbxError> {
+        let period = self.params.blocks_per_target_adj_period as usize;
+        let len = self.headers.len().min(period);
+        if len == 0 {
+            return Ok(self.params.max_target);
+        }
+        if self.params.pow_no_retargeting {
+            return Ok(self.get_tip().unwrap().target);
+        }
+        let first_header = &self.headers[self.headers.len() - len];
+        if new_timestamp <= first_header.timestamp {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "timestamps must be increasing".to_string(),
+            });
+        }
+        let real_time_diff = new_timestamp - first_header.timestamp;
+        Header::new_target_from_old_targets(
+            self.target_sum.clone(),
+            real_time_diff,
+            len as u32,
+            &self.params,
+        )
+    }
+
+    pub fn verify_new_header_at(&self, header: &Header, timestamp: u64) -> Result<(), HeaderError> {
+        if !header.is_timestamp_valid_at(timestamp, &self.params) {
+            return Err(HeaderError::TimestampInFuture);
+        }
+        let target = self.get_next_target(header.timestamp).ok();
+        header.verify_in_lch_with_target(&self.headers, target, &self.params)
+    }
+
+    pub fn verify_new_header_now(&self, header: &Header) -> Result<(), HeaderError> {
+        self.verify_new_header_at(header, Header::get_new_timestamp())
     }
 
     pub fn get_next_coinbase_tx(&self, pkh: &Pkh, domain: &String) -> Tx {
@@ -45,18 +160,25 @@ impl HeaderChain {
         let script_chunk_domain = ScriptChunk::from_data(domain_buf.to_vec());
         let input_script = Script::new(vec![script_chunk_domain]);
         let output_script = Script::from_pkh_output(&pkh.buf);
-        let output_amount = Header::coinbase_amount(building_block_n);
+        let output_amount = Header::coinbase_amount(building_block_n, &self.params);
         Tx::from_coinbase(input_script, output_script, output_amount, building_block_n)
     }
 
     pub fn get_next_header(
         &self,
         merkle_root: [u8; 32],
+        n_transactions: u64,
         new_timestamp: u64,
     ) -> Result<Header, EbxError> {
         // valid block header, except for PoW
-        let mut block_header: Header = Header::from_lch(&self.headers, new_timestamp)?;
+        let mut block_header: Header = match self.get_tip() {
+            Some(tip) => {
+                Header::from_prev(tip, self.get_next_target(new_timestamp)?, new_timestamp)
+            }
+            None => Header::from_genesis(new_timestamp, &self.params),
+        };
         block_header.merkle_root = merkle_root;
+        block_header.n_transactions = n_transactions;
         Ok(block_header)
     }
 }
@@ -73,6 +195,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
@@ -83,7 +206,7 @@ mod tests {
             work_par_hash: [0; 32],
         };
         chain.add(header);
-        assert_eq!(chain.headers.len(), 1);
+        assert_eq!(chain.len(), 1);
     }
 
     #[test]
@@ -93,6 +216,7 @@ mod tests {
             version: 0,
             prev_block_id: [0; 32],
             merkle_root: [0; 32],
+            n_transactions: 0,
             timestamp: 0,
             block_num: 0,
             target: u256::from(0u8),
@@ -105,4 +229,91 @@ mod tests {
         chain.add(header);
         assert_eq!(chain.get_tip().unwrap().version, 0);
     }
+
+    #[test]
+    fn test_get_chain_work() {
+        let mut chain = HeaderChain::new();
+        assert_eq!(chain.get_chain_work(), ChainWork::default());
+        chain.add(Header::from_genesis(0, &NetworkParams::MAINNET));
+        chain.add(Header::from_genesis(0, &NetworkParams::MAINNET));
+        assert_eq!(chain.get_chain_work().to_u256().unwrap(), u256::from(2u8));
+    }
+
+    #[test]
+    fn test_regtest() {
+        let mut chain = HeaderChain::from_params(NetworkParams::REGTEST);
+        assert_eq!(chain.length_target_adj_period(), 2016);
+        assert_eq!(chain.length_safety_period(), 60);
+        // one second blocks at the max target, which every id meets
+        for i in 0..5 {
+            let header = chain.get_next_header([0; 32], 1, i * 1_000).unwrap();
+            assert_eq!(chain.verify_new_header_at(&header, i * 1_000), Ok(()));
+            chain.add(header);
+        }
+        assert_eq!(chain.len(), 5);
+        assert_eq!(chain.median_time_past(), 2_000);
+        assert_eq!(chain.get_tip().unwrap().target, u256::MAX);
+    }
+
+    #[test]
+    fn test_get_next_target_matches_new_target_from_lch() {
+        let params = NetworkParams {
+            blocks_per_target_adj_period: 16,
+            ..NetworkParams::MAINNET
+        };
+        let mut chain = HeaderChain::from_params(params.clone());
+        let assert_matches = |chain: &HeaderChain| {
+            let timestamp = chain.get_tip().map_or(0, |tip| tip.timestamp);
+            for new_timestamp in [timestamp, timestamp + 1, timestamp + 600_000] {
+                let expected = Header::new_target_from_lch(chain.headers(), new_timestamp, &params);
+                let actual = chain.get_next_target(new_timestamp);
+                assert_eq!(actual.ok(), expected.ok());
+            }
+        };
+
+        // varied targets and block intervals
+        let mut timestamp = 0;
+        for i in 0..40u64 {
+            assert_matches(&chain);
+            timestamp += 1 + (i * 7_919 % 13) * 100_000;
+            let mut header = Header::from_genesis(timestamp, &params);
+            header.target = u256::MAX >> (i * 31 % 23) as u32;
+            chain.add(header);
+        }
+        // pop back through the start of the period and add again
+        for _ in 0..20 {
+            assert_matches(&chain);
+            chain.pop();
+        }
+        for _ in 0..10 {
+            timestamp += 300_000;
+            let header = chain.get_next_header([0; 32], 1, timestamp).unwrap();
+            chain.add(header);
+            assert_matches(&chain);
+        }
+        for (i, header) in chain.headers().iter().enumerate() {
+            assert_eq!(chain.get_index(&header.id()), Some(i));
+        }
+        while let Some(header) = chain.pop() {
+            assert_eq!(chain.get_index(&header.id()), None);
+            assert_matches(&chain);
+        }
+        assert_eq!(chain.target_sum, BigUint::default());
+        assert!(chain.indexes.is_empty());
+    }
+
+    #[test]
+    fn test_get_next_target_clamps_to_max_target() {
+        let params = NetworkParams::MAINNET;
+        let mut chain = HeaderChain::from_params(params.clone());
+        chain.add(Header::from_genesis(0, &params));
+        // one block interval after a max target block is already too easy
+        assert_eq!(chain.get_next_target(600_001).unwrap(), params.max_target);
+        assert_eq!(chain.get_next_target(u64::MAX).unwrap(), params.max_target);
+        assert!(chain.get_next_target(599_999).unwrap() < params.max_target);
+        assert_eq!(
+            Header::new_target_from_lch(chain.headers(), 600_001, &params).unwrap(),
+            params.max_target
+        );
+    }
 }
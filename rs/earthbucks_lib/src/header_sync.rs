@@ -0,0 +1,551 @@
+use crate::chain_state::{ChainEvent, ChainState};
+use crate::error::EbxError;
+use crate::header::Header;
+
+// where headers come from, e.g. a connection to a peer. the peer answers a
+// request with ChainState::get_headers_after_locator on its own chain, each
+// header serialized with Header::to_buf.
+pub trait HeaderTransport {
+    fn get_headers(&mut self, request: &HeaderRequest) -> Result<Vec<Vec<u8>>, EbxError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderRequest {
+    pub locator: Vec<[u8; 32]>,
+    pub stop_id: Option<[u8; 32]>, // the last header we want, if not the peer's tip
+    pub max_headers: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderSyncState {
+    Idle,
+    Requested(HeaderRequest),
+    Synced,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderSyncResult {
+    pub events: Vec<ChainEvent>,
+    pub fork_id: Option<[u8; 32]>, // set if the new headers branch off before our tip
+    pub next_request: Option<HeaderRequest>, // none once we are synced with the peer
+}
+
+// headers-first sync with one peer. start makes a request from our longest
+// chain, and every response is validated and added to the chain state, which
+// tells us what to request next until the peer has no more headers for us.
+pub struct HeaderSync {
+    pub chain_state: ChainState,
+    pub max_headers: usize,
+    pub state: HeaderSyncState,
+}
+
+impl HeaderSync {
+    pub const DEFAULT_MAX_HEADERS: usize = 2000;
+
+    pub fn new(chain_state: ChainState) -> Self {
+        Self {
+            chain_state,
+            max_headers: Self::DEFAULT_MAX_HEADERS,
+            state: HeaderSyncState::Idle,
+        }
+    }
+
+    pub fn start(&mut self) -> HeaderRequest {
+        let locator = match self.chain_state.get_tip() {
+            Some(tip) => self.chain_state.get_block_locator(&tip.id()),
+            None => Vec::new(),
+        };
+        self.request(locator, None)
+    }
+
+    fn request(&mut self, locator: Vec<[u8; 32]>, stop_id: Option<[u8; 32]>) -> HeaderRequest {
+        let request = HeaderRequest {
+            locator,
+            stop_id,
+            max_headers: self.max_headers,
+        };
+        self.state = HeaderSyncState::Requested(request.clone());
+        request
+    }
+
+    fn parse_headers(bufs: &[Vec<u8>]) -> Result<Vec<Header>, EbxError> {
+        let mut headers: Vec<Header> = Vec::with_capacity(bufs.len());
+        for buf in bufs {
+            let buf = <[u8; Header::SIZE]>::try_from(buf.as_slice()).map_err(|_| {
+                EbxError::GenericError {
+                    source: None,
+                    message: "invalid header size".to_string(),
+                }
+            })?;
+            let header = Header::from_buf(buf)?;
+            if let Some(prev) = headers.last() {
+                if header.prev_block_id != prev.id() {
+                    return Err(EbxError::GenericError {
+                        source: None,
+                        message: "headers are not a chain".to_string(),
+                    });
+                }
+            }
+            headers.push(header);
+        }
+        Ok(headers)
+    }
+
+    fn add_headers(
+        &mut self,
+        headers: Vec<Header>,
+        timestamp: u64,
+    ) -> Result<HeaderSyncResult, EbxError> {
+        let tip_id = self.chain_state.get_tip().map(|tip| tip.id());
+        let mut result = HeaderSyncResult {
+            events: Vec::new(),
+            fork_id: None,
+            next_request: None,
+        };
+        let mut is_first = true;
+        for header in headers {
+            if self.chain_state.contains(&header.id()) {
+                continue;
+            }
+            // the first new header tells us where the fork is, if any
+            if is_first && !header.is_genesis() && Some(header.prev_block_id) != tip_id {
+                result.fork_id = Some(header.prev_block_id);
+            }
+            is_first = false;
+            let events = self.chain_state.add_header_at(header, timestamp)?;
+            result.events.extend(events);
+        }
+        Ok(result)
+    }
+
+    // the response to the request we made last
+    pub fn process_headers_at(
+        &mut self,
+        bufs: &[Vec<u8>],
+        timestamp: u64,
+    ) -> Result<HeaderSyncResult, EbxError> {
+        let request = match &self.state {
+            HeaderSyncState::Requested(request) => request.clone(),
+            _ => {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "no headers were requested".to_string(),
+                })
+            }
+        };
+        // a peer that sends bad headers is not synced with any further
+        self.state = HeaderSyncState::Idle;
+        if bufs.len() > request.max_headers {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "too many headers".to_string(),
+            });
+        }
+        let headers = HeaderSync::parse_headers(bufs)?;
+        if let Some(first) = headers.first() {
+            if !first.is_genesis() && !self.chain_state.contains(&first.prev_block_id) {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "headers do not connect to the locator".to_string(),
+                });
+            }
+        }
+        let last_id = headers.last().map(|header| header.id());
+        let is_full = headers.len() == request.max_headers;
+        let is_new = headers
+            .iter()
+            .any(|header| !self.chain_state.contains(&header.id()));
+        // otherwise a peer could send the same full batch forever
+        if is_full && !is_new {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "full batch of known headers".to_string(),
+            });
+        }
+        let mut result = self.add_headers(headers, timestamp)?;
+
+        // a full batch means the peer may have more. the next locator starts
+        // from the last header, which may not be on our lch if it is a fork
+        // with less work so far.
+        match last_id {
+            Some(last_id) if is_full && Some(last_id) != request.stop_id => {
+                let locator = self.chain_state.get_block_locator(&last_id);
+                result.next_request = Some(self.request(locator, request.stop_id));
+            }
+            _ => self.state = HeaderSyncState::Synced,
+        }
+        Ok(result)
+    }
+
+    pub fn process_headers_now(&mut self, bufs: &[Vec<u8>]) -> Result<HeaderSyncResult, EbxError> {
+        self.process_headers_at(bufs, Header::get_new_timestamp())
+    }
+
+    // headers a peer sent without a request, e.g. a new tip. if they do not
+    // connect to our chain state, the missing range up to the last of them is
+    // requested.
+    pub fn process_announcement_at(
+        &mut self,
+        bufs: &[Vec<u8>],
+        timestamp: u64,
+    ) -> Result<HeaderSyncResult, EbxError> {
+        if bufs.len() > self.max_headers {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "too many headers".to_string(),
+            });
+        }
+        let headers = HeaderSync::parse_headers(bufs)?;
+        let first = match headers.first() {
+            Some(first) => first,
+            None => {
+                return Ok(HeaderSyncResult {
+                    events: Vec::new(),
+                    fork_id: None,
+                    next_request: None,
+                })
+            }
+        };
+        if !first.is_genesis() && !self.chain_state.contains(&first.prev_block_id) {
+            let last_id = headers.last().unwrap().id();
+            let locator = match self.chain_state.get_tip() {
+                Some(tip) => self.chain_state.get_block_locator(&tip.id()),
+                None => Vec::new(),
+            };
+            return Ok(HeaderSyncResult {
+                events: Vec::new(),
+                fork_id: None,
+                next_request: Some(self.request(locator, Some(last_id))),
+            });
+        }
+        self.add_headers(headers, timestamp)
+    }
+
+    pub fn process_announcement_now(
+        &mut self,
+        bufs: &[Vec<u8>],
+    ) -> Result<HeaderSyncResult, EbxError> {
+        self.process_announcement_at(bufs, Header::get_new_timestamp())
+    }
+
+    // requests headers from the transport until the peer has no more
+    pub fn sync_at<T: HeaderTransport>(
+        &mut self,
+        transport: &mut T,
+        timestamp: u64,
+    ) -> Result<Vec<ChainEvent>, EbxError> {
+        let mut events = Vec::new();
+        let mut request = self.start();
+        loop {
+            let bufs = transport.get_headers(&request)?;
+            let result = self.process_headers_at(&bufs, timestamp)?;
+            events.extend(result.events);
+            match result.next_request {
+                Some(next_request) => request = next_request,
+                None => return Ok(events),
+            }
+        }
+    }
+
+    pub fn sync_now<T: HeaderTransport>(
+        &mut self,
+        transport: &mut T,
+    ) -> Result<Vec<ChainEvent>, EbxError> {
+        self.sync_at(transport, Header::get_new_timestamp())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network_params::NetworkParams;
+
+    // the local chain's timestamp. regtest headers are one second apart.
+    const NOW: u64 = 1_000_000;
+
+    // a peer in the same process, answering requests from its chain state
+    struct FakePeer {
+        chain_state: ChainState,
+        requests: Vec<HeaderRequest>,
+    }
+
+    impl FakePeer {
+        fn new(chain_state: ChainState) -> Self {
+            Self {
+                chain_state,
+                requests: Vec::new(),
+            }
+        }
+    }
+
+    impl HeaderTransport for FakePeer {
+        fn get_headers(&mut self, request: &HeaderRequest) -> Result<Vec<Vec<u8>>, EbxError> {
+            self.requests.push(request.clone());
+            let headers = self.chain_state.get_headers_after_locator(
+                &request.locator,
+                request.stop_id.as_ref(),
+                request.max_headers,
+            );
+            Ok(headers.iter().map(|h| h.to_buf().to_vec()).collect())
+        }
+    }
+
+    // adds n headers on top of tip_id (or a new genesis), with merkle_root
+    // to tell branches apart
+    fn extend(chain_state: &mut ChainState, tip_id: Option<[u8; 32]>, n: usize, merkle_root: u8) {
+        let mut branch = match tip_id {
+            Some(tip_id) => chain_state.get_branch(&tip_id).unwrap(),
+            None => Vec::new(),
+        };
+        for _ in 0..n {
+            let timestamp = branch.len() as u64 * 1_000;
+            let mut header = Header::from_lch(&branch, timestamp, &NetworkParams::REGTEST).unwrap();
+            header.merkle_root = [merkle_root; 32];
+            chain_state.add_header_at(header.clone(), NOW).unwrap();
+            branch.push(header);
+        }
+    }
+
+    fn tip_id(chain_state: &ChainState) -> [u8; 32] {
+        chain_state.get_tip().unwrap().id()
+    }
+
+    fn to_bufs(headers: &[Header]) -> Vec<Vec<u8>> {
+        headers.iter().map(|h| h.to_buf().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_sync_from_empty() {
+        let mut peer_chain = ChainState::from_params(NetworkParams::REGTEST);
+        extend(&mut peer_chain, None, 25, 0);
+        let mut peer = FakePeer::new(peer_chain);
+
+        let mut sync = HeaderSync::new(ChainState::from_params(NetworkParams::REGTEST));
+        sync.max_headers = 10;
+        let events = sync.sync_at(&mut peer, NOW).unwrap();
+        assert_eq!(events.len(), 25);
+        assert_eq!(sync.state, HeaderSyncState::Synced);
+        assert_eq!(
+            sync.chain_state.lch().headers(),
+            peer.chain_state.lch().headers()
+        );
+
+        // 10, 10 and 5 headers, each request starting from the last header
+        assert_eq!(peer.requests.len(), 3);
+        assert!(peer.requests[0].locator.is_empty());
+        assert_eq!(
+            peer.requests[2].locator[0],
+            peer.chain_state.lch().headers()[19].id()
+        );
+
+        // nothing new the second time
+        let events = sync.sync_at(&mut peer, NOW).unwrap();
+        assert!(events.is_empty());
+        assert_eq!(peer.requests.len(), 4);
+    }
+
+    #[test]
+    fn test_sync_reorg() {
+        let mut peer_chain = ChainState::from_params(NetworkParams::REGTEST);
+        extend(&mut peer_chain, None, 5, 0);
+        let mut local_chain = peer_chain.clone();
+        let fork_id = tip_id(&peer_chain);
+        extend(&mut local_chain, Some(fork_id), 2, 1);
+        extend(&mut peer_chain, Some(fork_id), 4, 2);
+        let mut peer = FakePeer::new(peer_chain);
+
+        let mut sync = HeaderSync::new(local_chain.clone());
+        let request = sync.start();
+        let bufs = peer.get_headers(&request).unwrap();
+        assert_eq!(bufs.len(), 4);
+        let result = sync.process_headers_at(&bufs, NOW).unwrap();
+        assert_eq!(result.fork_id, Some(fork_id));
+        assert_eq!(result.next_request, None);
+        let disconnected = &local_chain.lch().headers()[5..];
+        assert_eq!(
+            result.events[..2],
+            [
+                ChainEvent::Disconnect(disconnected[1].clone()),
+                ChainEvent::Disconnect(disconnected[0].clone()),
+            ]
+        );
+        assert_eq!(result.events.len(), 6);
+        assert_eq!(
+            sync.chain_state.lch().headers(),
+            peer.chain_state.lch().headers()
+        );
+    }
+
+    #[test]
+    fn test_sync_fork_with_less_work_so_far() {
+        // the peer's branch only has more work once every batch is in, so the
+        // next locator has to come from the peer's branch, not our lch
+        let mut peer_chain = ChainState::from_params(NetworkParams::REGTEST);
+        extend(&mut peer_chain, None, 3, 0);
+        let mut local_chain = peer_chain.clone();
+        let fork_id = tip_id(&peer_chain);
+        extend(&mut local_chain, Some(fork_id), 5, 1);
+        extend(&mut peer_chain, Some(fork_id), 8, 2);
+        let mut peer = FakePeer::new(peer_chain);
+
+        let mut sync = HeaderSync::new(local_chain);
+        sync.max_headers = 3;
+        let events = sync.sync_at(&mut peer, NOW).unwrap();
+        assert_eq!(events.len(), 5 + 8);
+        assert_eq!(
+            sync.chain_state.lch().headers(),
+            peer.chain_state.lch().headers()
+        );
+        assert_eq!(peer.requests.len(), 3);
+    }
+
+    #[test]
+    fn test_announcement_with_missing_range() {
+        let mut peer_chain = ChainState::from_params(NetworkParams::REGTEST);
+        extend(&mut peer_chain, None, 5, 0);
+        let mut sync = HeaderSync::new(peer_chain.clone());
+        let old_tip_id = tip_id(&peer_chain);
+        extend(&mut peer_chain, Some(old_tip_id), 3, 0);
+        let mut peer = FakePeer::new(peer_chain);
+
+        // the new tip connects to a header we do not have
+        let new_tip = peer.chain_state.get_tip().unwrap().clone();
+        let result = sync
+            .process_announcement_at(&[new_tip.to_buf().to_vec()], NOW)
+            .unwrap();
+        assert!(result.events.is_empty());
+        let request = result.next_request.unwrap();
+        assert_eq!(request.stop_id, Some(new_tip.id()));
+        assert_eq!(sync.state, HeaderSyncState::Requested(request.clone()));
+
+        let bufs = peer.get_headers(&request).unwrap();
+        let result = sync.process_headers_at(&bufs, NOW).unwrap();
+        assert_eq!(result.events.len(), 3);
+        assert_eq!(result.fork_id, None);
+        assert_eq!(result.next_request, None);
+        assert_eq!(sync.chain_state.get_tip().unwrap().id(), new_tip.id());
+
+        // an announcement that connects is added right away
+        extend(&mut peer.chain_state, Some(new_tip.id()), 1, 0);
+        let new_tip = peer.chain_state.get_tip().unwrap().clone();
+        let result = sync
+            .process_announcement_at(&[new_tip.to_buf().to_vec()], NOW)
+            .unwrap();
+        assert_eq!(result.events, vec![ChainEvent::Connect(new_tip)]);
+        assert_eq!(result.next_request, None);
+    }
+
+    #[test]
+    fn test_reject_bad_responses() {
+        let mut peer_chain = ChainState::from_params(NetworkParams::REGTEST);
+        extend(&mut peer_chain, None, 6, 0);
+        let headers = peer_chain.lch().headers().to_vec();
+        let mut sync = HeaderSync::new(ChainState::from_params(NetworkParams::REGTEST));
+
+        // not requested
+        assert!(sync.process_headers_at(&to_bufs(&headers), NOW).is_err());
+
+        sync.max_headers = 5;
+        sync.start();
+        assert!(sync.process_headers_at(&to_bufs(&headers), NOW).is_err());
+        assert_eq!(sync.state, HeaderSyncState::Idle);
+
+        sync.start();
+        let mut bufs = to_bufs(&headers[..3]);
+        bufs[1].pop();
+        assert!(sync.process_headers_at(&bufs, NOW).is_err());
+
+        sync.start();
+        let bufs = to_bufs(&[headers[0].clone(), headers[2].clone()]);
+        assert!(sync.process_headers_at(&bufs, NOW).is_err());
+
+        sync.start();
+        assert!(sync
+            .process_headers_at(&to_bufs(&headers[1..3]), NOW)
+            .is_err());
+
+        // an invalid header is not added, nor any after it
+        sync.start();
+        let mut bad = headers[..3].to_vec();
+        bad[2].version = 1;
+        assert!(sync.process_headers_at(&to_bufs(&bad), NOW).is_err());
+        assert_eq!(sync.chain_state.lch().headers(), &headers[..2]);
+    }
+
+    // a peer that answers every request with the same headers
+    struct ReplayPeer {
+        bufs: Vec<Vec<u8>>,
+        n_requests: usize,
+    }
+
+    impl HeaderTransport for ReplayPeer {
+        fn get_headers(&mut self, _: &HeaderRequest) -> Result<Vec<Vec<u8>>, EbxError> {
+            self.n_requests += 1;
+            Ok(self.bufs.clone())
+        }
+    }
+
+    #[test]
+    fn test_sync_stops_on_replayed_batch() {
+        let mut peer_chain = ChainState::from_params(NetworkParams::REGTEST);
+        extend(&mut peer_chain, None, 5, 0);
+        let mut peer = ReplayPeer {
+            bufs: to_bufs(peer_chain.lch().headers()),
+            n_requests: 0,
+        };
+        let mut sync = HeaderSync::new(ChainState::from_params(NetworkParams::REGTEST));
+        sync.max_headers = 5;
+        let error = sync.sync_at(&mut peer, NOW).unwrap_err();
+        assert_eq!(error.to_string(), "ebx error: full batch of known headers");
+        assert_eq!(peer.n_requests, 2);
+        assert_eq!(sync.chain_state.lch().headers(), peer_chain.lch().headers());
+        assert_eq!(sync.state, HeaderSyncState::Idle);
+    }
+
+    #[test]
+    fn test_reject_oversized_announcement() {
+        let mut peer_chain = ChainState::from_params(NetworkParams::REGTEST);
+        extend(&mut peer_chain, None, 6, 0);
+        let headers = peer_chain.lch().headers().to_vec();
+        let mut sync = HeaderSync::new(ChainState::from_params(NetworkParams::REGTEST));
+        sync.max_headers = 5;
+        assert!(sync
+            .process_announcement_at(&to_bufs(&headers), NOW)
+            .is_err());
+        assert!(sync.chain_state.get_tip().is_none());
+        let result = sync
+            .process_announcement_at(&to_bufs(&headers[..5]), NOW)
+            .unwrap();
+        assert_eq!(result.events.len(), 5);
+    }
+
+    #[test]
+    fn test_slow_block_after_max_target_block() {
+        let params = NetworkParams::MAINNET;
+        let lch = vec![Header::from_genesis(0, &params).mine_pow5()];
+        // twice the block interval, so the target would be above the max
+        let slow = Header::from_lch(&lch, 1_200_000, &params)
+            .unwrap()
+            .mine_pow5();
+        assert_eq!(slow.target, params.max_target);
+
+        let mut sync = HeaderSync::new(ChainState::from_params(params.clone()));
+        sync.start();
+        let result = sync.process_headers_at(&to_bufs(&lch), 1_200_000).unwrap();
+        assert_eq!(result.events.len(), 1);
+        let result = sync
+            .process_announcement_at(&to_bufs(std::slice::from_ref(&slow)), 1_200_000)
+            .unwrap();
+        assert_eq!(result.events, vec![ChainEvent::Connect(slow)]);
+
+        // a side branch from genesis, even slower
+        let slower = Header::from_lch(&lch, 600_000_000, &params)
+            .unwrap()
+            .mine_pow5();
+        assert_eq!(slower.target, params.max_target);
+        let result = sync
+            .process_announcement_at(&to_bufs(&[slower]), 600_000_000)
+            .unwrap();
+        assert!(result.events.is_empty());
+    }
+}
@@ -1,28 +1,42 @@
 pub mod block;
 pub mod block_builder;
+pub mod block_store;
+pub mod block_template;
 pub mod block_verifier;
 pub mod buf;
 pub mod buf_reader;
 pub mod buf_writer;
+pub mod chain_state;
+pub mod chain_work;
 pub mod domain;
 pub mod error;
+pub mod file_utxo_store;
 pub mod hash;
 pub mod header;
 pub mod header_chain;
+pub mod header_sync;
 pub mod key_pair;
+pub mod mempool;
 pub mod merkle_node;
 pub mod merkle_proof;
 pub mod merkle_txs;
+pub mod message;
+pub mod miner;
+pub mod network_params;
 pub mod numbers;
 pub mod opcode;
+pub mod out_point;
 pub mod pkh;
 pub mod pkh_key_map;
 pub mod priv_key;
 pub mod pub_key;
 pub mod script;
+pub mod script_asm;
 pub mod script_chunk;
 pub mod script_interpreter;
 pub mod script_num;
+pub mod script_template;
+pub mod spv_verifier;
 pub mod tx;
 pub mod tx_builder;
 pub mod tx_in;
@@ -32,4 +46,6 @@ pub mod tx_out_bn_map;
 pub mod tx_signature;
 pub mod tx_signer;
 pub mod tx_verifier;
+pub mod utxo_set;
+pub mod utxo_store;
 pub mod var_int;
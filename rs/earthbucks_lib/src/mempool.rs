@@ -0,0 +1,542 @@
+use crate::block::Block;
+use crate::error::{EbxError, MempoolError};
+use crate::network_params::NetworkParams;
+use crate::out_point::OutPoint;
+use crate::tx::Tx;
+use crate::tx_out_bn::TxOutBn;
+use crate::tx_verifier::TxVerifier;
+use crate::utxo_store::UtxoStore;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone)]
+pub struct MempoolEntry {
+    pub tx: Tx,
+    pub parents: HashSet<[u8; 32]>, // unconfirmed txs this tx spends from
+    pub children: HashSet<[u8; 32]>, // unconfirmed txs spending from this tx
+    seq: u64,
+}
+
+// unconfirmed txs that are valid on top of the utxo set. a tx may spend the
+// outputs of other txs in the pool, but no two txs in the pool may spend the
+// same output.
+#[derive(Debug, Clone, Default)]
+pub struct Mempool {
+    pub entries: HashMap<[u8; 32], MempoolEntry>,
+    pub spent_out_points: HashMap<OutPoint, [u8; 32]>, // out point -> spending tx id
+    next_seq: u64,
+}
+
+impl Mempool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, tx_id: &[u8; 32]) -> bool {
+        self.entries.contains_key(tx_id)
+    }
+
+    pub fn get(&self, tx_id: &[u8; 32]) -> Option<&MempoolEntry> {
+        self.entries.get(tx_id)
+    }
+
+    // all txs in the order they were added, so parents come before children
+    pub fn get_txs(&self) -> Vec<&Tx> {
+        let mut entries: Vec<&MempoolEntry> = self.entries.values().collect();
+        entries.sort_by_key(|entry| entry.seq);
+        entries.into_iter().map(|entry| &entry.tx).collect()
+    }
+
+    // block_num is the number of the next block, i.e. the first block the tx
+    // could be included in. the tx is checked with the script limits of
+    // params, like the block verifier does.
+    pub fn add_tx<S: UtxoStore>(
+        &mut self,
+        tx: Tx,
+        utxo_store: &S,
+        block_num: u32,
+        params: &NetworkParams,
+    ) -> Result<(), MempoolError> {
+        let tx_id = tx.id();
+        if self.entries.contains_key(&tx_id) {
+            return Err(MempoolError::AlreadyInMempool);
+        }
+        for (n_in, tx_in) in tx.inputs.iter().enumerate() {
+            let out_point = OutPoint::new(tx_in.input_tx_id, tx_in.input_tx_out_num);
+            if self.spent_out_points.contains_key(&out_point) {
+                return Err(MempoolError::DoubleSpend { n_in });
+            }
+        }
+        let view = MempoolUtxoView {
+            mempool: self,
+            utxo_store,
+            block_num,
+        };
+        let mut tx_verifier = TxVerifier::new(tx.clone(), &view, block_num);
+        tx_verifier.script_limits = params.script_limits.clone();
+        tx_verifier.verify().map_err(MempoolError::InvalidTx)?;
+
+        let mut parents = HashSet::new();
+        for tx_in in &tx.inputs {
+            let out_point = OutPoint::new(tx_in.input_tx_id, tx_in.input_tx_out_num);
+            self.spent_out_points.insert(out_point, tx_id);
+            if let Some(parent) = self.entries.get_mut(&tx_in.input_tx_id) {
+                parent.children.insert(tx_id);
+                parents.insert(tx_in.input_tx_id);
+            }
+        }
+        let entry = MempoolEntry {
+            tx,
+            parents,
+            children: HashSet::new(),
+            seq: self.next_seq,
+        };
+        self.next_seq += 1;
+        self.entries.insert(tx_id, entry);
+        Ok(())
+    }
+
+    // remove a tx and everything that depends on it. the removed txs are
+    // returned with parents before children.
+    pub fn remove_tx(&mut self, tx_id: &[u8; 32]) -> Vec<Tx> {
+        let mut removed = Vec::new();
+        let mut stack = vec![*tx_id];
+        while let Some(tx_id) = stack.pop() {
+            if let Some(entry) = self.remove_entry(&tx_id) {
+                stack.extend(entry.children.iter());
+                removed.push(entry);
+            }
+        }
+        removed.sort_by_key(|entry| entry.seq);
+        removed.into_iter().map(|entry| entry.tx).collect()
+    }
+
+    // remove the txs that were confirmed by the block, and evict txs that
+    // conflict with it together with their descendants. returns the evicted
+    // txs.
+    pub fn connect_block(&mut self, block: &Block) -> Vec<Tx> {
+        let mut evicted = Vec::new();
+        for tx in &block.txs {
+            let tx_id = tx.id();
+            if self.remove_entry(&tx_id).is_some() {
+                continue;
+            }
+            if tx.is_coinbase() {
+                continue;
+            }
+            for tx_in in &tx.inputs {
+                let out_point = OutPoint::new(tx_in.input_tx_id, tx_in.input_tx_out_num);
+                if let Some(conflict_id) = self.spent_out_points.get(&out_point).copied() {
+                    evicted.extend(self.remove_tx(&conflict_id));
+                }
+            }
+        }
+        evicted
+    }
+
+    // put the txs of a block that was disconnected in a reorg back in the
+    // pool. the block must already be disconnected from utxo_store. txs that
+    // are no longer valid, including txs already in the pool that now
+    // conflict with the block's txs, are dropped and returned.
+    pub fn disconnect_block<S: UtxoStore>(
+        &mut self,
+        block: &Block,
+        utxo_store: &S,
+        block_num: u32,
+        params: &NetworkParams,
+    ) -> Vec<Tx> {
+        // the pool's txs may spend the outputs of the block's txs, so the
+        // block's txs go in first
+        let pooled_txs: Vec<Tx> = self.get_txs().into_iter().cloned().collect();
+        self.entries.clear();
+        self.spent_out_points.clear();
+        let mut dropped = Vec::new();
+        let block_txs = block.txs.iter().filter(|tx| !tx.is_coinbase()).cloned();
+        for tx in block_txs.chain(pooled_txs) {
+            if self
+                .add_tx(tx.clone(), utxo_store, block_num, params)
+                .is_err()
+            {
+                dropped.push(tx);
+            }
+        }
+        dropped
+    }
+
+    // remove a single entry, leaving its children in the pool
+    fn remove_entry(&mut self, tx_id: &[u8; 32]) -> Option<MempoolEntry> {
+        let entry = self.entries.remove(tx_id)?;
+        for tx_in in &entry.tx.inputs {
+            let out_point = OutPoint::new(tx_in.input_tx_id, tx_in.input_tx_out_num);
+            self.spent_out_points.remove(&out_point);
+        }
+        for parent_id in &entry.parents {
+            if let Some(parent) = self.entries.get_mut(parent_id) {
+                parent.children.remove(tx_id);
+            }
+        }
+        for child_id in &entry.children {
+            if let Some(child) = self.entries.get_mut(child_id) {
+                child.parents.remove(tx_id);
+            }
+        }
+        Some(entry)
+    }
+}
+
+// the utxo set with the outputs of the txs in the pool added on top. the
+// outputs of unconfirmed txs are given the block num of the next block.
+struct MempoolUtxoView<'a, S: UtxoStore> {
+    mempool: &'a Mempool,
+    utxo_store: &'a S,
+    block_num: u32,
+}
+
+impl<S: UtxoStore> UtxoStore for MempoolUtxoView<'_, S> {
+    fn get_tx_out_bn(&self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+        if let Some(entry) = self.mempool.entries.get(&out_point.tx_id) {
+            let tx_out = entry.tx.outputs.get(out_point.tx_out_num as usize);
+            return Ok(tx_out.map(|tx_out| TxOutBn {
+                tx_out: tx_out.clone(),
+                block_num: self.block_num,
+            }));
+        }
+        self.utxo_store.get_tx_out_bn(out_point)
+    }
+
+    fn add_tx_out_bn(&mut self, _: OutPoint, _: TxOutBn) -> Result<(), EbxError> {
+        Err(EbxError::GenericError {
+            source: None,
+            message: "mempool utxo view is read only".to_string(),
+        })
+    }
+
+    fn remove_tx_out_bn(&mut self, _: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+        Err(EbxError::GenericError {
+            source: None,
+            message: "mempool utxo view is read only".to_string(),
+        })
+    }
+
+    fn get_all_tx_out_bns(&self) -> Result<Vec<(OutPoint, TxOutBn)>, EbxError> {
+        let mut tx_out_bns = self.utxo_store.get_all_tx_out_bns()?;
+        for (tx_id, entry) in &self.mempool.entries {
+            for (tx_out_num, tx_out) in entry.tx.outputs.iter().enumerate() {
+                let out_point = OutPoint::new(*tx_id, tx_out_num as u32);
+                let tx_out_bn = TxOutBn {
+                    tx_out: tx_out.clone(),
+                    block_num: self.block_num,
+                };
+                tx_out_bns.push((out_point, tx_out_bn));
+            }
+        }
+        Ok(tx_out_bns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ScriptLimitError, TxError};
+    use crate::header::Header;
+    use crate::key_pair::KeyPair;
+    use crate::pkh::Pkh;
+    use crate::pkh_key_map::PkhKeyMap;
+    use crate::script::Script;
+    use crate::tx_in::TxIn;
+    use crate::tx_out::TxOut;
+    use crate::tx_out_bn_map::TxOutBnMap;
+    use crate::tx_signer::TxSigner;
+    use crate::utxo_set::UtxoSet;
+
+    struct Wallet {
+        pkh_key_map: PkhKeyMap,
+        pkh: [u8; 32],
+        // every output the wallet has seen, confirmed or not, for signing
+        tx_out_bn_map: TxOutBnMap,
+    }
+
+    impl Wallet {
+        fn new() -> Self {
+            let key = KeyPair::from_random();
+            let pkh = Pkh::from_pub_key_buffer(key.pub_key.buf.to_vec()).buf;
+            let mut pkh_key_map = PkhKeyMap::new();
+            pkh_key_map.add(key, &pkh);
+            Self {
+                pkh_key_map,
+                pkh,
+                tx_out_bn_map: TxOutBnMap::new(),
+            }
+        }
+
+        fn coinbase_tx(&mut self, block_num: u32) -> Tx {
+            let tx = Tx::from_coinbase(
+                Script::from_empty(),
+                Script::from_pkh_output(&self.pkh),
+                100,
+                block_num,
+            );
+            self.tx_out_bn_map.add_tx_outputs(&tx, block_num);
+            tx
+        }
+
+        fn spend_tx(&mut self, out_points: &[OutPoint], values: &[u64]) -> Tx {
+            let inputs = out_points
+                .iter()
+                .map(|out_point| {
+                    TxIn::new(
+                        out_point.tx_id,
+                        out_point.tx_out_num,
+                        Script::from_pkh_input_placeholder(),
+                        0,
+                    )
+                })
+                .collect();
+            let outputs = values
+                .iter()
+                .map(|value| TxOut::new(*value, Script::from_pkh_output(&self.pkh)))
+                .collect();
+            let tx = Tx::new(0, inputs, outputs, 0);
+            let mut tx_signer = TxSigner::new(tx, &self.tx_out_bn_map, &self.pkh_key_map, 0);
+            for n_in in 0..out_points.len() {
+                tx_signer.sign_input(n_in).unwrap();
+            }
+            let tx = tx_signer.tx;
+            self.tx_out_bn_map.add_tx_outputs(&tx, 0);
+            tx
+        }
+    }
+
+    fn block(block_num: u32, txs: Vec<Tx>) -> Block {
+        let mut header = Header::from_genesis(0, &NetworkParams::MAINNET);
+        header.block_num = block_num;
+        Block::new(header, txs)
+    }
+
+    fn setup() -> (Wallet, UtxoSet, Tx) {
+        let mut wallet = Wallet::new();
+        let coinbase = wallet.coinbase_tx(0);
+        let mut utxo_set: UtxoSet = UtxoSet::default();
+        utxo_set
+            .connect_block(&block(0, vec![coinbase.clone()]))
+            .unwrap();
+        (wallet, utxo_set, coinbase)
+    }
+
+    #[test]
+    fn test_add_tx_with_unconfirmed_parent() {
+        let (mut wallet, utxo_set, coinbase) = setup();
+        let mut mempool = Mempool::new();
+        let parent = wallet.spend_tx(&[OutPoint::new(coinbase.id(), 0)], &[60, 40]);
+        let child = wallet.spend_tx(&[OutPoint::new(parent.id(), 1)], &[40]);
+
+        // the child is not valid until its parent is in the pool
+        assert_eq!(
+            mempool.add_tx(
+                child.clone(),
+                &utxo_set.utxo_store,
+                1,
+                &NetworkParams::MAINNET
+            ),
+            Err(MempoolError::InvalidTx(TxError::MissingInput { n_in: 0 }))
+        );
+        mempool
+            .add_tx(
+                parent.clone(),
+                &utxo_set.utxo_store,
+                1,
+                &NetworkParams::MAINNET,
+            )
+            .unwrap();
+        mempool
+            .add_tx(
+                child.clone(),
+                &utxo_set.utxo_store,
+                1,
+                &NetworkParams::MAINNET,
+            )
+            .unwrap();
+        assert_eq!(
+            mempool.add_tx(
+                child.clone(),
+                &utxo_set.utxo_store,
+                1,
+                &NetworkParams::MAINNET
+            ),
+            Err(MempoolError::AlreadyInMempool)
+        );
+
+        let parent_entry = mempool.get(&parent.id()).unwrap();
+        assert_eq!(parent_entry.children, HashSet::from([child.id()]));
+        let child_entry = mempool.get(&child.id()).unwrap();
+        assert_eq!(child_entry.parents, HashSet::from([parent.id()]));
+        assert_eq!(mempool.get_txs(), vec![&parent, &child]);
+    }
+
+    #[test]
+    fn test_reject_invalid_tx() {
+        let (mut wallet, utxo_set, coinbase) = setup();
+        let mut mempool = Mempool::new();
+        // outputs worth more than the inputs
+        let tx = wallet.spend_tx(&[OutPoint::new(coinbase.id(), 0)], &[101]);
+        assert_eq!(
+            mempool.add_tx(tx, &utxo_set.utxo_store, 1, &NetworkParams::MAINNET),
+            Err(MempoolError::InvalidTx(TxError::ValueMismatch {
+                input_value: 100,
+                output_value: 101
+            }))
+        );
+        // coinbase txs are never accepted
+        let coinbase = wallet.coinbase_tx(1);
+        assert_eq!(
+            mempool.add_tx(coinbase, &utxo_set.utxo_store, 1, &NetworkParams::MAINNET),
+            Err(MempoolError::InvalidTx(TxError::Coinbase))
+        );
+        assert!(mempool.is_empty());
+    }
+
+    #[test]
+    fn test_script_limits_from_params() {
+        let (mut wallet, utxo_set, coinbase) = setup();
+        let mut mempool = Mempool::new();
+        let tx = wallet.spend_tx(&[OutPoint::new(coinbase.id(), 0)], &[100]);
+        let mut params = NetworkParams::MAINNET;
+        params.script_limits.max_script_size = tx.inputs[0].script.to_buf().len() - 1;
+        assert_eq!(
+            mempool.add_tx(tx.clone(), &utxo_set.utxo_store, 1, &params),
+            Err(MempoolError::InvalidTx(TxError::ScriptLimitExceeded {
+                n_in: 0,
+                error: ScriptLimitError::ScriptSize
+            }))
+        );
+        mempool
+            .add_tx(tx, &utxo_set.utxo_store, 1, &NetworkParams::MAINNET)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_reject_double_spend() {
+        let (mut wallet, utxo_set, coinbase) = setup();
+        let mut mempool = Mempool::new();
+        let tx1 = wallet.spend_tx(&[OutPoint::new(coinbase.id(), 0)], &[100]);
+        let tx2 = wallet.spend_tx(&[OutPoint::new(coinbase.id(), 0)], &[50, 50]);
+        mempool
+            .add_tx(
+                tx1.clone(),
+                &utxo_set.utxo_store,
+                1,
+                &NetworkParams::MAINNET,
+            )
+            .unwrap();
+        assert_eq!(
+            mempool.add_tx(tx2, &utxo_set.utxo_store, 1, &NetworkParams::MAINNET),
+            Err(MempoolError::DoubleSpend { n_in: 0 })
+        );
+        assert_eq!(
+            mempool
+                .spent_out_points
+                .get(&OutPoint::new(coinbase.id(), 0)),
+            Some(&tx1.id())
+        );
+    }
+
+    #[test]
+    fn test_remove_tx_removes_descendants() {
+        let (mut wallet, utxo_set, coinbase) = setup();
+        let mut mempool = Mempool::new();
+        let tx1 = wallet.spend_tx(&[OutPoint::new(coinbase.id(), 0)], &[50, 50]);
+        let tx2 = wallet.spend_tx(&[OutPoint::new(tx1.id(), 0)], &[50]);
+        let tx3 = wallet.spend_tx(&[OutPoint::new(tx2.id(), 0)], &[50]);
+        let tx4 = wallet.spend_tx(&[OutPoint::new(tx1.id(), 1)], &[50]);
+        for tx in [&tx1, &tx2, &tx3, &tx4] {
+            mempool
+                .add_tx(tx.clone(), &utxo_set.utxo_store, 1, &NetworkParams::MAINNET)
+                .unwrap();
+        }
+
+        assert_eq!(mempool.remove_tx(&tx2.id()), vec![tx2, tx3]);
+        assert_eq!(mempool.get_txs(), vec![&tx1, &tx4]);
+        assert_eq!(
+            mempool.get(&tx1.id()).unwrap().children,
+            HashSet::from([tx4.id()])
+        );
+        assert_eq!(mempool.spent_out_points.len(), 2);
+    }
+
+    #[test]
+    fn test_connect_block() {
+        let (mut wallet, mut utxo_set, coinbase) = setup();
+        let mut mempool = Mempool::new();
+        let coinbase1 = wallet.coinbase_tx(1);
+        utxo_set
+            .connect_block(&block(1, vec![coinbase1.clone()]))
+            .unwrap();
+
+        let tx1 = wallet.spend_tx(&[OutPoint::new(coinbase.id(), 0)], &[100]);
+        let tx2 = wallet.spend_tx(&[OutPoint::new(tx1.id(), 0)], &[100]);
+        let tx3 = wallet.spend_tx(&[OutPoint::new(coinbase1.id(), 0)], &[100]);
+        let tx4 = wallet.spend_tx(&[OutPoint::new(tx3.id(), 0)], &[100]);
+        for tx in [&tx1, &tx2, &tx3, &tx4] {
+            mempool
+                .add_tx(tx.clone(), &utxo_set.utxo_store, 2, &NetworkParams::MAINNET)
+                .unwrap();
+        }
+
+        // block 2 confirms tx1 and contains a tx that conflicts with tx3
+        let conflict = wallet.spend_tx(&[OutPoint::new(coinbase1.id(), 0)], &[99, 1]);
+        let block2 = block(2, vec![wallet.coinbase_tx(2), tx1.clone(), conflict]);
+        utxo_set.connect_block(&block2).unwrap();
+        assert_eq!(mempool.connect_block(&block2), vec![tx3, tx4]);
+        assert_eq!(mempool.get_txs(), vec![&tx2]);
+        assert!(mempool.get(&tx2.id()).unwrap().parents.is_empty());
+    }
+
+    #[test]
+    fn test_disconnect_block() {
+        let (mut wallet, mut utxo_set, coinbase) = setup();
+        let mut mempool = Mempool::new();
+        let tx1 = wallet.spend_tx(&[OutPoint::new(coinbase.id(), 0)], &[100]);
+        let coinbase1 = wallet.coinbase_tx(1);
+        let block1 = block(1, vec![coinbase1.clone(), tx1.clone()]);
+        let undo1 = utxo_set.connect_block(&block1).unwrap();
+
+        // a child of the confirmed tx, and a tx spending the coinbase, which
+        // cannot survive the block being disconnected
+        let tx2 = wallet.spend_tx(&[OutPoint::new(tx1.id(), 0)], &[100]);
+        let tx3 = wallet.spend_tx(&[OutPoint::new(coinbase1.id(), 0)], &[100]);
+        mempool
+            .add_tx(
+                tx2.clone(),
+                &utxo_set.utxo_store,
+                2,
+                &NetworkParams::MAINNET,
+            )
+            .unwrap();
+        mempool
+            .add_tx(
+                tx3.clone(),
+                &utxo_set.utxo_store,
+                2,
+                &NetworkParams::MAINNET,
+            )
+            .unwrap();
+
+        utxo_set.disconnect_block(&block1, undo1).unwrap();
+        assert_eq!(
+            mempool.disconnect_block(&block1, &utxo_set.utxo_store, 1, &NetworkParams::MAINNET),
+            vec![tx3]
+        );
+        assert_eq!(mempool.get_txs(), vec![&tx1, &tx2]);
+        assert_eq!(
+            mempool.get(&tx2.id()).unwrap().parents,
+            HashSet::from([tx1.id()])
+        );
+    }
+}
@@ -1,9 +1,11 @@
 use crate::buf::EbxBuf;
 use crate::buf_reader::BufReader;
 use crate::buf_writer::BufWriter;
+use crate::error::EbxError;
 use crate::hash::double_blake3_hash;
+use std::collections::HashSet;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MerkleProof {
     pub root: [u8; 32],
     pub proof: Vec<([u8; 32], bool)>,
@@ -14,24 +16,44 @@ impl MerkleProof {
         Self { root, proof }
     }
 
+    // is_left is whether our node (not the sibling) is on the left. the proof
+    // goes from the root down, so it is hashed from the leaf up.
     pub fn verify(&self, hashed_data: &[u8; 32]) -> bool {
         let mut hash = hashed_data.to_vec();
-        for (sibling, is_left) in &self.proof {
+        for (sibling, is_left) in self.proof.iter().rev() {
             hash = if *is_left {
-                let mut new_hash = sibling.to_vec();
-                new_hash.extend_from_slice(&hash);
-                double_blake3_hash(&new_hash).to_vec()
-            } else {
                 let mut new_hash = hash.clone();
                 new_hash.extend_from_slice(sibling);
                 double_blake3_hash(&new_hash).to_vec()
+            } else {
+                let mut new_hash = sibling.to_vec();
+                new_hash.extend_from_slice(&hash);
+                double_blake3_hash(&new_hash).to_vec()
             }
         }
         hash == self.root
     }
 
     pub fn verify_proof(data: &[u8; 32], proof: &MerkleProof, root: &[u8; 32]) -> bool {
-        proof.root == *root || proof.verify(data)
+        proof.root == *root && proof.verify(data)
+    }
+
+    // the leaves are padded to a power of two, so every proof in a tree with
+    // n leaves has the same length
+    pub fn proof_length(n_leaves: u64) -> usize {
+        if n_leaves <= 1 {
+            return 0;
+        }
+        (64 - (n_leaves - 1).leading_zeros()) as usize
+    }
+
+    // also checks the proof against the number of leaves: a shorter proof
+    // could prove an inner node instead of a leaf, and a position past the
+    // last leaf is a padding copy of it
+    pub fn verify_strict(&self, hashed_data: &[u8; 32], root: &[u8; 32], n_leaves: u64) -> bool {
+        self.proof.len() == MerkleProof::proof_length(n_leaves)
+            && self.position_in_tree() < n_leaves
+            && MerkleProof::verify_proof(hashed_data, self, root)
     }
 
     pub fn position_in_tree(&self) -> u64 {
@@ -45,6 +67,15 @@ impl MerkleProof {
         position
     }
 
+    // the leaves are padded by copying the last one, so leaves that end with
+    // copies of the last leaf have the same root as without them (like
+    // cve-2012-2459). this is the index of the first leaf that repeats an
+    // earlier one, if any, so that such a mutation can be rejected.
+    pub fn find_duplicate_leaf(hashed_datas: &[[u8; 32]]) -> Option<usize> {
+        let mut seen = HashSet::new();
+        hashed_datas.iter().position(|data| !seen.insert(*data))
+    }
+
     pub fn generate_proofs_and_root(hashed_datas: Vec<[u8; 32]>) -> ([u8; 32], Vec<MerkleProof>) {
         if hashed_datas.is_empty() {
             panic!("Cannot create Merkle tree from empty array");
@@ -118,19 +149,32 @@ impl MerkleProof {
         bw.to_buf()
     }
 
-    pub fn from_buf(u8: &[u8]) -> Result<MerkleProof, String> {
-        let mut br = BufReader::new(u8.to_vec());
-        let root: [u8; 32] = br.read(32).map_err(|e| e.to_string())?.try_into().unwrap();
+    pub fn from_buf_reader(br: &mut BufReader) -> Result<MerkleProof, EbxError> {
+        let root: [u8; 32] = br.read(32)?.try_into().unwrap();
         let mut proof = vec![];
-        let proof_length = br.read_var_int().map_err(|e| e.to_string())? as usize;
+        let proof_length = br.read_var_int()? as usize;
         for _ in 0..proof_length {
-            let sibling: [u8; 32] = br.read(32).map_err(|e| e.to_string())?.try_into().unwrap();
-            let is_left = br.read_u8().map_err(|e| e.to_string())? == 1;
+            let sibling: [u8; 32] = br.read(32)?.try_into().unwrap();
+            let is_left = match br.read_u8()? {
+                0 => false,
+                1 => true,
+                _ => {
+                    return Err(EbxError::GenericError {
+                        source: None,
+                        message: "invalid is_left flag".to_string(),
+                    })
+                }
+            };
             proof.push((sibling, is_left));
         }
         Ok(MerkleProof::new(root, proof))
     }
 
+    pub fn from_buf(u8: &[u8]) -> Result<MerkleProof, String> {
+        let mut br = BufReader::new(u8.to_vec());
+        MerkleProof::from_buf_reader(&mut br).map_err(|e| e.to_string())
+    }
+
     pub fn to_strict_str(&self) -> String {
         hex::encode(self.to_buf())
     }
@@ -481,6 +525,104 @@ mod tests {
         assert!(verified2);
     }
 
+    #[test]
+    fn verify_recomputes_root_from_leaf() {
+        for n in 1..=9 {
+            let data: Vec<[u8; 32]> = (0..n)
+                .map(|i| double_blake3_hash(format!("data{}", i).as_bytes()))
+                .collect();
+            let (root, proofs) = MerkleProof::generate_proofs_and_root(data.clone());
+            for (i, proof) in proofs.iter().enumerate().take(n) {
+                assert_eq!(proof.root, root);
+                assert!(proof.verify(&data[i]));
+                assert!(!proof.verify(&double_blake3_hash("other".as_bytes())));
+            }
+        }
+    }
+
+    #[test]
+    fn verify_proof_rejects_forged_proofs() {
+        let data: Vec<[u8; 32]> = (0..3)
+            .map(|i| double_blake3_hash(format!("data{}", i).as_bytes()))
+            .collect();
+        let (root, proofs) = MerkleProof::generate_proofs_and_root(data.clone());
+        let forged_data = double_blake3_hash("forged".as_bytes());
+
+        // only the root is right
+        let forged = MerkleProof::new(root, vec![]);
+        assert!(!MerkleProof::verify_proof(&forged_data, &forged, &root));
+        assert!(!MerkleProof::verify_proof(&forged_data, &proofs[0], &root));
+
+        // the path is right, but the root is someone else's
+        let other_root = double_blake3_hash("other root".as_bytes());
+        let forged = MerkleProof::new(other_root, proofs[0].proof.clone());
+        assert!(!MerkleProof::verify_proof(&data[0], &forged, &other_root));
+        assert!(!MerkleProof::verify_proof(
+            &data[0],
+            &proofs[0],
+            &other_root
+        ));
+
+        // a sibling flipped to the other side
+        let mut forged = proofs[0].clone();
+        forged.proof[1].1 = !forged.proof[1].1;
+        assert!(!MerkleProof::verify_proof(&data[0], &forged, &root));
+    }
+
+    #[test]
+    fn verify_strict_rejects_inner_nodes_and_padding() {
+        let data: Vec<[u8; 32]> = (0..3)
+            .map(|i| double_blake3_hash(format!("data{}", i).as_bytes()))
+            .collect();
+        let (root, proofs) = MerkleProof::generate_proofs_and_root(data.clone());
+        for (i, proof) in proofs.iter().enumerate().take(3) {
+            assert!(proof.verify_strict(&data[i], &root, 3));
+            assert!(!proof.verify_strict(&data[i], &root, 5));
+        }
+
+        // the inner node over the first two leaves, with the rest of the path
+        let mut inner = data[0].to_vec();
+        inner.extend_from_slice(&data[1]);
+        let inner = double_blake3_hash(&inner);
+        let truncated = MerkleProof::new(root, proofs[0].proof[..1].to_vec());
+        assert!(MerkleProof::verify_proof(&inner, &truncated, &root));
+        assert!(!truncated.verify_strict(&inner, &root, 3));
+
+        // the padding copy of the last leaf
+        assert!(MerkleProof::verify_proof(&data[2], &proofs[3], &root));
+        assert!(!proofs[3].verify_strict(&data[2], &root, 3));
+    }
+
+    #[test]
+    fn find_duplicate_leaf() {
+        let data: Vec<[u8; 32]> = (0..5)
+            .map(|i| double_blake3_hash(format!("data{}", i).as_bytes()))
+            .collect();
+        assert_eq!(MerkleProof::find_duplicate_leaf(&data), None);
+
+        // the last leaf copied has the same root
+        for n in [3, 5] {
+            let leaves = data[..n].to_vec();
+            let mut mutated = leaves.clone();
+            mutated.push(leaves[n - 1]);
+            let (root, _) = MerkleProof::generate_proofs_and_root(leaves.clone());
+            let (mutated_root, _) = MerkleProof::generate_proofs_and_root(mutated.clone());
+            assert_eq!(root, mutated_root);
+            assert_eq!(MerkleProof::find_duplicate_leaf(&leaves), None);
+            assert_eq!(MerkleProof::find_duplicate_leaf(&mutated), Some(n));
+        }
+
+        let mut leaves = data.clone();
+        leaves[3] = data[1];
+        assert_eq!(MerkleProof::find_duplicate_leaf(&leaves), Some(3));
+    }
+
+    #[test]
+    fn proof_length() {
+        let lengths: Vec<usize> = (0..10).map(MerkleProof::proof_length).collect();
+        assert_eq!(lengths, vec![0, 0, 1, 2, 2, 3, 3, 3, 3, 4]);
+    }
+
     #[test]
     fn to_buf_and_from_buf() {
         let data1 = double_blake3_hash("data1".as_bytes());
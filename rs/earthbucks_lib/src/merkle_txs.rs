@@ -18,11 +18,22 @@ impl MerkleTxs {
         self.txs.iter().zip(self.proofs.iter())
     }
 
+    // see MerkleProof::find_duplicate_leaf
+    pub fn find_duplicate_tx(&self) -> Option<usize> {
+        let tx_ids: Vec<[u8; 32]> = self.txs.iter().map(|tx| tx.id()).collect();
+        MerkleProof::find_duplicate_leaf(&tx_ids)
+    }
+
     pub fn verify(&self) -> bool {
+        // there are also proofs for the padding leaves, which are not checked
+        if self.proofs.len() < self.txs.len() {
+            return false;
+        }
+        let n_leaves = self.txs.len() as u64;
         for i in 0..self.txs.len() {
             let tx = &self.txs[i];
             let proof = &self.proofs[i];
-            if !MerkleProof::verify_proof(&tx.id(), proof, &self.root) {
+            if !proof.verify_strict(&tx.id(), &self.root, n_leaves) {
                 return false;
             }
         }
@@ -60,4 +71,42 @@ mod tests {
         let verified = merkle_txs.verify();
         assert!(verified);
     }
+
+    #[test]
+    fn find_duplicate_tx() {
+        let txs: Vec<Tx> = (0..3).map(|i| Tx::new(0, vec![], vec![], i)).collect();
+        let merkle_txs = MerkleTxs::new(txs.clone());
+        assert_eq!(merkle_txs.find_duplicate_tx(), None);
+
+        let mut mutated_txs = txs;
+        mutated_txs.push(mutated_txs[2].clone());
+        let mutated = MerkleTxs::new(mutated_txs);
+        assert_eq!(mutated.root, merkle_txs.root);
+        assert_eq!(mutated.find_duplicate_tx(), Some(3));
+    }
+
+    #[test]
+    fn verify_rejects_forged_proofs() {
+        let txs: Vec<Tx> = (0..3).map(|i| Tx::new(0, vec![], vec![], i)).collect();
+
+        // a proof that only carries the right root
+        let mut merkle_txs = MerkleTxs::new(txs.clone());
+        merkle_txs.proofs[1] = MerkleProof::new(merkle_txs.root, vec![]);
+        assert!(!merkle_txs.verify());
+
+        // the proofs of two txs swapped
+        let mut merkle_txs = MerkleTxs::new(txs.clone());
+        merkle_txs.proofs.swap(0, 1);
+        assert!(!merkle_txs.verify());
+
+        // a tx replaced, keeping its proof
+        let mut merkle_txs = MerkleTxs::new(txs.clone());
+        merkle_txs.txs[2] = Tx::new(0, vec![], vec![], 3);
+        assert!(!merkle_txs.verify());
+
+        // a missing proof
+        let mut merkle_txs = MerkleTxs::new(txs);
+        merkle_txs.proofs.truncate(2);
+        assert!(!merkle_txs.verify());
+    }
 }
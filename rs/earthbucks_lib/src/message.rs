@@ -0,0 +1,567 @@
+use crate::block::Block;
+use crate::buf_reader::BufReader;
+use crate::buf_writer::BufWriter;
+use crate::error::EbxError;
+use crate::hash::blake3_hash;
+use crate::header::Header;
+use crate::header_sync::{HeaderRequest, HeaderSync};
+use crate::merkle_proof::MerkleProof;
+use crate::network_params::NetworkParams;
+use crate::tx::Tx;
+
+// every p2p message is an envelope followed by the payload. the envelope has
+// the network's magic, the envelope version, the command padded with zeros,
+// and the length and blake3 checksum of the payload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageEnvelope {
+    pub magic: [u8; 4],
+    pub version: u8,
+    pub command: String,
+    pub length: u32,
+    pub checksum: [u8; 4],
+}
+
+impl MessageEnvelope {
+    pub const VERSION: u8 = 0;
+    pub const COMMAND_SIZE: usize = 12;
+    pub const SIZE: usize = 4 + 1 + MessageEnvelope::COMMAND_SIZE + 4 + 4;
+    pub const MAX_PAYLOAD_SIZE: u32 = 32_000_000;
+
+    pub fn new(magic: [u8; 4], command: &str, payload: &[u8]) -> Self {
+        Self {
+            magic,
+            version: MessageEnvelope::VERSION,
+            command: command.to_string(),
+            length: payload.len() as u32,
+            checksum: MessageEnvelope::checksum(payload),
+        }
+    }
+
+    // the first 4 bytes of the blake3 hash
+    pub fn checksum(payload: &[u8]) -> [u8; 4] {
+        blake3_hash(payload)[..4].try_into().unwrap()
+    }
+
+    pub fn to_buf(&self) -> Vec<u8> {
+        let mut command = self.command.as_bytes().to_vec();
+        command.resize(MessageEnvelope::COMMAND_SIZE, 0);
+        let mut bw = BufWriter::new();
+        bw.write(self.magic.to_vec());
+        bw.write_u8(self.version);
+        bw.write(command);
+        bw.write_u32_be(self.length);
+        bw.write(self.checksum.to_vec());
+        bw.to_buf()
+    }
+
+    // reads just the envelope, so a stream reader knows how long the payload is
+    pub fn from_buf_reader(br: &mut BufReader) -> Result<Self, EbxError> {
+        let magic: [u8; 4] = br.read_field("magic", |br| br.read(4))?.try_into().unwrap();
+        let version = br.read_field("version", |br| br.read_u8())?;
+        if version != MessageEnvelope::VERSION {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: format!("unsupported message version: {}", version),
+            });
+        }
+        let command = br.read_field("command", |br| {
+            let buf = br.read(MessageEnvelope::COMMAND_SIZE)?;
+            // lowercase letters, then only zeros
+            let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            if len == 0
+                || !buf[..len].iter().all(|b| b.is_ascii_lowercase())
+                || buf[len..].iter().any(|&b| b != 0)
+            {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "invalid command".to_string(),
+                });
+            }
+            Ok(String::from_utf8(buf[..len].to_vec()).unwrap())
+        })?;
+        let length = br.read_field("length", |br| br.read_u32_be())?;
+        if length > MessageEnvelope::MAX_PAYLOAD_SIZE {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "message payload too large".to_string(),
+            });
+        }
+        let checksum: [u8; 4] = br
+            .read_field("checksum", |br| br.read(4))?
+            .try_into()
+            .unwrap();
+        Ok(Self {
+            magic,
+            version,
+            command,
+            length,
+            checksum,
+        })
+    }
+
+    pub fn verify_payload(&self, payload: &[u8]) -> Result<(), EbxError> {
+        if payload.len() != self.length as usize {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "message payload length mismatch".to_string(),
+            });
+        }
+        if MessageEnvelope::checksum(payload) != self.checksum {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "message checksum mismatch".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvKind {
+    Tx = 1,
+    Block = 2,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvItem {
+    pub kind: InvKind,
+    pub id: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionMessage {
+    pub protocol_version: u32,
+    pub timestamp: u64, // milliseconds
+    pub nonce: u64,     // random, to detect connecting to ourselves
+    pub tip_block_num: u32,
+    pub user_agent: String,
+}
+
+impl VersionMessage {
+    pub const MAX_USER_AGENT_SIZE: usize = 256;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Version(VersionMessage),
+    VerAck,
+    Inv(Vec<InvItem>),
+    GetHeaders(HeaderRequest),
+    Headers(Vec<Header>),
+    GetBlock {
+        block_id: [u8; 32],
+    },
+    Block(Block),
+    Tx(Tx),
+    GetMerkleProof {
+        block_id: [u8; 32],
+        tx_id: [u8; 32],
+    },
+    MerkleProof {
+        block_id: [u8; 32],
+        tx_id: [u8; 32],
+        proof: MerkleProof,
+    },
+}
+
+impl Message {
+    pub const PROTOCOL_VERSION: u32 = 1;
+
+    pub fn command(&self) -> &'static str {
+        match self {
+            Message::Version(_) => "version",
+            Message::VerAck => "verack",
+            Message::Inv(_) => "inv",
+            Message::GetHeaders(_) => "getheaders",
+            Message::Headers(_) => "headers",
+            Message::GetBlock { .. } => "getblock",
+            Message::Block(_) => "block",
+            Message::Tx(_) => "tx",
+            Message::GetMerkleProof { .. } => "getproof",
+            Message::MerkleProof { .. } => "proof",
+        }
+    }
+
+    pub fn payload_to_buf(&self) -> Vec<u8> {
+        let mut bw = BufWriter::new();
+        match self {
+            Message::Version(version) => {
+                bw.write_u32_be(version.protocol_version);
+                bw.write_u64_be(version.timestamp);
+                bw.write_u64_be(version.nonce);
+                bw.write_u32_be(version.tip_block_num);
+                bw.write_var_int(version.user_agent.len() as u64);
+                bw.write(version.user_agent.as_bytes().to_vec());
+            }
+            Message::VerAck => {}
+            Message::Inv(items) => {
+                bw.write_var_int(items.len() as u64);
+                for item in items {
+                    bw.write_u8(item.kind as u8);
+                    bw.write(item.id.to_vec());
+                }
+            }
+            Message::GetHeaders(request) => {
+                bw.write_var_int(request.locator.len() as u64);
+                for id in &request.locator {
+                    bw.write(id.to_vec());
+                }
+                match request.stop_id {
+                    Some(stop_id) => {
+                        bw.write_u8(1);
+                        bw.write(stop_id.to_vec());
+                    }
+                    None => {
+                        bw.write_u8(0);
+                    }
+                }
+                bw.write_var_int(request.max_headers as u64);
+            }
+            Message::Headers(headers) => {
+                bw.write_var_int(headers.len() as u64);
+                for header in headers {
+                    bw.write(header.to_buf().to_vec());
+                }
+            }
+            Message::GetBlock { block_id } => {
+                bw.write(block_id.to_vec());
+            }
+            Message::Block(block) => {
+                bw.write(block.to_buf());
+            }
+            Message::Tx(tx) => {
+                bw.write(tx.to_buf());
+            }
+            Message::GetMerkleProof { block_id, tx_id } => {
+                bw.write(block_id.to_vec());
+                bw.write(tx_id.to_vec());
+            }
+            Message::MerkleProof {
+                block_id,
+                tx_id,
+                proof,
+            } => {
+                bw.write(block_id.to_vec());
+                bw.write(tx_id.to_vec());
+                bw.write(proof.to_buf());
+            }
+        }
+        bw.to_buf()
+    }
+
+    fn read_id(br: &mut BufReader) -> Result<[u8; 32], EbxError> {
+        Ok(br.read(32)?.try_into().unwrap())
+    }
+
+    pub fn from_payload(command: &str, payload: Vec<u8>) -> Result<Message, EbxError> {
+        let mut br = BufReader::new(payload);
+        let message = match command {
+            "version" => {
+                let protocol_version = br.read_field("protocol_version", |br| br.read_u32_be())?;
+                let timestamp = br.read_field("timestamp", |br| br.read_u64_be())?;
+                let nonce = br.read_field("nonce", |br| br.read_u64_be())?;
+                let tip_block_num = br.read_field("tip_block_num", |br| br.read_u32_be())?;
+                let user_agent = br.read_field("user_agent", |br| {
+                    let len = br.read_var_int()? as usize;
+                    if len > VersionMessage::MAX_USER_AGENT_SIZE {
+                        return Err(EbxError::GenericError {
+                            source: None,
+                            message: "user agent too long".to_string(),
+                        });
+                    }
+                    String::from_utf8(br.read(len)?).map_err(|_| EbxError::GenericError {
+                        source: None,
+                        message: "user agent is not utf-8".to_string(),
+                    })
+                })?;
+                Message::Version(VersionMessage {
+                    protocol_version,
+                    timestamp,
+                    nonce,
+                    tip_block_num,
+                    user_agent,
+                })
+            }
+            "verack" => Message::VerAck,
+            "inv" => {
+                let count = br.read_field("count", |br| br.read_var_int())?;
+                let mut items = Vec::new();
+                for i in 0..count {
+                    let item = br.read_indexed_field("items", i as usize, |br| {
+                        let kind = match br.read_u8()? {
+                            1 => InvKind::Tx,
+                            2 => InvKind::Block,
+                            _ => {
+                                return Err(EbxError::GenericError {
+                                    source: None,
+                                    message: "unknown inv kind".to_string(),
+                                })
+                            }
+                        };
+                        let id = Message::read_id(br)?;
+                        Ok(InvItem { kind, id })
+                    })?;
+                    items.push(item);
+                }
+                Message::Inv(items)
+            }
+            "getheaders" => {
+                let count = br.read_field("locator_count", |br| br.read_var_int())?;
+                let mut locator = Vec::new();
+                for i in 0..count {
+                    locator.push(br.read_indexed_field("locator", i as usize, Message::read_id)?);
+                }
+                let stop_id = br.read_field("stop_id", |br| match br.read_u8()? {
+                    0 => Ok(None),
+                    1 => Ok(Some(Message::read_id(br)?)),
+                    _ => Err(EbxError::GenericError {
+                        source: None,
+                        message: "invalid stop id flag".to_string(),
+                    }),
+                })?;
+                let max_headers = br.read_field("max_headers", |br| br.read_var_int())?;
+                if max_headers > HeaderSync::DEFAULT_MAX_HEADERS as u64 {
+                    return Err(EbxError::GenericError {
+                        source: None,
+                        message: "max_headers too large".to_string(),
+                    });
+                }
+                let max_headers = max_headers as usize;
+                Message::GetHeaders(HeaderRequest {
+                    locator,
+                    stop_id,
+                    max_headers,
+                })
+            }
+            "headers" => {
+                let count = br.read_field("count", |br| br.read_var_int())?;
+                if count > HeaderSync::DEFAULT_MAX_HEADERS as u64 {
+                    return Err(EbxError::GenericError {
+                        source: None,
+                        message: "too many headers".to_string(),
+                    });
+                }
+                let mut headers = Vec::new();
+                for i in 0..count {
+                    headers.push(br.read_indexed_field(
+                        "headers",
+                        i as usize,
+                        Header::from_buf_reader,
+                    )?);
+                }
+                Message::Headers(headers)
+            }
+            "getblock" => Message::GetBlock {
+                block_id: br.read_field("block_id", Message::read_id)?,
+            },
+            "block" => Message::Block(br.read_field("block", Block::from_buf_reader)?),
+            "tx" => Message::Tx(br.read_field("tx", Tx::from_buf_reader)?),
+            "getproof" => Message::GetMerkleProof {
+                block_id: br.read_field("block_id", Message::read_id)?,
+                tx_id: br.read_field("tx_id", Message::read_id)?,
+            },
+            "proof" => Message::MerkleProof {
+                block_id: br.read_field("block_id", Message::read_id)?,
+                tx_id: br.read_field("tx_id", Message::read_id)?,
+                proof: br.read_field("proof", MerkleProof::from_buf_reader)?,
+            },
+            _ => {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: format!("unknown command: {}", command),
+                })
+            }
+        };
+        if !br.eof() {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "message payload has extra bytes".to_string(),
+            });
+        }
+        Ok(message)
+    }
+
+    pub fn to_buf(&self, params: &NetworkParams) -> Vec<u8> {
+        let payload = self.payload_to_buf();
+        let envelope = MessageEnvelope::new(params.magic, self.command(), &payload);
+        let mut buf = envelope.to_buf();
+        buf.extend(payload);
+        buf
+    }
+
+    pub fn from_envelope(
+        envelope: &MessageEnvelope,
+        payload: Vec<u8>,
+        params: &NetworkParams,
+    ) -> Result<Message, EbxError> {
+        if envelope.magic != params.magic {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "wrong network magic".to_string(),
+            });
+        }
+        envelope.verify_payload(&payload)?;
+        Message::from_payload(&envelope.command, payload)
+    }
+
+    pub fn from_buf_reader(
+        br: &mut BufReader,
+        params: &NetworkParams,
+    ) -> Result<Message, EbxError> {
+        let envelope = MessageEnvelope::from_buf_reader(br)?;
+        let payload = br.read_field("payload", |br| br.read(envelope.length as usize))?;
+        Message::from_envelope(&envelope, payload, params)
+    }
+
+    pub fn from_buf(buf: Vec<u8>, params: &NetworkParams) -> Result<Message, EbxError> {
+        let mut br = BufReader::new(buf);
+        let message = Message::from_buf_reader(&mut br, params)?;
+        if !br.eof() {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "extra bytes after message".to_string(),
+            });
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Script;
+    use crate::tx_in::TxIn;
+    use crate::tx_out::TxOut;
+    use serde::Deserialize;
+    use std::fs;
+
+    #[derive(Deserialize)]
+    struct TestVectorMessages {
+        messages: Vec<TestVectorMessage>,
+        errors: Vec<TestVectorMessageError>,
+    }
+
+    #[derive(Deserialize)]
+    struct TestVectorMessage {
+        command: String,
+        hex: String,
+    }
+
+    #[derive(Deserialize)]
+    struct TestVectorMessageError {
+        hex: String,
+        error: String,
+    }
+
+    fn read_test_vectors() -> TestVectorMessages {
+        let data = fs::read_to_string("./test_vectors/message.json").expect("Unable to read file");
+        serde_json::from_str(&data).expect("Unable to parse JSON")
+    }
+
+    // the messages in test_vectors/message.json, in order
+    fn messages() -> Vec<Message> {
+        let tx = Tx::new(
+            1,
+            vec![TxIn::new([6; 32], 0, Script::from_empty(), 0)],
+            vec![TxOut::new(100, Script::from_pkh_output(&[7; 32]))],
+            0,
+        );
+        let header = Header::from_genesis(0, &NetworkParams::MAINNET);
+        let (_, proofs) = MerkleProof::generate_proofs_and_root(vec![[8; 32], [9; 32], [10; 32]]);
+        vec![
+            Message::Version(VersionMessage {
+                protocol_version: Message::PROTOCOL_VERSION,
+                timestamp: 1_700_000_000_000,
+                nonce: 0x0102030405060708,
+                tip_block_num: 100,
+                user_agent: "earthbucks/0.1".to_string(),
+            }),
+            Message::VerAck,
+            Message::Inv(vec![
+                InvItem {
+                    kind: InvKind::Tx,
+                    id: [1; 32],
+                },
+                InvItem {
+                    kind: InvKind::Block,
+                    id: [2; 32],
+                },
+            ]),
+            Message::GetHeaders(HeaderRequest {
+                locator: vec![[3; 32], [4; 32]],
+                stop_id: None,
+                max_headers: 2000,
+            }),
+            Message::GetHeaders(HeaderRequest {
+                locator: vec![],
+                stop_id: Some([5; 32]),
+                max_headers: 10,
+            }),
+            Message::Headers(vec![header.clone()]),
+            Message::GetBlock { block_id: [5; 32] },
+            Message::Block(Block::new(header, vec![tx.clone()])),
+            Message::Tx(tx),
+            Message::GetMerkleProof {
+                block_id: [5; 32],
+                tx_id: [10; 32],
+            },
+            Message::MerkleProof {
+                block_id: [5; 32],
+                tx_id: [10; 32],
+                proof: proofs[2].clone(),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_vectors_messages() {
+        let test_vectors = read_test_vectors();
+        let messages = messages();
+        assert_eq!(test_vectors.messages.len(), messages.len());
+        for (test_vector, message) in test_vectors.messages.iter().zip(messages) {
+            let buf = hex::decode(&test_vector.hex).unwrap();
+            assert_eq!(message.command(), test_vector.command);
+            assert_eq!(message.to_buf(&NetworkParams::MAINNET), buf);
+            let decoded = Message::from_buf(buf, &NetworkParams::MAINNET).unwrap();
+            assert_eq!(decoded, message);
+        }
+    }
+
+    #[test]
+    fn test_vectors_errors() {
+        for test_vector in read_test_vectors().errors {
+            let buf = hex::decode(&test_vector.hex).unwrap();
+            match Message::from_buf(buf, &NetworkParams::MAINNET) {
+                Ok(_) => panic!("Expected an error, but got Ok(_)"),
+                Err(e) => assert_eq!(e.to_string(), test_vector.error),
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_stream() {
+        // a stream reader reads the envelope first, then exactly the payload
+        let params = NetworkParams::REGTEST;
+        let messages = messages();
+        let mut stream = Vec::new();
+        for message in &messages {
+            stream.extend(message.to_buf(&params));
+        }
+        let mut br = BufReader::new(stream);
+        for message in messages {
+            let envelope = MessageEnvelope::from_buf_reader(&mut br).unwrap();
+            assert_eq!(envelope.magic, *b"ebxr");
+            assert_eq!(envelope.command, message.command());
+            let payload = br.read(envelope.length as usize).unwrap();
+            assert_eq!(
+                Message::from_envelope(&envelope, payload, &params).unwrap(),
+                message
+            );
+        }
+        assert!(br.eof());
+
+        let buf = Message::VerAck.to_buf(&params);
+        assert_eq!(buf.len(), MessageEnvelope::SIZE);
+        assert!(Message::from_buf(buf, &NetworkParams::MAINNET).is_err());
+    }
+}
@@ -0,0 +1,204 @@
+use crate::header::Header;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct MineResult {
+    pub header: Option<Header>, // none if cancelled or the nonce space ran out
+    pub hash_count: u64,
+    pub elapsed: Duration,
+}
+
+impl MineResult {
+    // hashes per second
+    pub fn hashrate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.hash_count as f64 / secs
+    }
+}
+
+// searches the first 4 bytes of the nonce for a header with a valid id, using
+// the header's work_par_algo. thread i tries nonces i, i + n, i + 2n, ... for
+// n threads. the other 28 bytes of the nonce are left as they are, so callers
+// can change them to search again once the nonce space runs out.
+pub struct Miner {
+    pub header: Header,
+    pub thread_count: usize,
+    run: AtomicU64,           // the number of the current run, or the next one
+    cancelled_run: AtomicU64, // one more than the last run that was cancelled
+    hash_count: AtomicU64,
+    start: Mutex<Option<Instant>>,
+}
+
+impl Miner {
+    pub fn new(header: Header, thread_count: usize) -> Self {
+        Self {
+            header,
+            thread_count: thread_count.max(1),
+            run: AtomicU64::new(0),
+            cancelled_run: AtomicU64::new(0),
+            hash_count: AtomicU64::new(0),
+            start: Mutex::new(None),
+        }
+    }
+
+    // can be called from another thread. cancels the current run, or the next
+    // one if mine is not running, and the run after that searches again.
+    pub fn cancel(&self) {
+        let run = self.run.load(Ordering::Relaxed);
+        self.cancelled_run.store(run + 1, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.is_run_cancelled(self.run.load(Ordering::Relaxed))
+    }
+
+    fn is_run_cancelled(&self, run: u64) -> bool {
+        self.cancelled_run.load(Ordering::Relaxed) == run + 1
+    }
+
+    pub fn hash_count(&self) -> u64 {
+        self.hash_count.load(Ordering::Relaxed)
+    }
+
+    // hashes per second of the current or last run
+    pub fn hashrate(&self) -> f64 {
+        let start = *self.start.lock().unwrap();
+        match start {
+            Some(start) => {
+                let secs = start.elapsed().as_secs_f64();
+                if secs == 0.0 {
+                    return 0.0;
+                }
+                self.hash_count() as f64 / secs
+            }
+            None => 0.0,
+        }
+    }
+
+    pub fn mine(&self) -> MineResult {
+        let start = Instant::now();
+        *self.start.lock().unwrap() = Some(start);
+        self.hash_count.store(0, Ordering::Relaxed);
+        let run = self.run.load(Ordering::Relaxed);
+        let found = AtomicBool::new(false);
+        let solution: Mutex<Option<Header>> = Mutex::new(None);
+
+        thread::scope(|scope| {
+            for thread_num in 0..self.thread_count {
+                let found = &found;
+                let solution = &solution;
+                scope.spawn(move || {
+                    let mut header = self.header.clone();
+                    let mut nonce = thread_num as u64;
+                    while nonce <= u32::MAX as u64 {
+                        if self.is_run_cancelled(run) || found.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        header.set_first_4_bytes_of_nonce(nonce as u32);
+                        if header.work_par_algo != Header::WORK_PAR_ALGO_NULL {
+                            header.work_par_hash = match header.get_work_par() {
+                                Ok(work_par) => work_par,
+                                Err(_) => return,
+                            };
+                        }
+                        self.hash_count.fetch_add(1, Ordering::Relaxed);
+                        if header.is_id_valid() {
+                            if !found.swap(true, Ordering::Relaxed) {
+                                *solution.lock().unwrap() = Some(header);
+                            }
+                            return;
+                        }
+                        nonce += self.thread_count as u64;
+                    }
+                });
+            }
+        });
+        self.run.fetch_add(1, Ordering::Relaxed);
+
+        MineResult {
+            header: solution.into_inner().unwrap(),
+            hash_count: self.hash_count(),
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network_params::NetworkParams;
+    use crate::numbers::u256;
+    use std::sync::Arc;
+
+    fn header(target: u256) -> Header {
+        let mut header = Header::from_genesis(0, &NetworkParams::MAINNET);
+        header.target = target;
+        header.work_par_algo = Header::WORK_PAR_ALGO_POW5;
+        header
+    }
+
+    #[test]
+    fn test_mine() {
+        // about 1 in 64 ids are valid
+        let miner = Miner::new(header(u256::MAX >> 6), 4);
+        let result = miner.mine();
+        let solved = result.header.clone().unwrap();
+        assert!(solved.is_work_valid(&NetworkParams::MAINNET));
+        assert!(solved.is_id_valid());
+        assert_eq!(solved.nonce & ((u256::ONE << 224) - u256::ONE), u256::ZERO);
+        assert!(result.hash_count > 0);
+        assert_eq!(result.hash_count, miner.hash_count());
+        assert!(result.hashrate() > 0.0);
+    }
+
+    #[test]
+    fn test_mine_null_work_par_algo() {
+        let mut header = header(u256::MAX >> 4);
+        header.work_par_algo = Header::WORK_PAR_ALGO_NULL;
+        let solved = Miner::new(header, 2).mine().header.unwrap();
+        assert_eq!(solved.work_par_hash, [0; 32]);
+        assert!(solved.is_work_valid(&NetworkParams::REGTEST));
+        assert!(solved.is_id_valid());
+    }
+
+    #[test]
+    fn test_cancel() {
+        // a target of zero can never be met
+        let miner = Arc::new(Miner::new(header(u256::ZERO), 2));
+        let canceller = {
+            let miner = miner.clone();
+            thread::spawn(move || {
+                while miner.hash_count() < 10 {
+                    thread::yield_now();
+                }
+                assert!(miner.hashrate() > 0.0);
+                miner.cancel();
+            })
+        };
+        let result = miner.mine();
+        canceller.join().unwrap();
+        assert!(result.header.is_none());
+        assert!(result.hash_count >= 10);
+    }
+
+    #[test]
+    fn test_cancel_before_mine() {
+        let miner = Miner::new(header(u256::MAX >> 2), 2);
+        miner.cancel();
+        assert!(miner.is_cancelled());
+        // the cancel is not lost when it comes before the run starts
+        let result = miner.mine();
+        assert!(result.header.is_none());
+        assert_eq!(result.hash_count, 0);
+        // and it only cancels that run
+        assert!(!miner.is_cancelled());
+        let result = miner.mine();
+        assert!(result.header.unwrap().is_id_valid());
+    }
+}
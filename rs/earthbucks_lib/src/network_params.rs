@@ -0,0 +1,115 @@
+use crate::numbers::u256;
+use crate::script_interpreter::ScriptLimits;
+
+// consensus parameters that differ between networks. mainnet and testnet use
+// the same rules. regtest is for local testing and ci: 1 second blocks, the
+// easiest possible target that never changes, no parallel work, and short
+// lock times.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkParams {
+    pub name: &'static str,
+    pub magic: [u8; 4],      // the first bytes of every p2p message
+    pub block_interval: u64, // milliseconds
+    pub blocks_per_target_adj_period: u32,
+    pub max_target: u256,
+    pub pow_no_retargeting: bool,
+    pub allow_null_work_par: bool, // headers may skip the parallel work
+    pub median_time_past_len: u32,
+    pub max_future_drift: u64, // milliseconds
+    pub halving_interval: u32,
+    pub initial_coinbase_amount: u64, // adams
+    pub pkhx_90d_lock_rel: u32,
+    pub pkhxr_90d_60d_x_lock_rel: u32,
+    pub pkhxr_90d_60d_r_lock_rel: u32,
+    pub pkhx_1h_lock_rel: u32,
+    pub pkhxr_1h_40m_x_lock_rel: u32,
+    pub pkhxr_1h_40m_r_lock_rel: u32,
+    pub script_limits: ScriptLimits,
+}
+
+impl NetworkParams {
+    pub const MAINNET: NetworkParams = NetworkParams {
+        name: "mainnet",
+        magic: *b"ebxm",
+        // 600_000 milliseconds = 600 seconds = 10 minutes
+        block_interval: 600_000,
+        // exactly two weeks if block interval is 10 minutes
+        blocks_per_target_adj_period: 2016,
+        max_target: u256::MAX,
+        pow_no_retargeting: false,
+        allow_null_work_par: false,
+        // a new header's timestamp must be after the median of the last 11,
+        // and at most 2 hours ahead of our clock
+        median_time_past_len: 11,
+        max_future_drift: 7_200_000,
+        halving_interval: 210_000,
+        // 100 earthbucks, where 100_000_000_000 adams = 1 earthbuck
+        initial_coinbase_amount: 100 * 100_000_000_000,
+        // 90 days = 12960 blocks of 10 minutes
+        pkhx_90d_lock_rel: 12960,
+        pkhxr_90d_60d_x_lock_rel: 12960,
+        // 60 days = 8640 blocks of 10 minutes
+        pkhxr_90d_60d_r_lock_rel: 8640,
+        // 1 hour = 6 blocks of 10 minutes
+        pkhx_1h_lock_rel: 6,
+        pkhxr_1h_40m_x_lock_rel: 6,
+        // 40 minutes = 4 blocks of 10 minutes
+        pkhxr_1h_40m_r_lock_rel: 4,
+        script_limits: ScriptLimits::DEFAULT,
+    };
+
+    pub const TESTNET: NetworkParams = NetworkParams {
+        name: "testnet",
+        magic: *b"ebxt",
+        ..NetworkParams::MAINNET
+    };
+
+    pub const REGTEST: NetworkParams = NetworkParams {
+        name: "regtest",
+        magic: *b"ebxr",
+        block_interval: 1_000,
+        max_target: u256::MAX,
+        pow_no_retargeting: true,
+        allow_null_work_par: true,
+        halving_interval: 150,
+        pkhx_90d_lock_rel: 30,
+        pkhxr_90d_60d_x_lock_rel: 30,
+        pkhxr_90d_60d_r_lock_rel: 20,
+        ..NetworkParams::MAINNET
+    };
+
+    pub fn from_name(name: &str) -> Option<NetworkParams> {
+        match name {
+            "mainnet" => Some(NetworkParams::MAINNET),
+            "testnet" => Some(NetworkParams::TESTNET),
+            "regtest" => Some(NetworkParams::REGTEST),
+            _ => None,
+        }
+    }
+}
+
+impl Default for NetworkParams {
+    fn default() -> Self {
+        NetworkParams::MAINNET
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            NetworkParams::from_name("mainnet"),
+            Some(NetworkParams::MAINNET)
+        );
+        assert_eq!(
+            NetworkParams::from_name("regtest"),
+            Some(NetworkParams::REGTEST)
+        );
+        assert_eq!(NetworkParams::from_name("testnet").unwrap().name, "testnet");
+        assert_eq!(NetworkParams::from_name("foo"), None);
+        assert_eq!(NetworkParams::default(), NetworkParams::MAINNET);
+    }
+}
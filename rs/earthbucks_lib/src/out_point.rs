@@ -0,0 +1,81 @@
+use crate::buf::EbxBuf;
+use crate::buf_reader::BufReader;
+use crate::buf_writer::BufWriter;
+use crate::error::EbxError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct OutPoint {
+    pub tx_id: [u8; 32],
+    pub tx_out_num: u32,
+}
+
+impl OutPoint {
+    pub const SIZE: usize = 32 + 4;
+
+    pub fn new(tx_id: [u8; 32], tx_out_num: u32) -> Self {
+        Self { tx_id, tx_out_num }
+    }
+
+    pub fn to_buf(&self) -> [u8; OutPoint::SIZE] {
+        let mut bw = BufWriter::new();
+        bw.write(self.tx_id.to_vec());
+        bw.write_u32_be(self.tx_out_num);
+        bw.to_buf().try_into().unwrap()
+    }
+
+    pub fn from_buf(buf: [u8; OutPoint::SIZE]) -> Result<Self, EbxError> {
+        let mut br = BufReader::new(buf.to_vec());
+        Self::from_buf_reader(&mut br)
+    }
+
+    pub fn from_buf_reader(br: &mut BufReader) -> Result<Self, EbxError> {
+        let tx_id: [u8; 32] = br
+            .read_field("tx_id", |br| br.read(32))?
+            .try_into()
+            .unwrap();
+        let tx_out_num = br.read_field("tx_out_num", BufReader::read_u32_be)?;
+        Ok(Self::new(tx_id, tx_out_num))
+    }
+
+    pub fn to_strict_str(&self) -> String {
+        format!("{}:{}", self.tx_id.to_strict_hex(), self.tx_out_num)
+    }
+
+    pub fn from_strict_str(s: &str) -> Result<Self, EbxError> {
+        let (tx_id_hex, tx_out_num_str) = s
+            .split_once(':')
+            .ok_or(EbxError::InvalidEncodingError { source: None })?;
+        let tx_id = <[u8; 32]>::from_strict_hex(tx_id_hex)?;
+        let tx_out_num = tx_out_num_str
+            .parse()
+            .map_err(|_| EbxError::InvalidEncodingError { source: None })?;
+        Ok(Self::new(tx_id, tx_out_num))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_buf_and_from_buf() {
+        let out_point = OutPoint::new([1; 32], 5);
+        let buf = out_point.to_buf();
+        assert_eq!(buf.len(), OutPoint::SIZE);
+        assert_eq!(&buf[32..], &[0, 0, 0, 5]);
+        assert_eq!(OutPoint::from_buf(buf).unwrap(), out_point);
+    }
+
+    #[test]
+    fn test_to_strict_str_and_from_strict_str() {
+        let out_point = OutPoint::new([1; 32], 5);
+        let s = out_point.to_strict_str();
+        assert_eq!(
+            s,
+            "0101010101010101010101010101010101010101010101010101010101010101:5"
+        );
+        assert_eq!(OutPoint::from_strict_str(&s).unwrap(), out_point);
+        assert!(OutPoint::from_strict_str("0101").is_err());
+        assert!(OutPoint::from_strict_str("0101:x").is_err());
+    }
+}
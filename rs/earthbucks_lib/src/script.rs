@@ -1,9 +1,10 @@
 use crate::buf_reader::BufReader;
 use crate::error::EbxError;
+use crate::network_params::NetworkParams;
 use crate::opcode::Opcode;
 use crate::pub_key::PubKey;
 use crate::script_chunk::ScriptChunk;
-use crate::script_num::ScriptNum;
+use crate::script_template::{ScriptTemplate, ScriptTemplateValue};
 use crate::tx_signature::TxSignature;
 
 #[derive(PartialEq, Debug, Clone, Default)]
@@ -88,24 +89,13 @@ impl Script {
     }
 
     pub fn from_pkh_output(pkh: &[u8; 32]) -> Self {
-        Self::new(vec![
-            ScriptChunk::new(Opcode::OP_DUP, None),
-            ScriptChunk::new(Opcode::OP_DOUBLEBLAKE3, None),
-            ScriptChunk::from_data(pkh.to_vec()),
-            ScriptChunk::new(Opcode::OP_EQUALVERIFY, None),
-            ScriptChunk::new(Opcode::OP_CHECKSIG, None),
-        ])
+        ScriptTemplate::PKH_OUTPUT
+            .build(&[ScriptTemplateValue::Pkh(*pkh)])
+            .unwrap()
     }
 
     pub fn is_pkh_output(&self) -> bool {
-        self.chunks.len() == 5
-            && self.chunks[0].opcode == Opcode::OP_DUP
-            && self.chunks[1].opcode == Opcode::OP_DOUBLEBLAKE3
-            && self.chunks[2].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[2].buffer.is_some()
-            && self.chunks[2].buffer.as_ref().unwrap().len() == 32
-            && self.chunks[3].opcode == Opcode::OP_EQUALVERIFY
-            && self.chunks[4].opcode == Opcode::OP_CHECKSIG
+        ScriptTemplate::PKH_OUTPUT.is_match(self)
     }
 
     pub fn from_pkh_input(signature: &[u8], pub_key: &[u8]) -> Self {
@@ -118,13 +108,7 @@ impl Script {
     }
 
     pub fn is_pkh_input(&self) -> bool {
-        self.chunks.len() == 2
-            && self.chunks[0].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[0].buffer.is_some()
-            && self.chunks[0].buffer.as_ref().unwrap().len() == TxSignature::SIZE
-            && self.chunks[1].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[1].buffer.is_some()
-            && self.chunks[1].buffer.as_ref().unwrap().len() == PubKey::SIZE
+        ScriptTemplate::PKH_INPUT.is_match(self)
     }
 
     pub fn from_pkh_input_placeholder() -> Self {
@@ -133,292 +117,170 @@ impl Script {
         Self::from_pkh_input(&sig_buf, &pub_key)
     }
 
+    // the lock rel of a pkhx output, which depends on the network and expiry
+    fn pkhx_lock_rel(&self) -> Option<u32> {
+        match ScriptTemplate::PKHX_OUTPUT.match_script(self)?[..] {
+            [_, ScriptTemplateValue::LockNum(lock_rel)] => Some(lock_rel),
+            _ => None,
+        }
+    }
+
+    // the recovery and expiry lock rels of a pkhxr output
+    fn pkhxr_lock_rels(&self) -> Option<(u32, u32)> {
+        use ScriptTemplateValue::LockNum;
+        match ScriptTemplate::PKHXR_OUTPUT.match_script(self)?[..] {
+            [_, LockNum(r_lock_rel), _, LockNum(x_lock_rel)] => Some((r_lock_rel, x_lock_rel)),
+            _ => None,
+        }
+    }
+
     // PKHX 90D = PubKey Hash with Expiry: 90 Days
-    // 13104 blocks = 2016 blocks / 14 * 90
-    pub const PKHX_90D_LOCK_REL: u32 = 12960;
+    // 12960 blocks on mainnet, see NetworkParams
+    pub fn from_pkhx_90d_output(pkh: &[u8; 32], params: &NetworkParams) -> Self {
+        let values = [
+            ScriptTemplateValue::Pkh(*pkh),
+            ScriptTemplateValue::LockNum(params.pkhx_90d_lock_rel),
+        ];
+        ScriptTemplate::PKHX_OUTPUT.build(&values).unwrap()
+    }
 
-    pub fn from_pkhx_90d_output(pkh: &[u8; 32]) -> Self {
-        Self::new(vec![
-            ScriptChunk::new(Opcode::OP_IF, None),
-            ScriptChunk::new(Opcode::OP_DUP, None),
-            ScriptChunk::new(Opcode::OP_DOUBLEBLAKE3, None),
-            ScriptChunk::from_data(pkh.to_vec()),
-            ScriptChunk::new(Opcode::OP_EQUALVERIFY, None),
-            ScriptChunk::new(Opcode::OP_CHECKSIG, None),
-            ScriptChunk::new(Opcode::OP_ELSE, None),
-            ScriptChunk::from_data(ScriptNum::from_u32(Script::PKHX_90D_LOCK_REL).to_buf()),
-            ScriptChunk::new(Opcode::OP_CHECKLOCKRELVERIFY, None),
-            ScriptChunk::new(Opcode::OP_DROP, None),
-            ScriptChunk::new(Opcode::OP_1, None),
-            ScriptChunk::new(Opcode::OP_ENDIF, None),
-        ])
-    }
-
-    pub fn is_pkhx_90d_output(&self) -> bool {
-        self.chunks.len() == 12
-            && self.chunks[0].opcode == Opcode::OP_IF
-            && self.chunks[1].opcode == Opcode::OP_DUP
-            && self.chunks[2].opcode == Opcode::OP_DOUBLEBLAKE3
-            && self.chunks[3].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[3].buffer.is_some()
-            && self.chunks[3].buffer.as_ref().unwrap().len() == 32
-            && self.chunks[4].opcode == Opcode::OP_EQUALVERIFY
-            && self.chunks[5].opcode == Opcode::OP_CHECKSIG
-            && self.chunks[6].opcode == Opcode::OP_ELSE
-            && self.chunks[7].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[7].buffer.is_some()
-            && self.chunks[7].buffer.as_ref().unwrap().len() == 2
-            && u16::from_be_bytes([
-                self.chunks[7].buffer.as_ref().unwrap()[0],
-                self.chunks[7].buffer.as_ref().unwrap()[1],
-            ]) == Script::PKHX_90D_LOCK_REL as u16
-            && self.chunks[8].opcode == Opcode::OP_CHECKLOCKRELVERIFY
-            && self.chunks[9].opcode == Opcode::OP_DROP
-            && self.chunks[10].opcode == Opcode::OP_1
-            && self.chunks[11].opcode == Opcode::OP_ENDIF
-    }
-
-    pub fn is_pkhx_90d_expired(new_block_num: u32, prev_block_num: u32) -> bool {
-        new_block_num >= prev_block_num + Script::PKHX_90D_LOCK_REL
+    pub fn is_pkhx_90d_output(&self, params: &NetworkParams) -> bool {
+        self.pkhx_lock_rel() == Some(params.pkhx_90d_lock_rel)
+    }
+
+    pub fn is_pkhx_90d_expired(
+        new_block_num: u32,
+        prev_block_num: u32,
+        params: &NetworkParams,
+    ) -> bool {
+        new_block_num >= prev_block_num + params.pkhx_90d_lock_rel
     }
 
     // PKHXR 90D 60D = PubKey Hash with Expiry: 90 Days
     // And recovery: 60 Days
-    // 13104 blocks = 2016 blocks / 14 * 90
-    pub const PKHXR_90D_60D_X_LOCK_REL: u32 = 12960;
-    pub const PKHXR_90D_60D_R_LOCK_REL: u32 = 8640;
-
-    pub fn from_pkhxr_90d_60d_output(pkh: &[u8; 32], rpkh: &[u8; 32]) -> Self {
-        Self::new(vec![
-            // if simple pkh
-            ScriptChunk::new(Opcode::OP_IF, None),
-            ScriptChunk::new(Opcode::OP_DUP, None),
-            ScriptChunk::new(Opcode::OP_DOUBLEBLAKE3, None),
-            ScriptChunk::from_data(pkh.to_vec()),
-            ScriptChunk::new(Opcode::OP_EQUALVERIFY, None),
-            ScriptChunk::new(Opcode::OP_CHECKSIG, None),
-            // else
-            ScriptChunk::new(Opcode::OP_ELSE, None),
-            // if recovery pkh
-            ScriptChunk::new(Opcode::OP_IF, None),
-            ScriptChunk::from_data(ScriptNum::from_u32(Script::PKHXR_90D_60D_R_LOCK_REL).to_buf()),
-            ScriptChunk::new(Opcode::OP_CHECKLOCKRELVERIFY, None),
-            ScriptChunk::new(Opcode::OP_DROP, None),
-            ScriptChunk::new(Opcode::OP_DUP, None),
-            ScriptChunk::new(Opcode::OP_DOUBLEBLAKE3, None),
-            ScriptChunk::from_data(rpkh.to_vec()),
-            ScriptChunk::new(Opcode::OP_EQUALVERIFY, None),
-            ScriptChunk::new(Opcode::OP_CHECKSIG, None),
-            // else expiry
-            ScriptChunk::new(Opcode::OP_ELSE, None),
-            ScriptChunk::from_data(ScriptNum::from_u32(Script::PKHXR_90D_60D_X_LOCK_REL).to_buf()),
-            ScriptChunk::new(Opcode::OP_CHECKLOCKRELVERIFY, None),
-            ScriptChunk::new(Opcode::OP_DROP, None),
-            ScriptChunk::new(Opcode::OP_1, None),
-            ScriptChunk::new(Opcode::OP_ENDIF, None),
-            ScriptChunk::new(Opcode::OP_ENDIF, None),
-        ])
-    }
-
-    pub fn is_pkhxr_90d_60d_output(&self) -> bool {
-        self.chunks.len() == 23
-            && self.chunks[0].opcode == Opcode::OP_IF
-            && self.chunks[1].opcode == Opcode::OP_DUP
-            && self.chunks[2].opcode == Opcode::OP_DOUBLEBLAKE3
-            && self.chunks[3].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[3].buffer.is_some()
-            && self.chunks[3].buffer.as_ref().unwrap().len() == 32
-            && self.chunks[4].opcode == Opcode::OP_EQUALVERIFY
-            && self.chunks[5].opcode == Opcode::OP_CHECKSIG
-            && self.chunks[6].opcode == Opcode::OP_ELSE
-            && self.chunks[7].opcode == Opcode::OP_IF
-            && self.chunks[8].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[8].buffer.is_some()
-            && self.chunks[8].buffer.as_ref().unwrap().len() == 2
-            && u16::from_be_bytes([
-                self.chunks[8].buffer.as_ref().unwrap()[0],
-                self.chunks[8].buffer.as_ref().unwrap()[1],
-            ]) == Script::PKHXR_90D_60D_R_LOCK_REL as u16
-            && self.chunks[9].opcode == Opcode::OP_CHECKLOCKRELVERIFY
-            && self.chunks[10].opcode == Opcode::OP_DROP
-            && self.chunks[11].opcode == Opcode::OP_DUP
-            && self.chunks[12].opcode == Opcode::OP_DOUBLEBLAKE3
-            && self.chunks[13].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[13].buffer.is_some()
-            && self.chunks[13].buffer.as_ref().unwrap().len() == 32
-            && self.chunks[14].opcode == Opcode::OP_EQUALVERIFY
-            && self.chunks[15].opcode == Opcode::OP_CHECKSIG
-            && self.chunks[16].opcode == Opcode::OP_ELSE
-            && self.chunks[17].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[17].buffer.is_some()
-            && self.chunks[17].buffer.as_ref().unwrap().len() == 2
-            && u16::from_be_bytes([
-                self.chunks[17].buffer.as_ref().unwrap()[0],
-                self.chunks[17].buffer.as_ref().unwrap()[1],
-            ]) == Script::PKHXR_90D_60D_X_LOCK_REL as u16
-            && self.chunks[18].opcode == Opcode::OP_CHECKLOCKRELVERIFY
-            && self.chunks[19].opcode == Opcode::OP_DROP
-            && self.chunks[20].opcode == Opcode::OP_1
-            && self.chunks[21].opcode == Opcode::OP_ENDIF
-            && self.chunks[21].opcode == Opcode::OP_ENDIF
-    }
-
-    pub fn is_pkhxr_90d_60d_expired(new_block_num: u32, prev_block_num: u32) -> bool {
-        new_block_num >= prev_block_num + Script::PKHXR_90D_60D_X_LOCK_REL
-    }
-
-    pub fn is_pkhxr_90d_60d_recoverable(new_block_num: u32, prev_block_num: u32) -> bool {
-        new_block_num >= prev_block_num + Script::PKHXR_90D_60D_R_LOCK_REL
+    // 12960 and 8640 blocks on mainnet, see NetworkParams
+    pub fn from_pkhxr_90d_60d_output(
+        pkh: &[u8; 32],
+        rpkh: &[u8; 32],
+        params: &NetworkParams,
+    ) -> Self {
+        let values = [
+            ScriptTemplateValue::Pkh(*pkh),
+            ScriptTemplateValue::LockNum(params.pkhxr_90d_60d_r_lock_rel),
+            ScriptTemplateValue::Pkh(*rpkh),
+            ScriptTemplateValue::LockNum(params.pkhxr_90d_60d_x_lock_rel),
+        ];
+        ScriptTemplate::PKHXR_OUTPUT.build(&values).unwrap()
+    }
+
+    pub fn is_pkhxr_90d_60d_output(&self, params: &NetworkParams) -> bool {
+        self.pkhxr_lock_rels()
+            == Some((
+                params.pkhxr_90d_60d_r_lock_rel,
+                params.pkhxr_90d_60d_x_lock_rel,
+            ))
+    }
+
+    pub fn is_pkhxr_90d_60d_expired(
+        new_block_num: u32,
+        prev_block_num: u32,
+        params: &NetworkParams,
+    ) -> bool {
+        new_block_num >= prev_block_num + params.pkhxr_90d_60d_x_lock_rel
+    }
+
+    pub fn is_pkhxr_90d_60d_recoverable(
+        new_block_num: u32,
+        prev_block_num: u32,
+        params: &NetworkParams,
+    ) -> bool {
+        new_block_num >= prev_block_num + params.pkhxr_90d_60d_r_lock_rel
     }
 
     // PKHX 1H = PubKey Hash Expiry: 1 Hour
-    // 6 blocks = 1 hour for 10 min blocks
-    pub const PKHX_1H_LOCK_REL: u32 = 6;
+    // 6 blocks = 1 hour for 10 min blocks on mainnet, see NetworkParams
+    pub fn from_pkhx_1h_output(pkh: &[u8; 32], params: &NetworkParams) -> Self {
+        let values = [
+            ScriptTemplateValue::Pkh(*pkh),
+            ScriptTemplateValue::LockNum(params.pkhx_1h_lock_rel),
+        ];
+        ScriptTemplate::PKHX_OUTPUT.build(&values).unwrap()
+    }
 
-    pub fn from_pkhx_1h_output(pkh: &[u8; 32]) -> Self {
-        Self::new(vec![
-            ScriptChunk::new(Opcode::OP_IF, None),
-            ScriptChunk::new(Opcode::OP_DUP, None),
-            ScriptChunk::new(Opcode::OP_DOUBLEBLAKE3, None),
-            ScriptChunk::from_data(pkh.to_vec()),
-            ScriptChunk::new(Opcode::OP_EQUALVERIFY, None),
-            ScriptChunk::new(Opcode::OP_CHECKSIG, None),
-            ScriptChunk::new(Opcode::OP_ELSE, None),
-            ScriptChunk::new(Opcode::OP_6, None),
-            ScriptChunk::new(Opcode::OP_CHECKLOCKRELVERIFY, None),
-            ScriptChunk::new(Opcode::OP_DROP, None),
-            ScriptChunk::new(Opcode::OP_1, None),
-            ScriptChunk::new(Opcode::OP_ENDIF, None),
-        ])
-    }
-
-    pub fn is_pkhx_1h_output(&self) -> bool {
-        self.chunks.len() == 12
-            && self.chunks[0].opcode == Opcode::OP_IF
-            && self.chunks[1].opcode == Opcode::OP_DUP
-            && self.chunks[2].opcode == Opcode::OP_DOUBLEBLAKE3
-            && self.chunks[3].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[3].buffer.is_some()
-            && self.chunks[3].buffer.as_ref().unwrap().len() == 32
-            && self.chunks[4].opcode == Opcode::OP_EQUALVERIFY
-            && self.chunks[5].opcode == Opcode::OP_CHECKSIG
-            && self.chunks[6].opcode == Opcode::OP_ELSE
-            && self.chunks[7].opcode == Opcode::OP_6 // lock_rel
-            && self.chunks[8].opcode == Opcode::OP_CHECKLOCKRELVERIFY
-            && self.chunks[9].opcode == Opcode::OP_DROP
-            && self.chunks[10].opcode == Opcode::OP_1
-            && self.chunks[11].opcode == Opcode::OP_ENDIF
-    }
-
-    pub fn is_pkhx_1h_expired(new_block_num: u32, prev_block_num: u32) -> bool {
-        new_block_num >= prev_block_num + Script::PKHX_1H_LOCK_REL
+    pub fn is_pkhx_1h_output(&self, params: &NetworkParams) -> bool {
+        self.pkhx_lock_rel() == Some(params.pkhx_1h_lock_rel)
+    }
+
+    pub fn is_pkhx_1h_expired(
+        new_block_num: u32,
+        prev_block_num: u32,
+        params: &NetworkParams,
+    ) -> bool {
+        new_block_num >= prev_block_num + params.pkhx_1h_lock_rel
     }
 
     // PKHXR 1h 40m = PubKey Hash with Expiry: 1 Hour
     // and Recovery: 40 Minutes
-    // 6 blocks = 1 hour for 10 min blocks
-    pub const PKHXR_1H_40M_X_LOCK_REL: u32 = 6;
-    pub const PKHXR_1H_40M_R_LOCK_REL: u32 = 4;
-
-    pub fn from_pkhxr_1h_40m_output(pkh: &[u8; 32], rpkh: &[u8; 32]) -> Self {
-        Self::new(vec![
-            // if simple pkh
-            ScriptChunk::new(Opcode::OP_IF, None),
-            ScriptChunk::new(Opcode::OP_DUP, None),
-            ScriptChunk::new(Opcode::OP_DOUBLEBLAKE3, None),
-            ScriptChunk::from_data(pkh.to_vec()),
-            ScriptChunk::new(Opcode::OP_EQUALVERIFY, None),
-            ScriptChunk::new(Opcode::OP_CHECKSIG, None),
-            // else
-            ScriptChunk::new(Opcode::OP_ELSE, None),
-            // if recovery pkh
-            ScriptChunk::new(Opcode::OP_IF, None),
-            ScriptChunk::new(Opcode::OP_4, None),
-            ScriptChunk::new(Opcode::OP_CHECKLOCKRELVERIFY, None),
-            ScriptChunk::new(Opcode::OP_DROP, None),
-            ScriptChunk::new(Opcode::OP_DUP, None),
-            ScriptChunk::new(Opcode::OP_DOUBLEBLAKE3, None),
-            ScriptChunk::from_data(rpkh.to_vec()),
-            ScriptChunk::new(Opcode::OP_EQUALVERIFY, None),
-            ScriptChunk::new(Opcode::OP_CHECKSIG, None),
-            // else expiry
-            ScriptChunk::new(Opcode::OP_ELSE, None),
-            ScriptChunk::new(Opcode::OP_6, None),
-            ScriptChunk::new(Opcode::OP_CHECKLOCKRELVERIFY, None),
-            ScriptChunk::new(Opcode::OP_DROP, None),
-            ScriptChunk::new(Opcode::OP_1, None),
-            ScriptChunk::new(Opcode::OP_ENDIF, None),
-            ScriptChunk::new(Opcode::OP_ENDIF, None),
-        ])
-    }
-
-    pub fn is_pkhxr_1h_40m_output(&self) -> bool {
-        self.chunks.len() == 23
-            && self.chunks[0].opcode == Opcode::OP_IF
-            && self.chunks[1].opcode == Opcode::OP_DUP
-            && self.chunks[2].opcode == Opcode::OP_DOUBLEBLAKE3
-            && self.chunks[3].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[3].buffer.is_some()
-            && self.chunks[3].buffer.as_ref().unwrap().len() == 32
-            && self.chunks[4].opcode == Opcode::OP_EQUALVERIFY
-            && self.chunks[5].opcode == Opcode::OP_CHECKSIG
-            && self.chunks[6].opcode == Opcode::OP_ELSE
-            && self.chunks[7].opcode == Opcode::OP_IF
-            && self.chunks[8].opcode == Opcode::OP_4
-            && self.chunks[9].opcode == Opcode::OP_CHECKLOCKRELVERIFY
-            && self.chunks[10].opcode == Opcode::OP_DROP
-            && self.chunks[11].opcode == Opcode::OP_DUP
-            && self.chunks[12].opcode == Opcode::OP_DOUBLEBLAKE3
-            && self.chunks[13].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[13].buffer.is_some()
-            && self.chunks[13].buffer.as_ref().unwrap().len() == 32
-            && self.chunks[14].opcode == Opcode::OP_EQUALVERIFY
-            && self.chunks[15].opcode == Opcode::OP_CHECKSIG
-            && self.chunks[16].opcode == Opcode::OP_ELSE
-            && self.chunks[17].opcode == Opcode::OP_6
-            && self.chunks[18].opcode == Opcode::OP_CHECKLOCKRELVERIFY
-            && self.chunks[19].opcode == Opcode::OP_DROP
-            && self.chunks[20].opcode == Opcode::OP_1
-            && self.chunks[21].opcode == Opcode::OP_ENDIF
-            && self.chunks[21].opcode == Opcode::OP_ENDIF
-    }
-
-    pub fn is_pkhxr_1h_40m_expired(new_block_num: u32, prev_block_num: u32) -> bool {
-        new_block_num >= prev_block_num + Script::PKHXR_1H_40M_X_LOCK_REL
-    }
-
-    pub fn is_pkhxr_1h_40m_recoverable(new_block_num: u32, prev_block_num: u32) -> bool {
-        new_block_num >= prev_block_num + Script::PKHXR_1H_40M_R_LOCK_REL
+    // 6 and 4 blocks for 10 min blocks on mainnet, see NetworkParams
+    pub fn from_pkhxr_1h_40m_output(
+        pkh: &[u8; 32],
+        rpkh: &[u8; 32],
+        params: &NetworkParams,
+    ) -> Self {
+        let values = [
+            ScriptTemplateValue::Pkh(*pkh),
+            ScriptTemplateValue::LockNum(params.pkhxr_1h_40m_r_lock_rel),
+            ScriptTemplateValue::Pkh(*rpkh),
+            ScriptTemplateValue::LockNum(params.pkhxr_1h_40m_x_lock_rel),
+        ];
+        ScriptTemplate::PKHXR_OUTPUT.build(&values).unwrap()
+    }
+
+    pub fn is_pkhxr_1h_40m_output(&self, params: &NetworkParams) -> bool {
+        self.pkhxr_lock_rels()
+            == Some((
+                params.pkhxr_1h_40m_r_lock_rel,
+                params.pkhxr_1h_40m_x_lock_rel,
+            ))
+    }
+
+    pub fn is_pkhxr_1h_40m_expired(
+        new_block_num: u32,
+        prev_block_num: u32,
+        params: &NetworkParams,
+    ) -> bool {
+        new_block_num >= prev_block_num + params.pkhxr_1h_40m_x_lock_rel
+    }
+
+    pub fn is_pkhxr_1h_40m_recoverable(
+        new_block_num: u32,
+        prev_block_num: u32,
+        params: &NetworkParams,
+    ) -> bool {
+        new_block_num >= prev_block_num + params.pkhxr_1h_40m_r_lock_rel
     }
 
     pub fn from_expired_pkhx_input() -> Self {
-        Self::new(vec![ScriptChunk::new(Opcode::OP_0, None)])
+        ScriptTemplate::EXPIRED_PKHX_INPUT.build(&[]).unwrap()
     }
 
     pub fn is_expired_pkhx_input(&self) -> bool {
-        self.chunks.len() == 1 && self.chunks[0].opcode == Opcode::OP_0
+        ScriptTemplate::EXPIRED_PKHX_INPUT.is_match(self)
     }
 
     pub fn from_unexpired_pkhx_input(
         sig_buf: &[u8; TxSignature::SIZE],
         pub_key_buf: &[u8; PubKey::SIZE],
     ) -> Self {
-        Self::new(vec![
-            ScriptChunk::from_data(sig_buf.to_vec()),
-            ScriptChunk::from_data(pub_key_buf.to_vec()),
-            ScriptChunk::new(Opcode::OP_1, None),
-        ])
+        let values = [
+            ScriptTemplateValue::Sig(*sig_buf),
+            ScriptTemplateValue::PubKey(*pub_key_buf),
+        ];
+        ScriptTemplate::UNEXPIRED_PKHX_INPUT.build(&values).unwrap()
     }
 
     pub fn is_unexpired_pkhx_input(&self) -> bool {
-        self.chunks.len() == 3
-            && self.chunks[0].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[0].buffer.is_some()
-            && self.chunks[0].buffer.as_ref().unwrap().len() == TxSignature::SIZE
-            && self.chunks[1].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[1].buffer.is_some()
-            && self.chunks[1].buffer.as_ref().unwrap().len() == PubKey::SIZE
-            && self.chunks[2].opcode == Opcode::OP_1
+        ScriptTemplate::UNEXPIRED_PKHX_INPUT.is_match(self)
     }
 
     pub fn from_unexpired_pkhx_input_placeholder() -> Self {
@@ -431,28 +293,22 @@ impl Script {
     }
 
     pub fn from_expired_pkhxr_input() -> Self {
-        Self::new(vec![
-            ScriptChunk::new(Opcode::OP_0, None),
-            ScriptChunk::new(Opcode::OP_0, None),
-        ])
+        ScriptTemplate::EXPIRED_PKHXR_INPUT.build(&[]).unwrap()
     }
 
     pub fn is_expired_pkhxr_input(&self) -> bool {
-        self.chunks.len() == 2
-            && self.chunks[0].opcode == Opcode::OP_0
-            && self.chunks[1].opcode == Opcode::OP_0
+        ScriptTemplate::EXPIRED_PKHXR_INPUT.is_match(self)
     }
 
     pub fn from_recovery_pkhxr_input(
         sig_buf: &[u8; TxSignature::SIZE],
         pub_key_buf: &[u8; PubKey::SIZE],
     ) -> Self {
-        Self::new(vec![
-            ScriptChunk::from_data(sig_buf.to_vec()),
-            ScriptChunk::from_data(pub_key_buf.to_vec()),
-            ScriptChunk::new(Opcode::OP_1, None),
-            ScriptChunk::new(Opcode::OP_0, None),
-        ])
+        let values = [
+            ScriptTemplateValue::Sig(*sig_buf),
+            ScriptTemplateValue::PubKey(*pub_key_buf),
+        ];
+        ScriptTemplate::RECOVERY_PKHXR_INPUT.build(&values).unwrap()
     }
 
     pub fn from_recovery_pkhxr_input_placeholder() -> Self {
@@ -465,37 +321,24 @@ impl Script {
     }
 
     pub fn is_recovery_pkhxr_input(&self) -> bool {
-        self.chunks.len() == 4
-            && self.chunks[0].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[0].buffer.is_some()
-            && self.chunks[0].buffer.as_ref().unwrap().len() == TxSignature::SIZE
-            && self.chunks[1].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[1].buffer.is_some()
-            && self.chunks[1].buffer.as_ref().unwrap().len() == PubKey::SIZE
-            && self.chunks[2].opcode == Opcode::OP_1
-            && self.chunks[3].opcode == Opcode::OP_0
+        ScriptTemplate::RECOVERY_PKHXR_INPUT.is_match(self)
     }
 
     pub fn from_unexpired_pkhxr_input(
         sig_buf: &[u8; TxSignature::SIZE],
         pub_key_buf: &[u8; PubKey::SIZE],
     ) -> Self {
-        Self::new(vec![
-            ScriptChunk::from_data(sig_buf.to_vec()),
-            ScriptChunk::from_data(pub_key_buf.to_vec()),
-            ScriptChunk::new(Opcode::OP_1, None),
-        ])
+        let values = [
+            ScriptTemplateValue::Sig(*sig_buf),
+            ScriptTemplateValue::PubKey(*pub_key_buf),
+        ];
+        ScriptTemplate::UNEXPIRED_PKHXR_INPUT
+            .build(&values)
+            .unwrap()
     }
 
     pub fn is_unexpired_pkhxr_input(&self) -> bool {
-        self.chunks.len() == 3
-            && self.chunks[0].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[0].buffer.is_some()
-            && self.chunks[0].buffer.as_ref().unwrap().len() == TxSignature::SIZE
-            && self.chunks[1].opcode == Opcode::OP_PUSHDATA1
-            && self.chunks[1].buffer.is_some()
-            && self.chunks[1].buffer.as_ref().unwrap().len() == PubKey::SIZE
-            && self.chunks[2].opcode == Opcode::OP_1
+        ScriptTemplate::UNEXPIRED_PKHXR_INPUT.is_match(self)
     }
 
     pub fn from_unexpired_pkhxr_input_placeholder() -> Self {
@@ -525,8 +368,8 @@ impl Script {
         self.is_push_only() && (self.is_unexpired_pkhx_input() || self.is_expired_pkhx_input())
     }
 
-    pub fn is_standard_output(&self) -> bool {
-        self.is_pkhx_90d_output() || self.is_pkhx_1h_output()
+    pub fn is_standard_output(&self, params: &NetworkParams) -> bool {
+        self.is_pkhx_90d_output(params) || self.is_pkhx_1h_output(params)
     }
 }
 
@@ -535,6 +378,7 @@ mod tests {
     use super::*;
     use crate::buf::EbxBuf;
     use crate::script_chunk::ScriptChunk;
+    use crate::script_num::ScriptNum;
     use serde::Deserialize;
 
     #[test]
@@ -644,6 +488,32 @@ mod tests {
         assert!(!script.is_pkh_output());
     }
 
+    #[test]
+    fn test_pkhx_lock_rel_params() {
+        let pkh = [0; 32];
+        let mainnet = NetworkParams::MAINNET;
+        let regtest = NetworkParams::REGTEST;
+
+        let script = Script::from_pkhx_1h_output(&pkh, &mainnet);
+        assert_eq!(script.chunks[7], ScriptChunk::new(Opcode::OP_6, None));
+        assert!(script.is_pkhx_1h_output(&mainnet));
+
+        let script = Script::from_pkhx_90d_output(&pkh, &mainnet);
+        assert_eq!(
+            script.chunks[7],
+            ScriptChunk::from_data(ScriptNum::from_u32(12960).to_buf())
+        );
+        assert!(script.is_pkhx_90d_output(&mainnet));
+        assert!(!script.is_pkhx_90d_output(&regtest));
+
+        let script = Script::from_pkhxr_90d_60d_output(&pkh, &pkh, &regtest);
+        assert!(script.is_pkhxr_90d_60d_output(&regtest));
+        assert!(!script.is_pkhxr_90d_60d_output(&mainnet));
+        assert!(Script::is_pkhxr_90d_60d_expired(30, 0, &regtest));
+        assert!(!Script::is_pkhxr_90d_60d_expired(30, 0, &mainnet));
+        assert!(Script::is_pkhxr_90d_60d_recoverable(20, 0, &regtest));
+    }
+
     // standard test vectors
 
     #[derive(Deserialize)]
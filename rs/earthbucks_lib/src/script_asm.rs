@@ -0,0 +1,633 @@
+use crate::buf::EbxBuf;
+use crate::error::EbxError;
+use crate::network_params::NetworkParams;
+use crate::opcode::{Opcode, OP, OPCODE_TO_NAME};
+use crate::script::Script;
+use crate::script_chunk::ScriptChunk;
+use crate::script_num::ScriptNum;
+use lazy_static::lazy_static;
+use num_bigint::BigInt;
+use std::collections::HashMap;
+
+// a richer assembly language than Script::from_strict_str. one or more items
+// per line, separated by whitespace:
+//
+//   DUP CHECKSIG     opcodes, by the same names as from_strict_str
+//   0x00ff           hex data
+//   12960 -1         decimal numbers, encoded with ScriptNum
+//   "hello world"    utf-8 strings, with \" and \\ escapes
+//   $name            a named constant or a macro parameter
+//   name arg ...     a macro, followed by one value per parameter
+//   # comment        to the end of the line
+//
+// all data is pushed with the minimal push, like ScriptChunk::from_data, so
+// 5 and 0x05 both assemble to the opcode 5. PUSHDATA1 0x05 pushes a value
+// with an explicit push opcode instead. constants and macros can also be
+// defined in the source, and are then local to that source:
+//
+//   .const name value
+//   .macro name param ...
+//     body
+//   .endm
+//
+// there are no labels, as scripts have no jumps. the only control flow is
+// IF/NOTIF/ELSE/ENDIF, which nest and need no targets.
+lazy_static! {
+    static ref PUSH_OPCODES: HashMap<&'static str, u8> = HashMap::from([
+        ("PUSHDATA1", Opcode::OP_PUSHDATA1),
+        ("PUSHDATA2", Opcode::OP_PUSHDATA2),
+        ("PUSHDATA4", Opcode::OP_PUSHDATA4),
+    ]);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptMacro {
+    pub params: Vec<String>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScriptAsm {
+    pub constants: HashMap<String, Vec<u8>>,
+    pub macros: HashMap<String, ScriptMacro>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+}
+
+impl ScriptAsm {
+    // macros can use other macros, but not recursively
+    pub const MAX_MACRO_DEPTH: usize = 16;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the lock-rel values of the network as constants, e.g.
+    // $pkhx_90d_lock_rel, and macros for the common parts of the pkhx scripts
+    pub fn from_params(params: &NetworkParams) -> Self {
+        let mut asm = Self::new();
+        let lock_rels = [
+            ("pkhx_90d_lock_rel", params.pkhx_90d_lock_rel),
+            ("pkhxr_90d_60d_x_lock_rel", params.pkhxr_90d_60d_x_lock_rel),
+            ("pkhxr_90d_60d_r_lock_rel", params.pkhxr_90d_60d_r_lock_rel),
+            ("pkhx_1h_lock_rel", params.pkhx_1h_lock_rel),
+            ("pkhxr_1h_40m_x_lock_rel", params.pkhxr_1h_40m_x_lock_rel),
+            ("pkhxr_1h_40m_r_lock_rel", params.pkhxr_1h_40m_r_lock_rel),
+        ];
+        for (name, lock_rel) in lock_rels {
+            asm.constants
+                .insert(name.to_string(), ScriptNum::from_u32(lock_rel).to_buf());
+        }
+        let macros = [
+            (
+                "pkh_check",
+                "pkh",
+                "DUP DOUBLEBLAKE3 $pkh EQUALVERIFY CHECKSIG",
+            ),
+            (
+                "lock_rel_verify",
+                "lock_rel",
+                "$lock_rel CHECKLOCKRELVERIFY DROP",
+            ),
+        ];
+        for (name, param, body) in macros {
+            let script_macro = ScriptMacro {
+                params: vec![param.to_string()],
+                body: body.to_string(),
+            };
+            asm.macros.insert(name.to_string(), script_macro);
+        }
+        asm
+    }
+
+    pub fn add_const(&mut self, name: &str, data: Vec<u8>) -> Result<(), EbxError> {
+        Self::check_name(name).map_err(Self::error)?;
+        self.constants.insert(name.to_string(), data);
+        Ok(())
+    }
+
+    pub fn add_macro(&mut self, name: &str, params: &[&str], body: &str) -> Result<(), EbxError> {
+        let params: Vec<String> = params.iter().map(|param| param.to_string()).collect();
+        self.add_script_macro(name, params, body.to_string())
+            .map_err(Self::error)
+    }
+
+    pub fn assemble(&self, src: &str) -> Result<Script, EbxError> {
+        // definitions in the source go in local, which is looked up before
+        // self, so they do not change self
+        let mut local = Self::new();
+        let mut chunks = Vec::new();
+        let mut lines = src.lines().enumerate();
+        while let Some((i, line)) = lines.next() {
+            let line_error = |message: String| Self::error(format!("line {}: {}", i + 1, message));
+            let tokens = Self::tokenize(line).map_err(line_error)?;
+            match tokens.first() {
+                Some(Token::Word(word)) if word == ".const" => {
+                    let (name, value) = match &tokens[1..] {
+                        [Token::Word(name), value] => (name, value),
+                        _ => return Err(line_error("expected .const name value".to_string())),
+                    };
+                    let data = self
+                        .eval(&local, value, &HashMap::new())
+                        .map_err(line_error)?;
+                    Self::check_name(name).map_err(line_error)?;
+                    local.constants.insert(name.clone(), data);
+                }
+                Some(Token::Word(word)) if word == ".macro" => {
+                    let mut words = Vec::new();
+                    for token in &tokens[1..] {
+                        match token {
+                            Token::Word(word) => words.push(word.clone()),
+                            Token::Str(_) => {
+                                return Err(line_error(
+                                    "expected .macro name param ...".to_string(),
+                                ))
+                            }
+                        }
+                    }
+                    if words.is_empty() {
+                        return Err(line_error("expected .macro name param ...".to_string()));
+                    }
+                    let mut body = Vec::new();
+                    loop {
+                        let (_, body_line) = lines
+                            .next()
+                            .ok_or_else(|| line_error("missing .endm".to_string()))?;
+                        if body_line.trim() == ".endm" {
+                            break;
+                        }
+                        body.push(body_line);
+                    }
+                    local
+                        .add_script_macro(&words[0], words[1..].to_vec(), body.join("\n"))
+                        .map_err(line_error)?;
+                }
+                Some(Token::Word(word)) if word.starts_with('.') => {
+                    return Err(line_error(format!("unknown directive {}", word)));
+                }
+                _ => self
+                    .expand(&local, &tokens, &HashMap::new(), 0, &mut chunks)
+                    .map_err(line_error)?,
+            }
+        }
+        Ok(Script::new(chunks))
+    }
+
+    // the inverse of assemble: opcodes by name and data in hex, one line per
+    // branch of IF/NOTIF/ELSE/ENDIF, indented by depth. non-minimal pushes are
+    // written with their push opcode, e.g. PUSHDATA1 0x05, so they assemble
+    // back to the same chunk.
+    pub fn disassemble(script: &Script) -> Result<String, EbxError> {
+        let mut lines: Vec<String> = Vec::new();
+        let mut line: Vec<String> = Vec::new();
+        let mut depth: usize = 0;
+        for chunk in &script.chunks {
+            let mut s = chunk.to_strict_str()?;
+            if let Some(buffer) = &chunk.buffer {
+                if ScriptChunk::from_data(buffer.clone()) != *chunk {
+                    Self::check_push(chunk.opcode, buffer).map_err(Self::error)?;
+                    let name = OPCODE_TO_NAME.get(&chunk.opcode).unwrap();
+                    s = format!("{} {}", name, s);
+                }
+            }
+            match chunk.opcode {
+                Opcode::OP_IF | Opcode::OP_NOTIF | Opcode::OP_ELSE | Opcode::OP_ENDIF => {
+                    Self::push_line(&mut lines, &mut line, depth);
+                    if chunk.opcode != Opcode::OP_IF && chunk.opcode != Opcode::OP_NOTIF {
+                        depth = depth.saturating_sub(1);
+                    }
+                    lines.push(format!("{}{}", "  ".repeat(depth), s));
+                    if chunk.opcode != Opcode::OP_ENDIF {
+                        depth += 1;
+                    }
+                }
+                _ => line.push(s),
+            }
+        }
+        Self::push_line(&mut lines, &mut line, depth);
+        Ok(lines.join("\n"))
+    }
+
+    fn push_line(lines: &mut Vec<String>, line: &mut Vec<String>, depth: usize) {
+        if !line.is_empty() {
+            lines.push(format!("{}{}", "  ".repeat(depth), line.join(" ")));
+            line.clear();
+        }
+    }
+
+    fn add_script_macro(
+        &mut self,
+        name: &str,
+        params: Vec<String>,
+        body: String,
+    ) -> Result<(), String> {
+        Self::check_name(name)?;
+        for param in &params {
+            Self::check_name(param)?;
+        }
+        // check the body now rather than when the macro is used
+        for line in body.lines() {
+            Self::tokenize(line).map_err(|message| format!("macro {}: {}", name, message))?;
+        }
+        self.macros
+            .insert(name.to_string(), ScriptMacro { params, body });
+        Ok(())
+    }
+
+    fn expand(
+        &self,
+        local: &ScriptAsm,
+        tokens: &[Token],
+        args: &HashMap<String, Vec<u8>>,
+        depth: usize,
+        chunks: &mut Vec<ScriptChunk>,
+    ) -> Result<(), String> {
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            i += 1;
+            if let Token::Word(word) = token {
+                if let Some(&opcode) = PUSH_OPCODES.get(word.as_str()) {
+                    let value = tokens
+                        .get(i)
+                        .ok_or_else(|| format!("{} expects a value", word))?;
+                    i += 1;
+                    let data = self.eval(local, value, args)?;
+                    Self::check_push(opcode, &data)?;
+                    chunks.push(ScriptChunk::new(opcode, Some(data)));
+                    continue;
+                }
+                if let Some(opcode) = OP.get(word.as_str()) {
+                    chunks.push(ScriptChunk::new(*opcode, None));
+                    continue;
+                }
+                let script_macro = local.macros.get(word).or_else(|| self.macros.get(word));
+                if let Some(script_macro) = script_macro {
+                    if depth >= Self::MAX_MACRO_DEPTH {
+                        return Err(format!("macro {} nested too deeply", word));
+                    }
+                    let n_params = script_macro.params.len();
+                    if tokens.len() - i < n_params {
+                        return Err(format!("macro {} expects {} arguments", word, n_params));
+                    }
+                    let mut macro_args = HashMap::new();
+                    for param in &script_macro.params {
+                        macro_args.insert(param.clone(), self.eval(local, &tokens[i], args)?);
+                        i += 1;
+                    }
+                    for line in script_macro.body.lines() {
+                        let body_tokens = Self::tokenize(line)?;
+                        self.expand(local, &body_tokens, &macro_args, depth + 1, chunks)
+                            .map_err(|message| format!("macro {}: {}", word, message))?;
+                    }
+                    continue;
+                }
+            }
+            chunks.push(ScriptChunk::from_data(self.eval(local, token, args)?));
+        }
+        Ok(())
+    }
+
+    // the data pushed by a value: a literal, a constant or a macro argument
+    fn eval(
+        &self,
+        local: &ScriptAsm,
+        token: &Token,
+        args: &HashMap<String, Vec<u8>>,
+    ) -> Result<Vec<u8>, String> {
+        let word = match token {
+            Token::Str(s) => return Ok(s.as_bytes().to_vec()),
+            Token::Word(word) => word,
+        };
+        if let Some(hex) = word.strip_prefix("0x") {
+            return Vec::<u8>::from_strict_hex(hex).map_err(|_| format!("invalid hex {}", word));
+        }
+        if let Some(name) = word.strip_prefix('$') {
+            return args
+                .get(name)
+                .or_else(|| local.constants.get(name))
+                .or_else(|| self.constants.get(name))
+                .cloned()
+                .ok_or_else(|| format!("unknown constant {}", word));
+        }
+        let digits = word.strip_prefix('-').unwrap_or(word);
+        if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+            let num = BigInt::parse_bytes(word.as_bytes(), 10).unwrap();
+            return Ok(ScriptNum::new(num).to_buf());
+        }
+        Err(format!("unknown opcode or macro {}", word))
+    }
+
+    fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+        let mut tokens = Vec::new();
+        let mut chars = line.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else if c == '#' {
+                break;
+            } else if c == '"' {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) if c == '"' || c == '\\' => s.push(c),
+                            _ => return Err("invalid escape in string".to_string()),
+                        },
+                        Some(c) => s.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            } else {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '#' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+        Ok(tokens)
+    }
+
+    // the explicit pushes that assemble and disassemble support
+    fn check_push(opcode: u8, data: &[u8]) -> Result<(), String> {
+        let max_len: usize = match opcode {
+            Opcode::OP_PUSHDATA1 => 0xff,
+            Opcode::OP_PUSHDATA2 => 0xffff,
+            Opcode::OP_PUSHDATA4 => 0xffffffff,
+            _ => return Err(format!("data pushed by opcode {}", opcode)),
+        };
+        if data.len() > max_len {
+            return Err(format!("data too long for {}", OPCODE_TO_NAME[&opcode]));
+        }
+        Ok(())
+    }
+
+    // constant, macro and parameter names are lowercase, so they can never be
+    // confused with opcodes
+    fn check_name(name: &str) -> Result<(), String> {
+        let mut chars = name.chars();
+        let is_valid = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+            && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !is_valid {
+            return Err(format!("invalid name {}", name));
+        }
+        Ok(())
+    }
+
+    fn error(message: String) -> EbxError {
+        EbxError::GenericError {
+            source: None,
+            message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_literals() {
+        let asm = ScriptAsm::new();
+        let script = asm
+            .assemble("0 5 16 17 -1 12960 0x05 0x00ff \"hi there\" \"a\\\"b\" DUP")
+            .unwrap();
+        let expected = Script::new(vec![
+            ScriptChunk::new(Opcode::OP_0, None),
+            ScriptChunk::new(Opcode::OP_5, None),
+            ScriptChunk::new(Opcode::OP_16, None),
+            ScriptChunk::from_data(vec![17]),
+            ScriptChunk::from_data(vec![0xff]),
+            ScriptChunk::from_data(ScriptNum::from_u32(12960).to_buf()),
+            ScriptChunk::new(Opcode::OP_5, None),
+            ScriptChunk::from_data(vec![0x00, 0xff]),
+            ScriptChunk::from_data(b"hi there".to_vec()),
+            ScriptChunk::from_data(b"a\"b".to_vec()),
+            ScriptChunk::new(Opcode::OP_DUP, None),
+        ]);
+        assert_eq!(script, expected);
+        assert_eq!(asm.assemble("").unwrap(), Script::from_empty());
+
+        // explicit pushes of any value
+        let script = asm
+            .assemble(".const one 0x01\nPUSHDATA1 \"\" PUSHDATA1 5 PUSHDATA2 $one")
+            .unwrap();
+        let expected = Script::new(vec![
+            ScriptChunk::new(Opcode::OP_PUSHDATA1, Some(vec![])),
+            ScriptChunk::new(Opcode::OP_PUSHDATA1, Some(vec![5])),
+            ScriptChunk::new(Opcode::OP_PUSHDATA2, Some(vec![1])),
+        ]);
+        assert_eq!(script, expected);
+    }
+
+    #[test]
+    fn test_comments_and_lines() {
+        let asm = ScriptAsm::new();
+        let src = "
+            # pay to pubkey hash
+            DUP DOUBLEBLAKE3   # hash the pubkey
+            0x0101010101010101010101010101010101010101010101010101010101010101
+            EQUALVERIFY CHECKSIG \"# not a comment\"
+        ";
+        let script = asm.assemble(src).unwrap();
+        let mut expected = Script::from_pkh_output(&[1; 32]);
+        expected
+            .chunks
+            .push(ScriptChunk::from_data(b"# not a comment".to_vec()));
+        assert_eq!(script, expected);
+    }
+
+    #[test]
+    fn test_constants_and_macros() {
+        let asm = ScriptAsm::new();
+        let src = "
+            .const answer 42
+            .const greeting \"hi\"
+            .macro push_twice value
+              $value $value
+            .endm
+            .macro four_times a
+              push_twice $a push_twice $a # macros can use macros
+            .endm
+            $answer four_times $greeting
+        ";
+        let script = asm.assemble(src).unwrap();
+        let hi = ScriptChunk::from_data(b"hi".to_vec());
+        let expected = Script::new(vec![
+            ScriptChunk::from_data(vec![42]),
+            hi.clone(),
+            hi.clone(),
+            hi.clone(),
+            hi,
+        ]);
+        assert_eq!(script, expected);
+        // in-source definitions do not leak out of the source
+        assert!(asm.constants.is_empty() && asm.macros.is_empty());
+
+        let mut asm = ScriptAsm::new();
+        asm.add_const("answer", vec![42]).unwrap();
+        asm.add_macro("verify_equal", &["value"], "$value EQUALVERIFY")
+            .unwrap();
+        let script = asm.assemble("verify_equal $answer").unwrap();
+        assert_eq!(script.to_strict_str().unwrap(), "0x2a EQUALVERIFY");
+        // and they shadow self's for the rest of the source only
+        let src =
+            ".const answer 7\n.macro verify_equal value\n$value EQUAL\n.endm\nverify_equal $answer";
+        let script = asm.assemble(src).unwrap();
+        assert_eq!(script.to_strict_str().unwrap(), "7 EQUAL");
+        let script = asm.assemble("verify_equal $answer").unwrap();
+        assert_eq!(script.to_strict_str().unwrap(), "0x2a EQUALVERIFY");
+    }
+
+    #[test]
+    fn test_pkhx_scripts_from_params() {
+        let pkh = [1; 32];
+        let rpkh = [2; 32];
+        for params in [NetworkParams::MAINNET, NetworkParams::REGTEST] {
+            let mut asm = ScriptAsm::from_params(&params);
+            asm.add_const("pkh", pkh.to_vec()).unwrap();
+            asm.add_const("rpkh", rpkh.to_vec()).unwrap();
+
+            let src = "
+                IF
+                  pkh_check $pkh
+                ELSE
+                  lock_rel_verify $pkhx_90d_lock_rel 1
+                ENDIF
+            ";
+            let script = asm.assemble(src).unwrap();
+            assert_eq!(script, Script::from_pkhx_90d_output(&pkh, &params));
+
+            let src = "
+                IF
+                  pkh_check $pkh
+                ELSE
+                  IF  # recovery
+                    lock_rel_verify $pkhxr_1h_40m_r_lock_rel
+                    pkh_check $rpkh
+                  ELSE  # expiry
+                    lock_rel_verify $pkhxr_1h_40m_x_lock_rel 1
+                  ENDIF
+                ENDIF
+            ";
+            let script = asm.assemble(src).unwrap();
+            assert_eq!(
+                script,
+                Script::from_pkhxr_1h_40m_output(&pkh, &rpkh, &params)
+            );
+            assert_eq!(
+                asm.assemble(&ScriptAsm::disassemble(&script).unwrap())
+                    .unwrap(),
+                script
+            );
+        }
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let params = NetworkParams::MAINNET;
+        let script = Script::from_pkhx_90d_output(&[1; 32], &params);
+        let expected = "IF\n  DUP DOUBLEBLAKE3 0x0101010101010101010101010101010101010101010101010101010101010101 EQUALVERIFY CHECKSIG\nELSE\n  0x32a0 CHECKLOCKRELVERIFY DROP 1\nENDIF";
+        assert_eq!(ScriptAsm::disassemble(&script).unwrap(), expected);
+
+        let scripts = [
+            Script::from_empty(),
+            Script::from_pkh_output(&[3; 32]),
+            Script::from_pkhxr_90d_60d_output(&[1; 32], &[2; 32], &params),
+            Script::from_multi_sig_output(1, vec![vec![2; 33], vec![3; 33]]),
+            Script::from_strict_str("ENDIF ELSE 0x00 0x81 0x0100").unwrap(),
+        ];
+        let asm = ScriptAsm::new();
+        for script in scripts {
+            let src = ScriptAsm::disassemble(&script).unwrap();
+            assert_eq!(asm.assemble(&src).unwrap(), script);
+        }
+
+        // from_strict_str pushes 0x05 with PUSHDATA1 rather than the opcode 5
+        let scripts = [
+            Script::from_strict_str("0x05").unwrap(),
+            Script::from_strict_str("0x DUP 0x81 0x0100").unwrap(),
+            Script::new(vec![
+                ScriptChunk::new(Opcode::OP_PUSHDATA2, Some(vec![0x05])),
+                ScriptChunk::new(Opcode::OP_PUSHDATA4, Some(vec![1; 300])),
+            ]),
+        ];
+        for script in scripts {
+            let src = ScriptAsm::disassemble(&script).unwrap();
+            assert_eq!(asm.assemble(&src).unwrap(), script);
+        }
+        let script = Script::from_strict_str("0x05 0x").unwrap();
+        assert_eq!(
+            ScriptAsm::disassemble(&script).unwrap(),
+            "PUSHDATA1 0x05 PUSHDATA1 0x"
+        );
+
+        // data that the opcode cannot push
+        let script = Script::new(vec![ScriptChunk::new(
+            Opcode::OP_PUSHDATA1,
+            Some(vec![1; 256]),
+        )]);
+        assert!(ScriptAsm::disassemble(&script).is_err());
+        let script = Script::new(vec![ScriptChunk::new(Opcode::OP_DUP, Some(vec![5]))]);
+        assert!(ScriptAsm::disassemble(&script).is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut asm = ScriptAsm::new();
+        let error = |src: &str| asm.assemble(src).unwrap_err().to_string();
+        assert_eq!(
+            error("DUP\nFOO"),
+            "ebx error: line 2: unknown opcode or macro FOO"
+        );
+        assert_eq!(error("0xabc"), "ebx error: line 1: invalid hex 0xabc");
+        assert_eq!(error("$nope"), "ebx error: line 1: unknown constant $nope");
+        assert_eq!(error("\"abc"), "ebx error: line 1: unterminated string");
+        assert_eq!(
+            error("\"\\n\""),
+            "ebx error: line 1: invalid escape in string"
+        );
+        assert_eq!(error(".const DUP 1"), "ebx error: line 1: invalid name DUP");
+        assert_eq!(
+            error(".const a"),
+            "ebx error: line 1: expected .const name value"
+        );
+        assert_eq!(error(".macro m\nDUP"), "ebx error: line 1: missing .endm");
+        assert_eq!(error(".foo"), "ebx error: line 1: unknown directive .foo");
+        assert_eq!(
+            error(".macro m a\n$a\n.endm\nm"),
+            "ebx error: line 4: macro m expects 1 arguments"
+        );
+        assert_eq!(
+            error(".macro m\nm\n.endm\nm"),
+            "ebx error: line 4: macro m: macro m: macro m: macro m: macro m: macro m: macro m: macro m: macro m: macro m: macro m: macro m: macro m: macro m: macro m: macro m: macro m nested too deeply"
+        );
+
+        assert_eq!(
+            error("PUSHDATA1"),
+            "ebx error: line 1: PUSHDATA1 expects a value"
+        );
+        assert_eq!(
+            error(&format!("PUSHDATA1 0x{}", "00".repeat(256))),
+            "ebx error: line 1: data too long for PUSHDATA1"
+        );
+
+        assert!(asm.add_const("Foo", vec![]).is_err());
+        assert!(asm.add_macro("m", &["a b"], "$a").is_err());
+        assert!(asm.add_macro("m", &["a"], "\"$a").is_err());
+    }
+}
@@ -75,6 +75,7 @@ impl ScriptChunk {
         Ok(chunk)
     }
 
+    #[allow(clippy::single_match)]
     pub fn to_buf(&self) -> Vec<u8> {
         let mut result = Vec::new();
         result.push(self.opcode);
@@ -133,6 +134,25 @@ impl ScriptChunk {
         Ok(chunk)
     }
 
+    // whether a push is the one from_data would give, which is also what
+    // from_buf_reader requires. opcodes that are not pushes are minimal.
+    pub fn is_minimal_push(&self) -> bool {
+        let len = match &self.buffer {
+            Some(buffer) => buffer.len(),
+            None => return true,
+        };
+        match self.opcode {
+            Opcode::OP_PUSHDATA1 => {
+                let is_small_number =
+                    len == 1 && (1..=16).contains(&self.buffer.as_ref().unwrap()[0]);
+                len > 0 && len <= 0xff && !is_small_number
+            }
+            Opcode::OP_PUSHDATA2 => len > 0xff && len <= 0xffff,
+            Opcode::OP_PUSHDATA4 => len > 0xffff,
+            _ => true,
+        }
+    }
+
     pub fn from_data(data: Vec<u8>) -> ScriptChunk {
         let len = data.len();
         if len == 0 {
@@ -369,4 +389,30 @@ mod tests {
             _ => panic!("Expected an error for insufficient buffer length in PUSHDATA4 case"),
         }
     }
+
+    #[test]
+    fn test_is_minimal_push() {
+        for data in [
+            vec![],
+            vec![5],
+            vec![0],
+            vec![17],
+            vec![0; 256],
+            vec![0; 65536],
+        ] {
+            assert!(ScriptChunk::from_data(data).is_minimal_push());
+        }
+        let non_minimal = [
+            ScriptChunk::new(Opcode::OP_PUSHDATA1, Some(vec![])),
+            ScriptChunk::new(Opcode::OP_PUSHDATA1, Some(vec![16])),
+            ScriptChunk::new(Opcode::OP_PUSHDATA1, Some(vec![0; 256])),
+            ScriptChunk::new(Opcode::OP_PUSHDATA2, Some(vec![0; 255])),
+            ScriptChunk::new(Opcode::OP_PUSHDATA2, Some(vec![0; 65536])),
+            ScriptChunk::new(Opcode::OP_PUSHDATA4, Some(vec![0; 65535])),
+        ];
+        for chunk in non_minimal {
+            assert!(!chunk.is_minimal_push());
+        }
+        assert!(ScriptChunk::new(Opcode::OP_DUP, None).is_minimal_push());
+    }
 }
@@ -1,13 +1,56 @@
+use crate::error::ScriptLimitError;
 use crate::hash::{blake3_hash, double_blake3_hash};
 use crate::opcode::{Opcode, OP};
 use crate::pub_key::PubKey;
 use crate::script::Script;
+use crate::script_chunk::ScriptChunk;
 use crate::script_num::ScriptNum;
 use crate::tx::{HashCache, Tx};
 use crate::tx_signature::TxSignature;
 use num_bigint::{BigInt, ToBigInt};
 use num_traits::ToPrimitive;
 
+// what one step of the interpreter did, for debugging scripts. chunks in a
+// branch that is not taken are stepped over without being executed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptStep {
+    pub pc: usize,
+    pub chunk: ScriptChunk,
+    pub is_executed: bool,
+    pub stack_before: Vec<Vec<u8>>,
+    pub stack_after: Vec<Vec<u8>>,
+    pub alt_stack: Vec<Vec<u8>>, // after
+    pub if_stack: Vec<bool>,     // after
+    pub err_str: String,
+}
+
+// consensus limits on the resources a script can use, so that a tx cannot
+// make us allocate unbounded memory, e.g. by growing an element with CAT
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptLimits {
+    pub max_script_size: usize,  // bytes
+    pub max_op_count: usize,     // non-push opcodes, executed or not
+    pub max_stack_size: usize,   // elements on the stack and alt stack
+    pub max_element_size: usize, // bytes
+}
+
+impl ScriptLimits {
+    pub const DEFAULT: ScriptLimits = ScriptLimits {
+        max_script_size: 100_000,
+        max_op_count: 201,
+        max_stack_size: 1_000,
+        max_element_size: 100_000,
+    };
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        ScriptLimits::DEFAULT
+    }
+}
+
+pub type ScriptTrace<'a> = Box<dyn FnMut(&ScriptStep) + 'a>;
+
 pub struct ScriptInterpreter<'a> {
     pub script: Script,
     pub tx: Tx,
@@ -20,8 +63,13 @@ pub struct ScriptInterpreter<'a> {
     pub return_value: Option<Vec<u8>>,
     pub return_success: Option<bool>,
     pub err_str: String,
+    pub limits: ScriptLimits,
+    pub limit_error: Option<ScriptLimitError>,
+    pub require_minimal_push: bool,
+    pub require_minimal_num: bool,
     pub value: u64,
     pub hash_cache: &'a mut HashCache,
+    pub trace: Option<ScriptTrace<'a>>,
 }
 
 impl<'a> ScriptInterpreter<'a> {
@@ -43,8 +91,13 @@ impl<'a> ScriptInterpreter<'a> {
             return_value: None,
             return_success: None,
             err_str: "".to_string(),
+            limits: ScriptLimits::DEFAULT,
+            limit_error: None,
+            require_minimal_push: false,
+            require_minimal_num: false,
             value: 0,
             hash_cache,
+            trace: None,
         }
     }
 
@@ -68,8 +121,13 @@ impl<'a> ScriptInterpreter<'a> {
             return_value: None,
             return_success: None,
             err_str: "".to_string(),
+            limits: ScriptLimits::DEFAULT,
+            limit_error: None,
+            require_minimal_push: false,
+            require_minimal_num: false,
             value,
             hash_cache,
+            trace: None,
         }
     }
 
@@ -77,1000 +135,1238 @@ impl<'a> ScriptInterpreter<'a> {
         !buf.iter().all(|&x| x == 0)
     }
 
-    pub fn eval_script(&mut self) -> bool {
-        while self.pc < self.script.chunks.len() {
-            let chunk = &self.script.chunks[self.pc];
-            let opcode = chunk.opcode;
-            let if_exec = !self.if_stack.contains(&false);
-
-            if !(if_exec
-                || opcode == Opcode::OP_IF
-                || opcode == Opcode::OP_NOTIF
-                || opcode == Opcode::OP_ELSE
-                || opcode == Opcode::OP_ENDIF)
-            {
-                self.pc += 1;
-                continue;
-            }
-
-            match opcode {
-                Opcode::OP_IF => {
-                    let mut if_value = false;
-                    if if_exec {
-                        if self.stack.is_empty() {
-                            self.err_str = "unbalanced conditional".to_string();
-                            break;
-                        }
-                        let buf = self.stack.pop().unwrap();
-                        if_value = ScriptInterpreter::cast_to_bool(&buf);
-                    }
-                    self.if_stack.push(if_value);
-                }
-                Opcode::OP_NOTIF => {
-                    let mut if_value = false;
-                    if if_exec {
-                        if self.stack.is_empty() {
-                            self.err_str = "unbalanced conditional".to_string();
-                            break;
-                        }
-                        let buf = self.stack.pop().unwrap();
-                        if_value = !ScriptInterpreter::cast_to_bool(&buf);
-                    }
-                    self.if_stack.push(if_value);
-                }
-                Opcode::OP_ELSE => {
-                    if self.if_stack.is_empty() {
-                        self.err_str = "unbalanced conditional".to_string();
-                        break;
-                    }
-                    let if_stack_len = self.if_stack.len();
-                    self.if_stack[if_stack_len - 1] = !self.if_stack[self.if_stack.len() - 1];
-                }
-                Opcode::OP_ENDIF => {
-                    if self.if_stack.is_empty() {
-                        self.err_str = "unbalanced conditional".to_string();
-                        break;
-                    }
-                    self.if_stack.pop();
-                }
-                Opcode::OP_0 => {
-                    self.stack.push(vec![]);
-                }
-                Opcode::OP_PUSHDATA1 | Opcode::OP_PUSHDATA2 | Opcode::OP_PUSHDATA4 => {
-                    if let Some(buffer) = &chunk.buffer {
-                        self.stack.push(buffer.clone());
-                    } else {
-                        self.err_str = "invalid pushdata".to_string();
-                    }
-                }
-                Opcode::OP_1NEGATE => {
-                    let script_num = ScriptNum::new((-1).to_bigint().unwrap());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_1 => {
-                    let script_num = ScriptNum::new(1.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_2 => {
-                    let script_num = ScriptNum::new(2.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_3 => {
-                    let script_num = ScriptNum::new(3.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_4 => {
-                    let script_num = ScriptNum::new(4.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_5 => {
-                    let script_num = ScriptNum::new(5.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_6 => {
-                    let script_num = ScriptNum::new(6.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_7 => {
-                    let script_num = ScriptNum::new(7.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_8 => {
-                    let script_num = ScriptNum::new(8.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_9 => {
-                    let script_num = ScriptNum::new(9.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_10 => {
-                    let script_num = ScriptNum::new(10.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_11 => {
-                    let script_num = ScriptNum::new(11.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_12 => {
-                    let script_num = ScriptNum::new(12.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_13 => {
-                    let script_num = ScriptNum::new(13.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_14 => {
-                    let script_num = ScriptNum::new(14.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_15 => {
-                    let script_num = ScriptNum::new(15.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_16 => {
-                    let script_num = ScriptNum::new(16.into());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_VERIFY => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf = self.stack.pop().unwrap();
-                    if !ScriptInterpreter::cast_to_bool(&buf) {
-                        self.err_str = "VERIFY failed".to_string();
-                        break;
-                    }
-                }
-                Opcode::OP_RETURN => {
-                    break;
-                }
-                Opcode::OP_TOALTSTACK => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    self.alt_stack.push(self.stack.pop().unwrap());
-                }
-                Opcode::OP_FROMALTSTACK => {
-                    if self.alt_stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    self.stack.push(self.alt_stack.pop().unwrap());
-                }
-                Opcode::OP_2DROP => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    self.stack.pop();
-                    self.stack.pop();
-                }
-                Opcode::OP_2DUP => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack[self.stack.len() - 2].clone();
-                    let buf2 = self.stack[self.stack.len() - 1].clone();
-                    self.stack.push(buf1);
-                    self.stack.push(buf2);
-                }
-                Opcode::OP_3DUP => {
-                    if self.stack.len() < 3 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack[self.stack.len() - 3].clone();
-                    let buf2 = self.stack[self.stack.len() - 2].clone();
-                    let buf3 = self.stack[self.stack.len() - 1].clone();
-                    self.stack.push(buf1);
-                    self.stack.push(buf2);
-                    self.stack.push(buf3);
-                }
-                Opcode::OP_2OVER => {
-                    if self.stack.len() < 4 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack[self.stack.len() - 4].clone();
-                    let buf2 = self.stack[self.stack.len() - 3].clone();
-                    self.stack.push(buf1);
-                    self.stack.push(buf2);
-                }
-                Opcode::OP_2ROT => {
-                    if self.stack.len() < 6 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack[self.stack.len() - 6].clone();
-                    let buf2 = self.stack[self.stack.len() - 5].clone();
-                    self.stack.remove(self.stack.len() - 6);
-                    self.stack.remove(self.stack.len() - 5);
-                    self.stack.push(buf1);
-                    self.stack.push(buf2);
-                }
-                Opcode::OP_2SWAP => {
-                    if self.stack.len() < 4 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack[self.stack.len() - 4].clone();
-                    let buf2 = self.stack[self.stack.len() - 3].clone();
-                    self.stack.remove(self.stack.len() - 4);
-                    self.stack.remove(self.stack.len() - 3);
-                    self.stack.push(buf1);
-                    self.stack.push(buf2);
-                }
-                Opcode::OP_IFDUP => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf = self.stack[self.stack.len() - 1].clone();
-                    if ScriptInterpreter::cast_to_bool(&buf) {
-                        self.stack.push(buf);
-                    }
-                }
-                Opcode::OP_DEPTH => {
-                    let script_num = ScriptNum::new(self.stack.len().to_bigint().unwrap());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_DROP => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    self.stack.pop();
-                }
-                Opcode::OP_DUP => {
+    // called with every step while eval_script runs
+    pub fn set_trace(&mut self, trace: impl FnMut(&ScriptStep) + 'a) {
+        self.trace = Some(Box::new(trace));
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.err_str.is_empty() || self.pc >= self.script.chunks.len()
+    }
+
+    // chunks in a branch that is not taken are skipped, except for the
+    // conditionals, which keep track of the branches
+    fn is_chunk_executed(&self) -> bool {
+        let opcode = self.script.chunks[self.pc].opcode;
+        !self.if_stack.contains(&false)
+            || opcode == Opcode::OP_IF
+            || opcode == Opcode::OP_NOTIF
+            || opcode == Opcode::OP_ELSE
+            || opcode == Opcode::OP_ENDIF
+    }
+
+    // decodes a number from the stack, failing if require_minimal_num is set
+    // and it is not in its shortest encoding
+    fn decode_num(&mut self, buf: &[u8]) -> Option<ScriptNum> {
+        if self.require_minimal_num && !ScriptNum::is_minimal_buf(buf) {
+            self.err_str = "non-minimal number".to_string();
+            return None;
+        }
+        Some(ScriptNum::from_buf(buf))
+    }
+
+    fn pop_num(&mut self) -> Option<ScriptNum> {
+        let buf = self.stack.pop().unwrap();
+        self.decode_num(&buf)
+    }
+
+    fn last_num(&mut self) -> Option<ScriptNum> {
+        let buf = self.stack.last().unwrap().clone();
+        self.decode_num(&buf)
+    }
+
+    fn set_limit_error(&mut self, error: ScriptLimitError) {
+        self.err_str = error.to_string();
+        self.limit_error = Some(error);
+    }
+
+    // new elements are only ever pushed on top of the stack, so only the top
+    // element needs checking after each chunk
+    fn verify_stack_limits(&self) -> Result<(), ScriptLimitError> {
+        if self.stack.len() + self.alt_stack.len() > self.limits.max_stack_size {
+            return Err(ScriptLimitError::StackSize);
+        }
+        if let Some(top) = self.stack.last() {
+            if top.len() > self.limits.max_element_size {
+                return Err(ScriptLimitError::ElementSize);
+            }
+        }
+        Ok(())
+    }
+
+    // the script and the stack it starts with, e.g. from an input script
+    fn verify_initial_limits(&self) -> Result<(), ScriptLimitError> {
+        if self.script.to_buf().len() > self.limits.max_script_size {
+            return Err(ScriptLimitError::ScriptSize);
+        }
+        if self
+            .stack
+            .iter()
+            .chain(self.alt_stack.iter())
+            .any(|buf| buf.len() > self.limits.max_element_size)
+        {
+            return Err(ScriptLimitError::ElementSize);
+        }
+        self.verify_stack_limits()
+    }
+
+    // runs the chunk at pc, or skips it in a branch that is not taken
+    fn exec_chunk(&mut self) {
+        if self.pc == 0 {
+            if let Err(error) = self.verify_initial_limits() {
+                self.set_limit_error(error);
+                return;
+            }
+        }
+        if self.script.chunks[self.pc].opcode > Opcode::OP_16 {
+            self.n_op_count += 1;
+            if self.n_op_count > self.limits.max_op_count {
+                self.set_limit_error(ScriptLimitError::OpCount);
+                return;
+            }
+        }
+        if !self.is_chunk_executed() {
+            self.pc += 1;
+            return;
+        }
+        self.exec_opcode();
+        if self.err_str.is_empty() {
+            if let Err(error) = self.verify_stack_limits() {
+                self.set_limit_error(error);
+            }
+        }
+    }
+
+    fn exec_opcode(&mut self) {
+        let chunk = &self.script.chunks[self.pc];
+        let opcode = chunk.opcode;
+        let if_exec = !self.if_stack.contains(&false);
+
+        match opcode {
+            Opcode::OP_IF => {
+                let mut if_value = false;
+                if if_exec {
                     if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf = self.stack[self.stack.len() - 1].clone();
-                    self.stack.push(buf);
-                }
-                Opcode::OP_NIP => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
+                        self.err_str = "unbalanced conditional".to_string();
+                        return;
                     }
                     let buf = self.stack.pop().unwrap();
-                    self.stack.pop();
-                    self.stack.push(buf);
+                    if_value = ScriptInterpreter::cast_to_bool(&buf);
                 }
-                Opcode::OP_OVER => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf = self.stack[self.stack.len() - 2].clone();
-                    self.stack.push(buf);
-                }
-                Opcode::OP_PICK => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    if (script_num.num < 0.to_bigint().unwrap())
-                        || (script_num.num >= self.stack.len().to_bigint().unwrap())
-                    {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let num = script_num.to_u32() as usize;
-                    if num >= self.stack.len() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf = self.stack[self.stack.len() - num - 1].clone();
-                    self.stack.push(buf);
-                }
-                Opcode::OP_ROLL => {
+                self.if_stack.push(if_value);
+            }
+            Opcode::OP_NOTIF => {
+                let mut if_value = false;
+                if if_exec {
                     if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    if (script_num.num < 0.to_bigint().unwrap())
-                        || (script_num.num >= self.stack.len().to_bigint().unwrap())
-                    {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let num = script_num.to_u32() as usize;
-                    if num >= self.stack.len() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf = self.stack.remove(self.stack.len() - num - 1);
-                    self.stack.push(buf);
-                }
-                Opcode::OP_ROT => {
-                    if self.stack.len() < 3 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf = self.stack.remove(self.stack.len() - 3);
-                    self.stack.push(buf);
-                }
-                Opcode::OP_SWAP => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf = self.stack.remove(self.stack.len() - 2);
-                    self.stack.push(buf);
-                }
-                Opcode::OP_TUCK => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf = self.stack[self.stack.len() - 1].clone();
-                    self.stack.insert(self.stack.len() - 2, buf);
-                }
-                Opcode::OP_CAT => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack.pop().unwrap();
-                    let buf2 = self.stack.pop().unwrap();
-                    let mut new_buf = Vec::new();
-                    new_buf.extend_from_slice(&buf2);
-                    new_buf.extend_from_slice(&buf1);
-                    self.stack.push(new_buf);
-                }
-                Opcode::OP_SUBSTR => {
-                    if self.stack.len() < 3 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2_bn = script_num2.clone().num;
-                    let script_num1_bn = script_num1.clone().num;
-                    let buf = self.stack.pop().unwrap();
-                    let buf_len = buf.len();
-                    if script_num1_bn < 0.to_bigint().unwrap()
-                        || script_num2_bn < 0.to_bigint().unwrap()
-                        || script_num1_bn + script_num2_bn > buf_len.to_bigint().unwrap()
-                    {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let start = script_num1.to_u32() as usize;
-                    let len = script_num2.to_u32() as usize;
-                    let new_buf = buf[start..start + len].to_vec();
-                    self.stack.push(new_buf);
-                }
-                Opcode::OP_LEFT => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let buf = self.stack.pop().unwrap();
-                    let len_bn = script_num.clone().num;
-                    if len_bn < 0.to_bigint().unwrap() || len_bn > buf.len().to_bigint().unwrap() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let len = script_num.to_u32() as usize;
-                    let new_buf = buf[0..len].to_vec();
-                    self.stack.push(new_buf);
-                }
-                Opcode::OP_RIGHT => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
+                        self.err_str = "unbalanced conditional".to_string();
+                        return;
                     }
-                    let script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
                     let buf = self.stack.pop().unwrap();
-                    let len_bn = script_num.clone().num;
-                    if len_bn < 0.to_bigint().unwrap() || len_bn > buf.len().to_bigint().unwrap() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let len = script_num.to_u32() as usize;
-                    let new_buf = buf[buf.len() - len..buf.len()].to_vec();
-                    self.stack.push(new_buf);
-                }
-                Opcode::OP_SIZE => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num =
-                        ScriptNum::new(self.stack[self.stack.len() - 1].len().to_bigint().unwrap());
-                    self.stack.push(script_num.to_buf());
-                }
-                Opcode::OP_INVERT => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let mut buf = self.stack.pop().unwrap();
-                    buf.iter_mut().for_each(|byte| *byte = !*byte);
-                    self.stack.push(buf);
-                }
-                Opcode::OP_AND => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack.pop().unwrap();
-                    let buf2 = self.stack.pop().unwrap();
-                    let len1 = buf1.len();
-                    let len2 = buf2.len();
-                    if len1 != len2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let len = len1;
-                    let mut new_buf = Vec::new();
-                    for i in 0..len {
-                        new_buf.push(buf1[i] & buf2[i]);
-                    }
-                    self.stack.push(new_buf);
-                }
-                Opcode::OP_OR => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack.pop().unwrap();
-                    let buf2 = self.stack.pop().unwrap();
-                    let len1 = buf1.len();
-                    let len2 = buf2.len();
-                    if len1 != len2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let len = len1;
-                    let mut new_buf = Vec::new();
-                    for i in 0..len {
-                        new_buf.push(buf1[i] | buf2[i]);
-                    }
-                    self.stack.push(new_buf);
-                }
-                Opcode::OP_XOR => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack.pop().unwrap();
-                    let buf2 = self.stack.pop().unwrap();
-                    let len1 = buf1.len();
-                    let len2 = buf2.len();
-                    if len1 != len2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let len = len1;
-                    let mut new_buf = Vec::new();
-                    for i in 0..len {
-                        new_buf.push(buf1[i] ^ buf2[i]);
-                    }
-                    self.stack.push(new_buf);
-                }
-                Opcode::OP_EQUAL => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack.pop().unwrap();
-                    let buf2 = self.stack.pop().unwrap();
-                    let equal = buf1 == buf2;
-                    self.stack.push(if equal { vec![1] } else { vec![] });
-                }
-                Opcode::OP_EQUALVERIFY => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack.pop().unwrap();
-                    let buf2 = self.stack.pop().unwrap();
-                    if buf1 != buf2 {
-                        self.err_str = "EQUALVERIFY failed".to_string();
-                        break;
-                    }
+                    if_value = !ScriptInterpreter::cast_to_bool(&buf);
                 }
-                Opcode::OP_1ADD => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let new_num = script_num.num + 1.to_bigint().unwrap();
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
+                self.if_stack.push(if_value);
+            }
+            Opcode::OP_ELSE => {
+                if self.if_stack.is_empty() {
+                    self.err_str = "unbalanced conditional".to_string();
+                    return;
                 }
-                Opcode::OP_1SUB => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let new_num = script_num.num - 1.to_bigint().unwrap();
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
+                let if_stack_len = self.if_stack.len();
+                self.if_stack[if_stack_len - 1] = !self.if_stack[self.if_stack.len() - 1];
+            }
+            Opcode::OP_ENDIF => {
+                if self.if_stack.is_empty() {
+                    self.err_str = "unbalanced conditional".to_string();
+                    return;
                 }
-                Opcode::OP_2MUL => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let new_num = script_num.num * 2.to_bigint().unwrap();
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
+                self.if_stack.pop();
+            }
+            Opcode::OP_0 => {
+                self.stack.push(vec![]);
+            }
+            Opcode::OP_PUSHDATA1 | Opcode::OP_PUSHDATA2 | Opcode::OP_PUSHDATA4 => {
+                if self.require_minimal_push && !chunk.is_minimal_push() {
+                    self.err_str = "non-minimal push".to_string();
+                    return;
                 }
-                Opcode::OP_2DIV => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let new_num = script_num.num / 2.to_bigint().unwrap();
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
+                if let Some(buffer) = &chunk.buffer {
+                    self.stack.push(buffer.clone());
+                } else {
+                    self.err_str = "invalid pushdata".to_string();
                 }
-                Opcode::OP_NEGATE => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let new_num = -script_num.num;
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_1NEGATE => {
+                let script_num = ScriptNum::new((-1).to_bigint().unwrap());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_1 => {
+                let script_num = ScriptNum::new(1.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_2 => {
+                let script_num = ScriptNum::new(2.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_3 => {
+                let script_num = ScriptNum::new(3.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_4 => {
+                let script_num = ScriptNum::new(4.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_5 => {
+                let script_num = ScriptNum::new(5.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_6 => {
+                let script_num = ScriptNum::new(6.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_7 => {
+                let script_num = ScriptNum::new(7.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_8 => {
+                let script_num = ScriptNum::new(8.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_9 => {
+                let script_num = ScriptNum::new(9.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_10 => {
+                let script_num = ScriptNum::new(10.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_11 => {
+                let script_num = ScriptNum::new(11.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_12 => {
+                let script_num = ScriptNum::new(12.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_13 => {
+                let script_num = ScriptNum::new(13.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_14 => {
+                let script_num = ScriptNum::new(14.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_15 => {
+                let script_num = ScriptNum::new(15.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_16 => {
+                let script_num = ScriptNum::new(16.into());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_VERIFY => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_ABS => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let mut script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    if script_num.num < 0.to_bigint().unwrap() {
-                        script_num.num = -script_num.num;
-                    }
-                    self.stack.push(ScriptNum::new(script_num.num).to_buf());
+                let buf = self.stack.pop().unwrap();
+                if !ScriptInterpreter::cast_to_bool(&buf) {
+                    self.err_str = "VERIFY failed".to_string();
+                    return;
                 }
-                Opcode::OP_NOT => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let new_num = if script_num.num == 0.to_bigint().unwrap() {
-                        1.to_bigint().unwrap()
-                    } else {
-                        0.to_bigint().unwrap()
-                    };
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
-                }
-                Opcode::OP_0NOTEQUAL => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let new_num = if script_num.num == 0.to_bigint().unwrap() {
-                        0.to_bigint().unwrap()
-                    } else {
-                        1.to_bigint().unwrap()
-                    };
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
-                }
-                Opcode::OP_ADD => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let new_num = script_num1.num + script_num2.num;
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
-                }
-                Opcode::OP_SUB => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let new_num = script_num2.num - script_num1.num;
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
-                }
-                Opcode::OP_MUL => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let new_num = script_num1.num * script_num2.num;
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
-                }
-                Opcode::OP_DIV => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    if script_num1.num == 0.to_bigint().unwrap() {
-                        self.err_str = "division by zero".to_string();
-                        break;
-                    }
-                    let new_num = script_num2.num / script_num1.num;
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_RETURN => {
+                self.pc = self.script.chunks.len();
+                return;
+            }
+            Opcode::OP_TOALTSTACK => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_MOD => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    if script_num1.num == 0.to_bigint().unwrap() {
-                        self.err_str = "division by zero".to_string();
-                        break;
-                    }
-                    let new_num = script_num2.num % script_num1.num;
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
+                self.alt_stack.push(self.stack.pop().unwrap());
+            }
+            Opcode::OP_FROMALTSTACK => {
+                if self.alt_stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_LSHIFT => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    if script_num1.num < 0.to_bigint().unwrap() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let new_num = script_num2.num << script_num1.to_u32();
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
+                self.stack.push(self.alt_stack.pop().unwrap());
+            }
+            Opcode::OP_2DROP => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_RSHIFT => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    if script_num1.num < 0.to_bigint().unwrap() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let new_num = script_num2.num >> script_num1.to_u32();
-                    self.stack.push(ScriptNum::new(new_num).to_buf());
+                self.stack.pop();
+                self.stack.pop();
+            }
+            Opcode::OP_2DUP => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack[self.stack.len() - 2].clone();
+                let buf2 = self.stack[self.stack.len() - 1].clone();
+                self.stack.push(buf1);
+                self.stack.push(buf2);
+            }
+            Opcode::OP_3DUP => {
+                if self.stack.len() < 3 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack[self.stack.len() - 3].clone();
+                let buf2 = self.stack[self.stack.len() - 2].clone();
+                let buf3 = self.stack[self.stack.len() - 1].clone();
+                self.stack.push(buf1);
+                self.stack.push(buf2);
+                self.stack.push(buf3);
+            }
+            Opcode::OP_2OVER => {
+                if self.stack.len() < 4 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack[self.stack.len() - 4].clone();
+                let buf2 = self.stack[self.stack.len() - 3].clone();
+                self.stack.push(buf1);
+                self.stack.push(buf2);
+            }
+            Opcode::OP_2ROT => {
+                if self.stack.len() < 6 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack[self.stack.len() - 6].clone();
+                let buf2 = self.stack[self.stack.len() - 5].clone();
+                self.stack.remove(self.stack.len() - 6);
+                self.stack.remove(self.stack.len() - 5);
+                self.stack.push(buf1);
+                self.stack.push(buf2);
+            }
+            Opcode::OP_2SWAP => {
+                if self.stack.len() < 4 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack[self.stack.len() - 4].clone();
+                let buf2 = self.stack[self.stack.len() - 3].clone();
+                self.stack.remove(self.stack.len() - 4);
+                self.stack.remove(self.stack.len() - 3);
+                self.stack.push(buf1);
+                self.stack.push(buf2);
+            }
+            Opcode::OP_IFDUP => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_BOOLAND => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack.pop().unwrap();
-                    let buf2 = self.stack.pop().unwrap();
-                    let bool1 = ScriptInterpreter::cast_to_bool(&buf1);
-                    let bool2 = ScriptInterpreter::cast_to_bool(&buf2);
-                    self.stack
-                        .push(if bool1 && bool2 { vec![1] } else { vec![] });
-                }
-                Opcode::OP_BOOLOR => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf1 = self.stack.pop().unwrap();
-                    let buf2 = self.stack.pop().unwrap();
-                    let bool1 = ScriptInterpreter::cast_to_bool(&buf1);
-                    let bool2 = ScriptInterpreter::cast_to_bool(&buf2);
-                    self.stack
-                        .push(if bool1 || bool2 { vec![1] } else { vec![] });
-                }
-                Opcode::OP_NUMEQUAL => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    self.stack.push(if script_num1.num == script_num2.num {
-                        vec![1]
-                    } else {
-                        vec![]
-                    });
+                let buf = self.stack[self.stack.len() - 1].clone();
+                if ScriptInterpreter::cast_to_bool(&buf) {
+                    self.stack.push(buf);
                 }
-                Opcode::OP_NUMEQUALVERIFY => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    if script_num1.num != script_num2.num {
-                        self.err_str = "NUMEQUALVERIFY failed".to_string();
-                        break;
-                    }
+            }
+            Opcode::OP_DEPTH => {
+                let script_num = ScriptNum::new(self.stack.len().to_bigint().unwrap());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_DROP => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_NUMNOTEQUAL => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    self.stack.push(if script_num1.num != script_num2.num {
-                        vec![1]
-                    } else {
-                        vec![]
-                    });
+                self.stack.pop();
+            }
+            Opcode::OP_DUP => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_LESSTHAN => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    self.stack.push(if script_num2.num < script_num1.num {
-                        vec![1]
-                    } else {
-                        vec![]
-                    });
+                let buf = self.stack[self.stack.len() - 1].clone();
+                self.stack.push(buf);
+            }
+            Opcode::OP_NIP => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf = self.stack.pop().unwrap();
+                self.stack.pop();
+                self.stack.push(buf);
+            }
+            Opcode::OP_OVER => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_GREATERTHAN => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    self.stack.push(if script_num2.num > script_num1.num {
-                        vec![1]
-                    } else {
-                        vec![]
-                    });
+                let buf = self.stack[self.stack.len() - 2].clone();
+                self.stack.push(buf);
+            }
+            Opcode::OP_PICK => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.pop_num() else {
+                    return;
+                };
+                if (script_num.num < 0.to_bigint().unwrap())
+                    || (script_num.num >= self.stack.len().to_bigint().unwrap())
+                {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let num = script_num.to_u32() as usize;
+                if num >= self.stack.len() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf = self.stack[self.stack.len() - num - 1].clone();
+                self.stack.push(buf);
+            }
+            Opcode::OP_ROLL => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.pop_num() else {
+                    return;
+                };
+                if (script_num.num < 0.to_bigint().unwrap())
+                    || (script_num.num >= self.stack.len().to_bigint().unwrap())
+                {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let num = script_num.to_u32() as usize;
+                if num >= self.stack.len() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf = self.stack.remove(self.stack.len() - num - 1);
+                self.stack.push(buf);
+            }
+            Opcode::OP_ROT => {
+                if self.stack.len() < 3 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_LESSTHANOREQUAL => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    self.stack.push(if script_num2.num <= script_num1.num {
-                        vec![1]
-                    } else {
-                        vec![]
-                    });
+                let buf = self.stack.remove(self.stack.len() - 3);
+                self.stack.push(buf);
+            }
+            Opcode::OP_SWAP => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_GREATERTHANOREQUAL => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    self.stack.push(if script_num2.num >= script_num1.num {
-                        vec![1]
-                    } else {
-                        vec![]
-                    });
+                let buf = self.stack.remove(self.stack.len() - 2);
+                self.stack.push(buf);
+            }
+            Opcode::OP_TUCK => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_MIN => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    self.stack.push(if script_num2.num < script_num1.num {
-                        script_num2.to_buf()
-                    } else {
-                        script_num1.to_buf()
-                    });
+                let buf = self.stack[self.stack.len() - 1].clone();
+                self.stack.insert(self.stack.len() - 2, buf);
+            }
+            Opcode::OP_CAT => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack.pop().unwrap();
+                let buf2 = self.stack.pop().unwrap();
+                let mut new_buf = Vec::new();
+                new_buf.extend_from_slice(&buf2);
+                new_buf.extend_from_slice(&buf1);
+                self.stack.push(new_buf);
+            }
+            Opcode::OP_SUBSTR => {
+                if self.stack.len() < 3 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let script_num2_bn = script_num2.clone().num;
+                let script_num1_bn = script_num1.clone().num;
+                let buf = self.stack.pop().unwrap();
+                let buf_len = buf.len();
+                if script_num1_bn < 0.to_bigint().unwrap()
+                    || script_num2_bn < 0.to_bigint().unwrap()
+                    || script_num1_bn + script_num2_bn > buf_len.to_bigint().unwrap()
+                {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let start = script_num1.to_u32() as usize;
+                let len = script_num2.to_u32() as usize;
+                let new_buf = buf[start..start + len].to_vec();
+                self.stack.push(new_buf);
+            }
+            Opcode::OP_LEFT => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.pop_num() else {
+                    return;
+                };
+                let buf = self.stack.pop().unwrap();
+                let len_bn = script_num.clone().num;
+                if len_bn < 0.to_bigint().unwrap() || len_bn > buf.len().to_bigint().unwrap() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let len = script_num.to_u32() as usize;
+                let new_buf = buf[0..len].to_vec();
+                self.stack.push(new_buf);
+            }
+            Opcode::OP_RIGHT => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.pop_num() else {
+                    return;
+                };
+                let buf = self.stack.pop().unwrap();
+                let len_bn = script_num.clone().num;
+                if len_bn < 0.to_bigint().unwrap() || len_bn > buf.len().to_bigint().unwrap() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let len = script_num.to_u32() as usize;
+                let new_buf = buf[buf.len() - len..buf.len()].to_vec();
+                self.stack.push(new_buf);
+            }
+            Opcode::OP_SIZE => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let script_num =
+                    ScriptNum::new(self.stack[self.stack.len() - 1].len().to_bigint().unwrap());
+                self.stack.push(script_num.to_buf());
+            }
+            Opcode::OP_INVERT => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let mut buf = self.stack.pop().unwrap();
+                buf.iter_mut().for_each(|byte| *byte = !*byte);
+                self.stack.push(buf);
+            }
+            Opcode::OP_AND => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack.pop().unwrap();
+                let buf2 = self.stack.pop().unwrap();
+                let len1 = buf1.len();
+                let len2 = buf2.len();
+                if len1 != len2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let len = len1;
+                let mut new_buf = Vec::new();
+                for i in 0..len {
+                    new_buf.push(buf1[i] & buf2[i]);
+                }
+                self.stack.push(new_buf);
+            }
+            Opcode::OP_OR => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack.pop().unwrap();
+                let buf2 = self.stack.pop().unwrap();
+                let len1 = buf1.len();
+                let len2 = buf2.len();
+                if len1 != len2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let len = len1;
+                let mut new_buf = Vec::new();
+                for i in 0..len {
+                    new_buf.push(buf1[i] | buf2[i]);
+                }
+                self.stack.push(new_buf);
+            }
+            Opcode::OP_XOR => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack.pop().unwrap();
+                let buf2 = self.stack.pop().unwrap();
+                let len1 = buf1.len();
+                let len2 = buf2.len();
+                if len1 != len2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let len = len1;
+                let mut new_buf = Vec::new();
+                for i in 0..len {
+                    new_buf.push(buf1[i] ^ buf2[i]);
+                }
+                self.stack.push(new_buf);
+            }
+            Opcode::OP_EQUAL => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack.pop().unwrap();
+                let buf2 = self.stack.pop().unwrap();
+                let equal = buf1 == buf2;
+                self.stack.push(if equal { vec![1] } else { vec![] });
+            }
+            Opcode::OP_EQUALVERIFY => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
                 }
-                Opcode::OP_MAX => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num1 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_num2 = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    self.stack.push(if script_num2.num > script_num1.num {
-                        script_num2.to_buf()
-                    } else {
-                        script_num1.to_buf()
-                    });
+                let buf1 = self.stack.pop().unwrap();
+                let buf2 = self.stack.pop().unwrap();
+                if buf1 != buf2 {
+                    self.err_str = "EQUALVERIFY failed".to_string();
+                    return;
                 }
-                Opcode::OP_WITHIN => {
-                    // (x min max -- out)
-                    if self.stack.len() < 3 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_max = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_min = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let script_x = ScriptNum::from_buf(&self.stack.pop().unwrap());
-                    let min = script_min.num;
-                    let max = script_max.num;
-                    let x = script_x.num;
-                    self.stack
-                        .push(if x >= min && x < max { vec![1] } else { vec![] });
-                }
-                Opcode::OP_BLAKE3 => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf = self.stack.pop().unwrap();
-                    let hash = blake3_hash(&buf);
-                    self.stack.push(hash.to_vec());
+            }
+            Opcode::OP_1ADD => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.pop_num() else {
+                    return;
+                };
+                let new_num = script_num.num + 1.to_bigint().unwrap();
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_1SUB => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.pop_num() else {
+                    return;
+                };
+                let new_num = script_num.num - 1.to_bigint().unwrap();
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_2MUL => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.pop_num() else {
+                    return;
+                };
+                let new_num = script_num.num * 2.to_bigint().unwrap();
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_2DIV => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.pop_num() else {
+                    return;
+                };
+                let new_num = script_num.num / 2.to_bigint().unwrap();
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_NEGATE => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.pop_num() else {
+                    return;
+                };
+                let new_num = -script_num.num;
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_ABS => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(mut script_num) = self.pop_num() else {
+                    return;
+                };
+                if script_num.num < 0.to_bigint().unwrap() {
+                    script_num.num = -script_num.num;
+                }
+                self.stack.push(ScriptNum::new(script_num.num).to_buf());
+            }
+            Opcode::OP_NOT => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.pop_num() else {
+                    return;
+                };
+                let new_num = if script_num.num == 0.to_bigint().unwrap() {
+                    1.to_bigint().unwrap()
+                } else {
+                    0.to_bigint().unwrap()
+                };
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_0NOTEQUAL => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.pop_num() else {
+                    return;
+                };
+                let new_num = if script_num.num == 0.to_bigint().unwrap() {
+                    0.to_bigint().unwrap()
+                } else {
+                    1.to_bigint().unwrap()
+                };
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_ADD => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                let new_num = script_num1.num + script_num2.num;
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_SUB => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                let new_num = script_num2.num - script_num1.num;
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_MUL => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                let new_num = script_num1.num * script_num2.num;
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_DIV => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                if script_num1.num == 0.to_bigint().unwrap() {
+                    self.err_str = "division by zero".to_string();
+                    return;
+                }
+                let new_num = script_num2.num / script_num1.num;
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_MOD => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                if script_num1.num == 0.to_bigint().unwrap() {
+                    self.err_str = "division by zero".to_string();
+                    return;
+                }
+                let new_num = script_num2.num % script_num1.num;
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_LSHIFT => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                if script_num1.num < 0.to_bigint().unwrap() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let new_num = script_num2.num << script_num1.to_u32();
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_RSHIFT => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                if script_num1.num < 0.to_bigint().unwrap() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let new_num = script_num2.num >> script_num1.to_u32();
+                self.stack.push(ScriptNum::new(new_num).to_buf());
+            }
+            Opcode::OP_BOOLAND => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack.pop().unwrap();
+                let buf2 = self.stack.pop().unwrap();
+                let bool1 = ScriptInterpreter::cast_to_bool(&buf1);
+                let bool2 = ScriptInterpreter::cast_to_bool(&buf2);
+                self.stack
+                    .push(if bool1 && bool2 { vec![1] } else { vec![] });
+            }
+            Opcode::OP_BOOLOR => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf1 = self.stack.pop().unwrap();
+                let buf2 = self.stack.pop().unwrap();
+                let bool1 = ScriptInterpreter::cast_to_bool(&buf1);
+                let bool2 = ScriptInterpreter::cast_to_bool(&buf2);
+                self.stack
+                    .push(if bool1 || bool2 { vec![1] } else { vec![] });
+            }
+            Opcode::OP_NUMEQUAL => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                self.stack.push(if script_num1.num == script_num2.num {
+                    vec![1]
+                } else {
+                    vec![]
+                });
+            }
+            Opcode::OP_NUMEQUALVERIFY => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                if script_num1.num != script_num2.num {
+                    self.err_str = "NUMEQUALVERIFY failed".to_string();
+                    return;
                 }
-                Opcode::OP_DOUBLEBLAKE3 => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let buf = self.stack.pop().unwrap();
-                    let hash = double_blake3_hash(&buf);
-                    self.stack.push(hash.to_vec());
+            }
+            Opcode::OP_NUMNOTEQUAL => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                self.stack.push(if script_num1.num != script_num2.num {
+                    vec![1]
+                } else {
+                    vec![]
+                });
+            }
+            Opcode::OP_LESSTHAN => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                self.stack.push(if script_num2.num < script_num1.num {
+                    vec![1]
+                } else {
+                    vec![]
+                });
+            }
+            Opcode::OP_GREATERTHAN => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                self.stack.push(if script_num2.num > script_num1.num {
+                    vec![1]
+                } else {
+                    vec![]
+                });
+            }
+            Opcode::OP_LESSTHANOREQUAL => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                self.stack.push(if script_num2.num <= script_num1.num {
+                    vec![1]
+                } else {
+                    vec![]
+                });
+            }
+            Opcode::OP_GREATERTHANOREQUAL => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                self.stack.push(if script_num2.num >= script_num1.num {
+                    vec![1]
+                } else {
+                    vec![]
+                });
+            }
+            Opcode::OP_MIN => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                self.stack.push(if script_num2.num < script_num1.num {
+                    script_num2.to_buf()
+                } else {
+                    script_num1.to_buf()
+                });
+            }
+            Opcode::OP_MAX => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num1) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_num2) = self.pop_num() else {
+                    return;
+                };
+                self.stack.push(if script_num2.num > script_num1.num {
+                    script_num2.to_buf()
+                } else {
+                    script_num1.to_buf()
+                });
+            }
+            Opcode::OP_WITHIN => {
+                // (x min max -- out)
+                if self.stack.len() < 3 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_max) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_min) = self.pop_num() else {
+                    return;
+                };
+                let Some(script_x) = self.pop_num() else {
+                    return;
+                };
+                let min = script_min.num;
+                let max = script_max.num;
+                let x = script_x.num;
+                self.stack
+                    .push(if x >= min && x < max { vec![1] } else { vec![] });
+            }
+            Opcode::OP_BLAKE3 => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf = self.stack.pop().unwrap();
+                let hash = blake3_hash(&buf);
+                self.stack.push(hash.to_vec());
+            }
+            Opcode::OP_DOUBLEBLAKE3 => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let buf = self.stack.pop().unwrap();
+                let hash = double_blake3_hash(&buf);
+                self.stack.push(hash.to_vec());
+            }
+            Opcode::OP_CHECKSIG | Opcode::OP_CHECKSIGVERIFY => {
+                if self.stack.len() < 2 {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let pub_key_buf = self.stack.pop().unwrap();
+                if pub_key_buf.len() != PubKey::SIZE {
+                    self.err_str = "invalid public key length".to_string();
+                    return;
+                }
+                let sig_buf = self.stack.pop().unwrap();
+                if sig_buf.len() != TxSignature::SIZE {
+                    self.err_str = "invalid signature length".to_string();
+                    return;
+                }
+                let signature = TxSignature::from_buf(sig_buf);
+
+                let exec_script_buf = self.script.to_buf();
+
+                let pub_key_arr: [u8; PubKey::SIZE] =
+                    pub_key_buf.try_into().unwrap_or_else(|v: Vec<u8>| {
+                        panic!(
+                            "Expected a Vec of length {} but it was {}",
+                            PubKey::SIZE,
+                            v.len()
+                        )
+                    });
+
+                let success = self.tx.verify_with_cache(
+                    self.n_in,
+                    pub_key_arr,
+                    signature.unwrap(),
+                    exec_script_buf,
+                    self.value,
+                    self.hash_cache,
+                );
+
+                self.stack.push(if success { vec![1] } else { vec![] });
+                if opcode == OP["CHECKSIGVERIFY"] && !success {
+                    self.err_str = "CHECKSIGVERIFY failed".to_string();
+                    return;
                 }
-                Opcode::OP_CHECKSIG | Opcode::OP_CHECKSIGVERIFY => {
-                    if self.stack.len() < 2 {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
+            }
+            Opcode::OP_CHECKMULTISIG | Opcode::OP_CHECKMULTISIGVERIFY => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(ScriptNum { num: n_keys }) = self.pop_num() else {
+                    return;
+                };
+                if n_keys < BigInt::from(0) || n_keys > BigInt::from(16) {
+                    self.err_str = "invalid number of keys".to_string();
+                    return;
+                }
+                if self.stack.len() < (n_keys.to_usize().unwrap() + 1) {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let mut pub_keys: Vec<Vec<u8>> = Vec::new();
+                for _ in 0..n_keys.to_usize().unwrap() {
                     let pub_key_buf = self.stack.pop().unwrap();
                     if pub_key_buf.len() != PubKey::SIZE {
                         self.err_str = "invalid public key length".to_string();
                         break;
                     }
+                    pub_keys.push(pub_key_buf);
+                }
+                let Some(ScriptNum { num: n_sigs }) = self.pop_num() else {
+                    return;
+                };
+                if n_sigs < BigInt::from(0) || n_sigs > n_keys {
+                    self.err_str = "invalid number of signatures".to_string();
+                    return;
+                }
+                if self.stack.len() < n_sigs.to_usize().unwrap() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let mut sigs: Vec<Vec<u8>> = Vec::new();
+                for _ in 0..n_sigs.to_usize().unwrap() {
                     let sig_buf = self.stack.pop().unwrap();
                     if sig_buf.len() != TxSignature::SIZE {
                         self.err_str = "invalid signature length".to_string();
                         break;
                     }
-                    let signature = TxSignature::from_buf(sig_buf);
-
-                    let exec_script_buf = self.script.to_buf();
-
-                    let pub_key_arr: [u8; PubKey::SIZE] =
-                        pub_key_buf.try_into().unwrap_or_else(|v: Vec<u8>| {
-                            panic!(
-                                "Expected a Vec of length {} but it was {}",
-                                PubKey::SIZE,
-                                v.len()
-                            )
-                        });
-
-                    let success = self.tx.verify_with_cache(
-                        self.n_in,
-                        pub_key_arr,
-                        signature.unwrap(),
-                        exec_script_buf,
-                        self.value,
-                        self.hash_cache,
-                    );
-
-                    self.stack.push(if success { vec![1] } else { vec![] });
-                    if opcode == OP["CHECKSIGVERIFY"] && !success {
-                        self.err_str = "CHECKSIGVERIFY failed".to_string();
-                        break;
-                    }
+                    sigs.push(sig_buf);
                 }
-                Opcode::OP_CHECKMULTISIG | Opcode::OP_CHECKMULTISIGVERIFY => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let n_keys = ScriptNum::from_buf(&self.stack.pop().unwrap()).num;
-                    if n_keys < BigInt::from(0) || n_keys > BigInt::from(16) {
-                        self.err_str = "invalid number of keys".to_string();
-                        break;
-                    }
-                    if self.stack.len() < (n_keys.to_usize().unwrap() + 1) {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let mut pub_keys: Vec<Vec<u8>> = Vec::new();
-                    for _ in 0..n_keys.to_usize().unwrap() {
-                        let pub_key_buf = self.stack.pop().unwrap();
-                        if pub_key_buf.len() != PubKey::SIZE {
-                            self.err_str = "invalid public key length".to_string();
-                            break;
-                        }
-                        pub_keys.push(pub_key_buf);
-                    }
-                    let n_sigs = ScriptNum::from_buf(&self.stack.pop().unwrap()).num;
-                    if n_sigs < BigInt::from(0) || n_sigs > n_keys {
-                        self.err_str = "invalid number of signatures".to_string();
-                        break;
-                    }
-                    if self.stack.len() < n_sigs.to_usize().unwrap() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let mut sigs: Vec<Vec<u8>> = Vec::new();
-                    for _ in 0..n_sigs.to_usize().unwrap() {
-                        let sig_buf = self.stack.pop().unwrap();
-                        if sig_buf.len() != TxSignature::SIZE {
-                            self.err_str = "invalid signature length".to_string();
+                let exec_script_buf = self.script.to_buf();
+
+                let mut matched_sigs = 0;
+                for sig in sigs {
+                    for j in 0..pub_keys.len() {
+                        let success = self.tx.verify_with_cache(
+                            self.n_in,
+                            pub_keys[j][..PubKey::SIZE].try_into().unwrap(),
+                            TxSignature::from_buf(sig.clone()).unwrap(),
+                            exec_script_buf.clone(),
+                            self.value,
+                            self.hash_cache,
+                        );
+                        if success {
+                            matched_sigs += 1;
+                            pub_keys.remove(j); // Remove the matched public key
                             break;
                         }
-                        sigs.push(sig_buf);
-                    }
-                    let exec_script_buf = self.script.to_buf();
-
-                    let mut matched_sigs = 0;
-                    for sig in sigs {
-                        for j in 0..pub_keys.len() {
-                            let success = self.tx.verify_with_cache(
-                                self.n_in,
-                                pub_keys[j][..PubKey::SIZE].try_into().unwrap(),
-                                TxSignature::from_buf(sig.clone()).unwrap(),
-                                exec_script_buf.clone(),
-                                self.value,
-                                self.hash_cache,
-                            );
-                            if success {
-                                matched_sigs += 1;
-                                pub_keys.remove(j); // Remove the matched public key
-                                break;
-                            }
-                        }
-                    }
-                    let success = matched_sigs == n_sigs.to_usize().unwrap();
-
-                    self.stack.push(if success { vec![1] } else { vec![] });
-                    if opcode == OP["CHECKMULTISIGVERIFY"] && !success {
-                        self.err_str = "CHECKMULTISIGVERIFY failed".to_string();
-                        break;
                     }
                 }
-                Opcode::OP_CHECKLOCKABSVERIFY => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(self.stack.last().unwrap());
-                    if script_num.num < 0.into() {
-                        self.err_str = "negative lockabs".to_string();
-                        break;
-                    }
-                    if self.tx.lock_abs.to_bigint().unwrap() < script_num.num {
-                        self.err_str = "lockabs requirement not met".to_string();
-                        break;
-                    }
+                let success = matched_sigs == n_sigs.to_usize().unwrap();
+
+                self.stack.push(if success { vec![1] } else { vec![] });
+                if opcode == OP["CHECKMULTISIGVERIFY"] && !success {
+                    self.err_str = "CHECKMULTISIGVERIFY failed".to_string();
+                    return;
                 }
-                Opcode::OP_CHECKLOCKRELVERIFY => {
-                    if self.stack.is_empty() {
-                        self.err_str = "invalid stack operation".to_string();
-                        break;
-                    }
-                    let script_num = ScriptNum::from_buf(self.stack.last().unwrap());
-                    if script_num.num < 0.into() {
-                        self.err_str = "negative lockrel".to_string();
-                        break;
-                    }
-                    let tx_input = &self.tx.inputs[self.n_in];
-                    if tx_input.lock_rel.to_bigint().unwrap() < script_num.num {
-                        self.err_str = "lockrel requirement not met".to_string();
-                        break;
-                    }
+            }
+            Opcode::OP_CHECKLOCKABSVERIFY => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.last_num() else {
+                    return;
+                };
+                if script_num.num < 0.into() {
+                    self.err_str = "negative lockabs".to_string();
+                    return;
+                }
+                if self.tx.lock_abs.to_bigint().unwrap() < script_num.num {
+                    self.err_str = "lockabs requirement not met".to_string();
+                    return;
                 }
-                _ => {
-                    self.err_str = "invalid opcode".to_string();
-                    break;
+            }
+            Opcode::OP_CHECKLOCKRELVERIFY => {
+                if self.stack.is_empty() {
+                    self.err_str = "invalid stack operation".to_string();
+                    return;
+                }
+                let Some(script_num) = self.last_num() else {
+                    return;
+                };
+                if script_num.num < 0.into() {
+                    self.err_str = "negative lockrel".to_string();
+                    return;
+                }
+                let tx_input = &self.tx.inputs[self.n_in];
+                if tx_input.lock_rel.to_bigint().unwrap() < script_num.num {
+                    self.err_str = "lockrel requirement not met".to_string();
+                    return;
                 }
             }
+            _ => {
+                self.err_str = "invalid opcode".to_string();
+                return;
+            }
+        }
 
-            self.pc += 1;
+        self.pc += 1;
+    }
+
+    // runs the next chunk, or returns none if the script has finished. call
+    // eval_script afterwards to run the rest and get the result.
+    pub fn step(&mut self) -> Option<ScriptStep> {
+        if self.is_finished() {
+            return None;
+        }
+        let pc = self.pc;
+        let chunk = self.script.chunks[pc].clone();
+        let is_executed = self.is_chunk_executed();
+        let stack_before = self.stack.clone();
+        self.exec_chunk();
+        let step = ScriptStep {
+            pc,
+            chunk,
+            is_executed,
+            stack_before,
+            stack_after: self.stack.clone(),
+            alt_stack: self.alt_stack.clone(),
+            if_stack: self.if_stack.clone(),
+            err_str: self.err_str.clone(),
+        };
+        if let Some(trace) = &mut self.trace {
+            trace(&step);
+        }
+        Some(step)
+    }
+
+    pub fn eval_script(&mut self) -> bool {
+        while !self.is_finished() {
+            if self.trace.is_some() {
+                self.step();
+            } else {
+                self.exec_chunk();
+            }
         }
         if !self.err_str.is_empty() {
             if !self.stack.is_empty() {
@@ -1335,6 +1631,8 @@ mod tests {
             expected_return_value: String,
             expected_success: bool,
             expected_error: String,
+            #[serde(default)]
+            require_minimal: bool,
         }
 
         #[derive(Deserialize)]
@@ -1344,8 +1642,19 @@ mod tests {
 
         #[test]
         fn test_vectors() {
-            let file = fs::read_to_string("./test_vectors/script_interpreter.json")
-                .expect("Failed to read JSON file");
+            run_vectors("./test_vectors/script_interpreter.json");
+        }
+
+        // the minimal push and number rules are not in the typescript
+        // interpreter, so their vectors are not in the shared file
+        #[test]
+        fn test_vectors_minimal() {
+            run_vectors("./test_vectors/script_interpreter_minimal.json");
+        }
+
+        #[allow(clippy::needless_borrows_for_generic_args)]
+        fn run_vectors(path: &str) {
+            let file = fs::read_to_string(path).expect("Failed to read JSON file");
             let test_scripts: TestScripts =
                 serde_json::from_str(&file).expect("Failed to parse JSON file");
 
@@ -1364,6 +1673,8 @@ mod tests {
                 let mut hash_cache = HashCache::new();
                 let mut script_interpreter =
                     ScriptInterpreter::from_script_tx(script, tx, 0, &mut hash_cache);
+                script_interpreter.require_minimal_push = test_script.require_minimal;
+                script_interpreter.require_minimal_num = test_script.require_minimal;
                 script_interpreter.eval_script();
                 assert_eq!(
                     script_interpreter.err_str, test_script.expected_error,
@@ -1385,4 +1696,214 @@ mod tests {
             }
         }
     }
+
+    mod step_tests {
+        use super::*;
+
+        fn interpreter<'a>(script: &str, hash_cache: &'a mut HashCache) -> ScriptInterpreter<'a> {
+            let tx = Tx::new(0, Vec::new(), Vec::new(), 0);
+            let script = Script::from_strict_str(script).unwrap();
+            ScriptInterpreter::from_script_tx(script, tx, 0, hash_cache)
+        }
+
+        #[test]
+        fn test_step() {
+            let mut hash_cache = HashCache::new();
+            let mut script_interpreter = interpreter("1 2 ADD 3 EQUAL", &mut hash_cache);
+            let mut stacks = Vec::new();
+            while let Some(step) = script_interpreter.step() {
+                assert_eq!(step.pc, stacks.len());
+                assert!(step.is_executed);
+                assert_eq!(step.err_str, "");
+                stacks.push((step.stack_before, step.stack_after));
+            }
+            assert_eq!(
+                stacks,
+                vec![
+                    (vec![], vec![vec![1]]),
+                    (vec![vec![1]], vec![vec![1], vec![2]]),
+                    (vec![vec![1], vec![2]], vec![vec![3]]),
+                    (vec![vec![3]], vec![vec![3], vec![3]]),
+                    (vec![vec![3], vec![3]], vec![vec![1]]),
+                ]
+            );
+            assert!(script_interpreter.is_finished());
+            assert!(script_interpreter.eval_script());
+        }
+
+        #[test]
+        fn test_step_then_eval() {
+            let mut hash_cache = HashCache::new();
+            let mut script_interpreter =
+                interpreter("0 IF 2 ELSE 3 TOALTSTACK ENDIF 1", &mut hash_cache);
+            let step = script_interpreter.step().unwrap();
+            assert_eq!(step.chunk.opcode, Opcode::OP_0);
+            let step = script_interpreter.step().unwrap();
+            assert_eq!(step.if_stack, vec![false]);
+            let step = script_interpreter.step().unwrap();
+            assert_eq!(step.chunk.opcode, Opcode::OP_2);
+            assert!(!step.is_executed);
+            assert_eq!(step.stack_after, Vec::<Vec<u8>>::new());
+            let step = script_interpreter.step().unwrap();
+            assert_eq!(step.if_stack, vec![true]);
+
+            // the rest runs as usual
+            assert!(script_interpreter.eval_script());
+            assert_eq!(script_interpreter.alt_stack, vec![vec![3]]);
+            assert_eq!(script_interpreter.if_stack, Vec::<bool>::new());
+            assert!(script_interpreter.step().is_none());
+        }
+
+        #[test]
+        fn test_trace() {
+            let mut steps = Vec::new();
+            let mut hash_cache = HashCache::new();
+            let mut script_interpreter =
+                interpreter("1 TOALTSTACK 1 VERIFY VERIFY 2", &mut hash_cache);
+            script_interpreter.set_trace(|step| steps.push(step.clone()));
+            assert!(!script_interpreter.eval_script());
+            assert_eq!(script_interpreter.err_str, "invalid stack operation");
+            drop(script_interpreter);
+
+            // stops at the error, so the last chunk is never run
+            let pcs: Vec<usize> = steps.iter().map(|step| step.pc).collect();
+            assert_eq!(pcs, vec![0, 1, 2, 3, 4]);
+            assert_eq!(steps[1].alt_stack, vec![vec![1]]);
+            assert_eq!(steps[3].err_str, "");
+            assert_eq!(steps[4].err_str, "invalid stack operation");
+        }
+
+        #[test]
+        fn test_return_stops() {
+            let mut hash_cache = HashCache::new();
+            let mut script_interpreter = interpreter("1 RETURN 0", &mut hash_cache);
+            assert!(script_interpreter.step().is_some());
+            assert!(script_interpreter.step().is_some());
+            assert!(script_interpreter.step().is_none());
+            assert!(script_interpreter.eval_script());
+        }
+    }
+
+    mod limit_tests {
+        use super::*;
+
+        // the success, the limit error and the pc the script stopped at
+        fn eval(
+            script: &str,
+            stack: Vec<Vec<u8>>,
+            limits: ScriptLimits,
+        ) -> (bool, Option<ScriptLimitError>, usize) {
+            let tx = Tx::new(0, Vec::new(), Vec::new(), 0);
+            let script = Script::from_strict_str(script).unwrap();
+            let mut hash_cache = HashCache::new();
+            let mut script_interpreter =
+                ScriptInterpreter::from_output_script_tx(script, tx, 0, stack, 0, &mut hash_cache);
+            script_interpreter.limits = limits;
+            let success = script_interpreter.eval_script();
+            (
+                success,
+                script_interpreter.limit_error,
+                script_interpreter.pc,
+            )
+        }
+
+        #[test]
+        fn test_script_size() {
+            let limits = ScriptLimits {
+                max_script_size: 3,
+                ..ScriptLimits::DEFAULT
+            };
+            assert_eq!(eval("1 1 EQUAL", vec![], limits.clone()), (true, None, 3));
+            assert_eq!(
+                eval("1 1 EQUAL 1", vec![], limits),
+                (false, Some(ScriptLimitError::ScriptSize), 0)
+            );
+        }
+
+        #[test]
+        fn test_op_count() {
+            let limits = ScriptLimits {
+                max_op_count: 3,
+                ..ScriptLimits::DEFAULT
+            };
+            assert_eq!(
+                eval("1 DUP DUP DROP", vec![], limits.clone()),
+                (true, None, 4)
+            );
+            // pushes are not counted
+            assert_eq!(
+                eval("1 2 3 4 5 6 0xffff", vec![], limits.clone()),
+                (true, None, 7)
+            );
+            // ops in branches that are not taken are counted
+            assert_eq!(
+                eval("0 IF DUP DUP ENDIF 1", vec![], limits),
+                (false, Some(ScriptLimitError::OpCount), 4)
+            );
+        }
+
+        #[test]
+        fn test_stack_size() {
+            let limits = ScriptLimits {
+                max_stack_size: 3,
+                ..ScriptLimits::DEFAULT
+            };
+            assert_eq!(eval("1 1 1", vec![], limits.clone()), (true, None, 3));
+            assert_eq!(
+                eval("1 1 1 1", vec![], limits.clone()),
+                (false, Some(ScriptLimitError::StackSize), 4)
+            );
+            // the alt stack counts too
+            assert_eq!(
+                eval("1 TOALTSTACK 1 1 1", vec![], limits.clone()),
+                (false, Some(ScriptLimitError::StackSize), 5)
+            );
+            // and so does the stack the script starts with
+            assert_eq!(
+                eval("1", vec![vec![]; 4], limits),
+                (false, Some(ScriptLimitError::StackSize), 0)
+            );
+        }
+
+        #[test]
+        fn test_element_size() {
+            let limits = ScriptLimits {
+                max_element_size: 4,
+                ..ScriptLimits::DEFAULT
+            };
+            assert_eq!(
+                eval("0xffff DUP CAT", vec![], limits.clone()),
+                (true, None, 3)
+            );
+            assert_eq!(
+                eval("0xffff DUP CAT DUP CAT", vec![], limits.clone()),
+                (false, Some(ScriptLimitError::ElementSize), 5)
+            );
+            assert_eq!(
+                eval("1", vec![vec![0; 5], vec![]], limits),
+                (false, Some(ScriptLimitError::ElementSize), 0)
+            );
+
+            // CAT can not grow an element past the default limit: 1_000 bytes
+            // doubled 7 times is 128_000 bytes
+            let script = "0x".to_owned() + &"ff".repeat(1_000) + &" DUP CAT".repeat(7);
+            assert_eq!(
+                eval(&script, vec![], ScriptLimits::DEFAULT),
+                (false, Some(ScriptLimitError::ElementSize), 15)
+            );
+        }
+
+        #[test]
+        fn test_err_str() {
+            let tx = Tx::new(0, Vec::new(), Vec::new(), 0);
+            let script = Script::from_strict_str("1 DUP").unwrap();
+            let mut hash_cache = HashCache::new();
+            let mut script_interpreter =
+                ScriptInterpreter::from_script_tx(script, tx, 0, &mut hash_cache);
+            script_interpreter.limits.max_op_count = 0;
+            assert!(!script_interpreter.eval_script());
+            assert_eq!(script_interpreter.err_str, "too many ops");
+            assert_eq!(script_interpreter.n_op_count, 1);
+        }
+    }
 }
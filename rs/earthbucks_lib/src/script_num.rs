@@ -3,6 +3,7 @@
 extern crate num_bigint;
 extern crate num_traits;
 use crate::buf::EbxBuf;
+use crate::error::EbxError;
 use num_bigint::BigInt;
 use num_traits::ToPrimitive;
 use num_traits::Zero;
@@ -50,6 +51,25 @@ impl ScriptNum {
         ScriptNum { num }
     }
 
+    // the shortest encoding, which is the one to_buf gives: zero is empty,
+    // and a leading 0x00 or 0xff is only there to set the sign
+    pub fn is_minimal_buf(buffer: &[u8]) -> bool {
+        match buffer {
+            [0x00] => false,
+            [0x00, next, ..] => next & 0x80 != 0,
+            [0xff, next, ..] => next & 0x80 == 0,
+            _ => true,
+        }
+    }
+
+    pub fn from_minimal_buf(buffer: &[u8]) -> Result<Self, EbxError> {
+        if !ScriptNum::is_minimal_buf(buffer) {
+            return Err(EbxError::NonMinimalEncodingError { source: None });
+        }
+        Ok(ScriptNum::from_buf(buffer))
+    }
+
+    #[allow(clippy::manual_div_ceil)]
     pub fn to_buf(&self) -> Vec<u8> {
         match self.num.cmp(&Zero::zero()) {
             std::cmp::Ordering::Equal => vec![],
@@ -62,7 +82,9 @@ impl ScriptNum {
                 bytes
             }
             std::cmp::Ordering::Less => {
-                let bit_length = self.num.bits();
+                // -128 fits in one byte, but -129 needs two: the bits of
+                // -num - 1, plus the sign bit
+                let bit_length = (-&self.num - 1u32).bits() + 1;
                 let byte_length = (bit_length + 7) / 8;
                 (BigInt::from(2).pow((byte_length * 8) as u32) + &self.num)
                     .to_bytes_be()
@@ -150,4 +172,49 @@ mod tests {
         let bytes = script_num.to_buf();
         assert_eq!(bytes, vec![0, 128]); // 128 in hexadecimal is 80, but we expect an extra '00' at the front
     }
+
+    #[test]
+    fn test_to_buf_negative() {
+        // the sign bit needs its own byte from -129 down to -256, and likewise
+        // at every byte boundary
+        let test_cases = [
+            ("-1", "ff"),
+            ("-127", "81"),
+            ("-128", "80"),
+            ("-129", "ff7f"),
+            ("-255", "ff01"),
+            ("-256", "ff00"),
+            ("-257", "feff"),
+            ("-32768", "8000"),
+            ("-32769", "ff7fff"),
+            ("-8388608", "800000"),
+            ("-8388609", "ff7fffff"),
+        ];
+        for (dec, hex) in test_cases {
+            let script_num = ScriptNum::from_strict_str(dec);
+            assert_eq!(script_num.to_strict_hex(), hex, "{}", dec);
+            assert_eq!(ScriptNum::from_strict_hex(hex).num, script_num.num);
+            assert!(ScriptNum::is_minimal_buf(&script_num.to_buf()));
+        }
+    }
+
+    #[test]
+    fn test_is_minimal_buf() {
+        let minimal = ["", "01", "7f", "0080", "ff", "80", "ff7f", "0100"];
+        for hex in minimal {
+            let buf = Vec::<u8>::from_strict_hex(hex).unwrap();
+            assert!(ScriptNum::is_minimal_buf(&buf), "{}", hex);
+            assert_eq!(ScriptNum::from_buf(&buf).to_strict_hex(), hex);
+            assert!(ScriptNum::from_minimal_buf(&buf).is_ok());
+        }
+        let non_minimal = ["00", "0001", "0000", "ff80", "ffff", "00007f"];
+        for hex in non_minimal {
+            let buf = Vec::<u8>::from_strict_hex(hex).unwrap();
+            assert!(!ScriptNum::is_minimal_buf(&buf), "{}", hex);
+            assert_eq!(
+                ScriptNum::from_minimal_buf(&buf).unwrap_err().to_string(),
+                "non-minimal encoding"
+            );
+        }
+    }
 }
@@ -0,0 +1,349 @@
+use crate::error::EbxError;
+use crate::opcode::Opcode;
+use crate::pub_key::PubKey;
+use crate::script::Script;
+use crate::script_chunk::ScriptChunk;
+use crate::script_num::ScriptNum;
+use crate::tx_signature::TxSignature;
+use num_traits::ToPrimitive;
+
+// the standard scripts as patterns of opcodes and typed placeholders. one
+// definition gives both the builder, which fills in the placeholders, and the
+// matcher, which checks a script and extracts them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptTemplateItem {
+    Op(u8),
+    Pkh,     // 32 bytes
+    LockNum, // a u32, pushed the shortest way, e.g. 6 with OP_6
+    PubKey,  // PubKey::SIZE bytes
+    Sig,     // TxSignature::SIZE bytes
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptTemplateValue {
+    Pkh([u8; 32]),
+    LockNum(u32),
+    PubKey([u8; PubKey::SIZE]),
+    Sig([u8; TxSignature::SIZE]),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptTemplate {
+    pub name: &'static str,
+    pub items: &'static [ScriptTemplateItem],
+}
+
+use ScriptTemplateItem as Item;
+
+impl ScriptTemplate {
+    pub const PKH_OUTPUT: ScriptTemplate = ScriptTemplate {
+        name: "pkh output",
+        items: &[
+            Item::Op(Opcode::OP_DUP),
+            Item::Op(Opcode::OP_DOUBLEBLAKE3),
+            Item::Pkh,
+            Item::Op(Opcode::OP_EQUALVERIFY),
+            Item::Op(Opcode::OP_CHECKSIG),
+        ],
+    };
+
+    pub const PKH_INPUT: ScriptTemplate = ScriptTemplate {
+        name: "pkh input",
+        items: &[Item::Sig, Item::PubKey],
+    };
+
+    // pkh, or anyone after lock num blocks
+    pub const PKHX_OUTPUT: ScriptTemplate = ScriptTemplate {
+        name: "pkhx output",
+        items: &[
+            Item::Op(Opcode::OP_IF),
+            Item::Op(Opcode::OP_DUP),
+            Item::Op(Opcode::OP_DOUBLEBLAKE3),
+            Item::Pkh,
+            Item::Op(Opcode::OP_EQUALVERIFY),
+            Item::Op(Opcode::OP_CHECKSIG),
+            Item::Op(Opcode::OP_ELSE),
+            Item::LockNum,
+            Item::Op(Opcode::OP_CHECKLOCKRELVERIFY),
+            Item::Op(Opcode::OP_DROP),
+            Item::Op(Opcode::OP_1),
+            Item::Op(Opcode::OP_ENDIF),
+        ],
+    };
+
+    pub const UNEXPIRED_PKHX_INPUT: ScriptTemplate = ScriptTemplate {
+        name: "unexpired pkhx input",
+        items: &[Item::Sig, Item::PubKey, Item::Op(Opcode::OP_1)],
+    };
+
+    pub const EXPIRED_PKHX_INPUT: ScriptTemplate = ScriptTemplate {
+        name: "expired pkhx input",
+        items: &[Item::Op(Opcode::OP_0)],
+    };
+
+    // pkh, or the recovery pkh after the first lock num blocks, or anyone
+    // after the second
+    pub const PKHXR_OUTPUT: ScriptTemplate = ScriptTemplate {
+        name: "pkhxr output",
+        items: &[
+            Item::Op(Opcode::OP_IF),
+            Item::Op(Opcode::OP_DUP),
+            Item::Op(Opcode::OP_DOUBLEBLAKE3),
+            Item::Pkh,
+            Item::Op(Opcode::OP_EQUALVERIFY),
+            Item::Op(Opcode::OP_CHECKSIG),
+            Item::Op(Opcode::OP_ELSE),
+            Item::Op(Opcode::OP_IF),
+            Item::LockNum,
+            Item::Op(Opcode::OP_CHECKLOCKRELVERIFY),
+            Item::Op(Opcode::OP_DROP),
+            Item::Op(Opcode::OP_DUP),
+            Item::Op(Opcode::OP_DOUBLEBLAKE3),
+            Item::Pkh,
+            Item::Op(Opcode::OP_EQUALVERIFY),
+            Item::Op(Opcode::OP_CHECKSIG),
+            Item::Op(Opcode::OP_ELSE),
+            Item::LockNum,
+            Item::Op(Opcode::OP_CHECKLOCKRELVERIFY),
+            Item::Op(Opcode::OP_DROP),
+            Item::Op(Opcode::OP_1),
+            Item::Op(Opcode::OP_ENDIF),
+            Item::Op(Opcode::OP_ENDIF),
+        ],
+    };
+
+    pub const UNEXPIRED_PKHXR_INPUT: ScriptTemplate = ScriptTemplate {
+        name: "unexpired pkhxr input",
+        items: &[Item::Sig, Item::PubKey, Item::Op(Opcode::OP_1)],
+    };
+
+    pub const RECOVERY_PKHXR_INPUT: ScriptTemplate = ScriptTemplate {
+        name: "recovery pkhxr input",
+        items: &[
+            Item::Sig,
+            Item::PubKey,
+            Item::Op(Opcode::OP_1),
+            Item::Op(Opcode::OP_0),
+        ],
+    };
+
+    pub const EXPIRED_PKHXR_INPUT: ScriptTemplate = ScriptTemplate {
+        name: "expired pkhxr input",
+        items: &[Item::Op(Opcode::OP_0), Item::Op(Opcode::OP_0)],
+    };
+
+    // values are given in the order of the placeholders
+    pub fn build(&self, values: &[ScriptTemplateValue]) -> Result<Script, EbxError> {
+        let mut values = values.iter();
+        let mut chunks = Vec::new();
+        for item in self.items {
+            let chunk = match item {
+                Item::Op(opcode) => ScriptChunk::new(*opcode, None),
+                _ => self.placeholder_chunk(item, values.next())?,
+            };
+            chunks.push(chunk);
+        }
+        if values.next().is_some() {
+            return Err(self.values_error());
+        }
+        Ok(Script::new(chunks))
+    }
+
+    // the values of the placeholders, or none if the script does not match
+    pub fn match_script(&self, script: &Script) -> Option<Vec<ScriptTemplateValue>> {
+        if script.chunks.len() != self.items.len() {
+            return None;
+        }
+        let mut values = Vec::new();
+        for (item, chunk) in self.items.iter().zip(&script.chunks) {
+            let value = match item {
+                Item::Op(opcode) => {
+                    if *chunk != ScriptChunk::new(*opcode, None) {
+                        return None;
+                    }
+                    continue;
+                }
+                Item::Pkh => ScriptTemplateValue::Pkh(Self::pushed_array(chunk)?),
+                Item::LockNum => {
+                    let data = chunk.get_data().ok()?;
+                    let num = ScriptNum::from_buf(&data).num.to_u32()?;
+                    if *chunk != Self::lock_num_chunk(num) {
+                        return None;
+                    }
+                    ScriptTemplateValue::LockNum(num)
+                }
+                Item::PubKey => ScriptTemplateValue::PubKey(Self::pushed_array(chunk)?),
+                Item::Sig => ScriptTemplateValue::Sig(Self::pushed_array(chunk)?),
+            };
+            values.push(value);
+        }
+        Some(values)
+    }
+
+    pub fn is_match(&self, script: &Script) -> bool {
+        self.match_script(script).is_some()
+    }
+
+    fn placeholder_chunk(
+        &self,
+        item: &ScriptTemplateItem,
+        value: Option<&ScriptTemplateValue>,
+    ) -> Result<ScriptChunk, EbxError> {
+        match (item, value) {
+            (Item::Pkh, Some(ScriptTemplateValue::Pkh(pkh))) => {
+                Ok(ScriptChunk::from_data(pkh.to_vec()))
+            }
+            (Item::LockNum, Some(ScriptTemplateValue::LockNum(num))) => {
+                Ok(Self::lock_num_chunk(*num))
+            }
+            (Item::PubKey, Some(ScriptTemplateValue::PubKey(pub_key))) => {
+                Ok(ScriptChunk::from_data(pub_key.to_vec()))
+            }
+            (Item::Sig, Some(ScriptTemplateValue::Sig(sig))) => {
+                Ok(ScriptChunk::from_data(sig.to_vec()))
+            }
+            _ => Err(self.values_error()),
+        }
+    }
+
+    // small numbers are pushed with OP_1 to OP_16, larger ones as data
+    fn lock_num_chunk(num: u32) -> ScriptChunk {
+        ScriptChunk::from_data(ScriptNum::from_u32(num).to_buf())
+    }
+
+    // data of exactly N bytes, pushed the shortest way
+    fn pushed_array<const N: usize>(chunk: &ScriptChunk) -> Option<[u8; N]> {
+        let buffer = chunk.buffer.as_ref()?;
+        let array: [u8; N] = buffer.as_slice().try_into().ok()?;
+        if !chunk.is_minimal_push() {
+            return None;
+        }
+        Some(array)
+    }
+
+    fn values_error(&self) -> EbxError {
+        EbxError::GenericError {
+            source: None,
+            message: format!("values do not match placeholders of {}", self.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ScriptTemplateValue as Value;
+
+    #[test]
+    fn test_build_and_match() {
+        let cases = [
+            (ScriptTemplate::PKH_OUTPUT, vec![Value::Pkh([1; 32])]),
+            (
+                ScriptTemplate::PKH_INPUT,
+                vec![Value::Sig([2; 65]), Value::PubKey([3; 33])],
+            ),
+            (
+                ScriptTemplate::PKHX_OUTPUT,
+                vec![Value::Pkh([1; 32]), Value::LockNum(12960)],
+            ),
+            (
+                ScriptTemplate::PKHXR_OUTPUT,
+                vec![
+                    Value::Pkh([1; 32]),
+                    Value::LockNum(4),
+                    Value::Pkh([2; 32]),
+                    Value::LockNum(6),
+                ],
+            ),
+            (ScriptTemplate::EXPIRED_PKHXR_INPUT, vec![]),
+        ];
+        for (template, values) in cases {
+            let script = template.build(&values).unwrap();
+            assert_eq!(
+                template.match_script(&script),
+                Some(values),
+                "{}",
+                template.name
+            );
+            let script = Script::from_buf(&script.to_buf()).unwrap();
+            assert!(template.is_match(&script));
+        }
+    }
+
+    #[test]
+    fn test_lock_num() {
+        let template = ScriptTemplate::PKHX_OUTPUT;
+        for num in [0, 1, 16, 17, 127, 128, 12960, u32::MAX] {
+            let values = vec![Value::Pkh([1; 32]), Value::LockNum(num)];
+            let script = template.build(&values).unwrap();
+            assert_eq!(template.match_script(&script), Some(values));
+        }
+        let script = template
+            .build(&[Value::Pkh([1; 32]), Value::LockNum(6)])
+            .unwrap();
+        assert_eq!(script.chunks[7], ScriptChunk::new(Opcode::OP_6, None));
+
+        // not the shortest push, negative, and too large for a u32
+        for data in [
+            vec![0x00, 0x06],
+            vec![0xff],
+            vec![0x01, 0x00, 0x00, 0x00, 0x00],
+        ] {
+            let mut script = script.clone();
+            script.chunks[7] = ScriptChunk::from_data(data);
+            assert!(!template.is_match(&script));
+        }
+        let mut script = script.clone();
+        script.chunks[7] = ScriptChunk::new(Opcode::OP_PUSHDATA1, Some(vec![6]));
+        assert!(!template.is_match(&script));
+    }
+
+    #[test]
+    fn test_no_match() {
+        let template = ScriptTemplate::PKH_OUTPUT;
+        let script = template.build(&[Value::Pkh([1; 32])]).unwrap();
+        assert!(template.is_match(&script));
+
+        let mut wrong_opcode = script.clone();
+        wrong_opcode.chunks[1] = ScriptChunk::new(Opcode::OP_BLAKE3, None);
+        let mut short_pkh = script.clone();
+        short_pkh.chunks[2] = ScriptChunk::from_data(vec![1; 31]);
+        let mut extra_chunk = script.clone();
+        extra_chunk
+            .chunks
+            .push(ScriptChunk::new(Opcode::OP_1, None));
+        let mut op_with_data = script.clone();
+        op_with_data.chunks[0].buffer = Some(vec![]);
+        for script in [wrong_opcode, short_pkh, extra_chunk, op_with_data] {
+            assert_eq!(template.match_script(&script), None);
+        }
+
+        // the last ENDIF of a pkhxr output
+        let values = [
+            Value::Pkh([1; 32]),
+            Value::LockNum(4),
+            Value::Pkh([2; 32]),
+            Value::LockNum(6),
+        ];
+        let mut script = ScriptTemplate::PKHXR_OUTPUT.build(&values).unwrap();
+        script.chunks[22] = ScriptChunk::new(Opcode::OP_ELSE, None);
+        assert!(!ScriptTemplate::PKHXR_OUTPUT.is_match(&script));
+    }
+
+    #[test]
+    fn test_build_errors() {
+        let template = ScriptTemplate::PKHX_OUTPUT;
+        let wrong_values = [
+            vec![],
+            vec![Value::Pkh([1; 32])],
+            vec![Value::LockNum(6), Value::Pkh([1; 32])],
+            vec![Value::Pkh([1; 32]), Value::LockNum(6), Value::LockNum(6)],
+        ];
+        for values in wrong_values {
+            assert_eq!(
+                template.build(&values).unwrap_err().to_string(),
+                "ebx error: values do not match placeholders of pkhx output"
+            );
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::error::SpvError;
+use crate::header_chain::HeaderChain;
+use crate::merkle_proof::MerkleProof;
+use crate::tx::Tx;
+
+// verifies that a tx is in a block of the longest chain from just the headers
+// and a merkle proof, for light clients that do not download blocks
+pub struct SpvVerifier<'a> {
+    pub lch: &'a HeaderChain, // longest chain, already validated
+}
+
+impl<'a> SpvVerifier<'a> {
+    pub fn new(lch: &'a HeaderChain) -> Self {
+        Self { lch }
+    }
+
+    // returns the number of confirmations, which is 1 if the block is the tip
+    pub fn verify(
+        &self,
+        tx: &Tx,
+        proof: &MerkleProof,
+        block_id: &[u8; 32],
+    ) -> Result<u32, SpvError> {
+        let index = self
+            .lch
+            .get_index(block_id)
+            .ok_or(SpvError::BlockNotFound)?;
+        let header = &self.lch.headers()[index];
+
+        // the same checks as MerkleProof::verify_strict, one at a time to
+        // report which one failed
+        if proof.proof.len() != MerkleProof::proof_length(header.n_transactions) {
+            return Err(SpvError::InvalidProofLength);
+        }
+        if proof.position_in_tree() >= header.n_transactions {
+            return Err(SpvError::InvalidPosition);
+        }
+        if !proof.verify(&tx.id()) {
+            return Err(SpvError::InvalidProof);
+        }
+        if proof.root != header.merkle_root {
+            return Err(SpvError::MerkleRootMismatch);
+        }
+        Ok((self.lch.len() - index) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_txs::MerkleTxs;
+    use crate::network_params::NetworkParams;
+
+    fn txs(block_num: u32, n: u32) -> Vec<Tx> {
+        (0..n)
+            .map(|i| Tx::new(0, vec![], vec![], block_num * 100 + i))
+            .collect()
+    }
+
+    // a regtest chain of blocks with 1, 2, 3 and 5 txs
+    fn setup() -> (HeaderChain, Vec<MerkleTxs>) {
+        let mut lch = HeaderChain::from_params(NetworkParams::REGTEST);
+        let mut blocks = Vec::new();
+        for (block_num, n) in [1, 2, 3, 5].into_iter().enumerate() {
+            let merkle_txs = MerkleTxs::new(txs(block_num as u32, n));
+            let timestamp = block_num as u64 * 1_000;
+            let header = lch
+                .get_next_header(merkle_txs.root, n as u64, timestamp)
+                .unwrap();
+            lch.add(header);
+            blocks.push(merkle_txs);
+        }
+        (lch, blocks)
+    }
+
+    #[test]
+    fn test_verify() {
+        let (lch, blocks) = setup();
+        let spv_verifier = SpvVerifier::new(&lch);
+        for (block_num, merkle_txs) in blocks.iter().enumerate() {
+            let block_id = lch.headers()[block_num].id();
+            for (tx, proof) in merkle_txs.get_iterator() {
+                assert_eq!(
+                    spv_verifier.verify(tx, proof, &block_id),
+                    Ok(4 - block_num as u32)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_errors() {
+        let (lch, blocks) = setup();
+        let spv_verifier = SpvVerifier::new(&lch);
+        let block_id = lch.headers()[3].id();
+        let tx = &blocks[3].txs[1];
+        let proof = &blocks[3].proofs[1];
+
+        assert_eq!(
+            spv_verifier.verify(tx, proof, &[0; 32]),
+            Err(SpvError::BlockNotFound)
+        );
+        // the proof is for another tx
+        assert_eq!(
+            spv_verifier.verify(&blocks[3].txs[2], proof, &block_id),
+            Err(SpvError::InvalidProof)
+        );
+        // a valid proof for a block with the same number of txs, but the
+        // root is not this block's
+        let other = MerkleTxs::new(txs(9, 5));
+        assert_eq!(
+            spv_verifier.verify(&other.txs[1], &other.proofs[1], &block_id),
+            Err(SpvError::MerkleRootMismatch)
+        );
+        // the proof is for the block with 3 txs
+        assert_eq!(
+            spv_verifier.verify(&blocks[2].txs[0], &blocks[2].proofs[0], &block_id),
+            Err(SpvError::InvalidProofLength)
+        );
+
+        // the padding copy of the last tx at position 3 of 3
+        let block_id = lch.headers()[2].id();
+        let last_tx = &blocks[2].txs[2];
+        let mut ids: Vec<[u8; 32]> = blocks[2].txs.iter().map(|tx| tx.id()).collect();
+        ids.push(last_tx.id());
+        let (root, proofs) = MerkleProof::generate_proofs_and_root(ids);
+        assert_eq!(root, lch.headers()[2].merkle_root);
+        assert_eq!(
+            spv_verifier.verify(last_tx, &proofs[3], &block_id),
+            Err(SpvError::InvalidPosition)
+        );
+    }
+}
@@ -30,7 +30,7 @@ impl HashCache {
 }
 
 // add clone support
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Tx {
     pub version: u8,
     pub inputs: Vec<TxIn>,
@@ -58,18 +58,18 @@ impl Tx {
     }
 
     pub fn from_buf_reader(reader: &mut BufReader) -> Result<Self, EbxError> {
-        let version = reader.read_u8()?;
-        let input_count = reader.read_var_int()? as usize;
+        let version = reader.read_field("version", BufReader::read_u8)?;
+        let input_count = reader.read_field("input_count", BufReader::read_var_int)? as usize;
         let mut inputs = Vec::new();
-        for _ in 0..input_count {
-            inputs.push(TxIn::from_buf_reader(reader)?);
+        for i in 0..input_count {
+            inputs.push(reader.read_indexed_field("inputs", i, TxIn::from_buf_reader)?);
         }
-        let output_count = reader.read_var_int()? as usize;
+        let output_count = reader.read_field("output_count", BufReader::read_var_int)? as usize;
         let mut outputs = Vec::new();
-        for _ in 0..output_count {
-            outputs.push(TxOut::from_buf_reader(reader)?);
+        for i in 0..output_count {
+            outputs.push(reader.read_indexed_field("outputs", i, TxOut::from_buf_reader)?);
         }
-        let lock_num = reader.read_u32_be()?;
+        let lock_num = reader.read_field("lock_abs", BufReader::read_u32_be)?;
         Ok(Self::new(version, inputs, outputs, lock_num))
     }
 
@@ -384,6 +384,30 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_from_buf_error_context() {
+        let script = Script::from_strict_str("DOUBLEBLAKE3 BLAKE3 DOUBLEBLAKE3 EQUAL").unwrap();
+        let tx_input = TxIn::new([0; 32], 0, script.clone(), 0);
+        let tx_output = TxOut::new(100, script);
+        let tx = Tx::new(1, vec![tx_input], vec![tx_output], 0);
+
+        // version (1) + input count (1) + input tx id (32) + input tx out num
+        // (4) + script len (1), then cut off inside the input script
+        let buf = tx.to_buf()[..40].to_vec();
+
+        // can be wrapped like any other error
+        fn parse(buf: Vec<u8>) -> Result<Tx, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(Tx::from_buf(buf)?)
+        }
+        let err = parse(buf).unwrap_err();
+        assert_eq!(err.to_string(), "failed to parse inputs[0] at byte 2");
+        let err = err.source().unwrap();
+        assert_eq!(err.to_string(), "failed to parse script at byte 38");
+        let err = err.source().unwrap();
+        assert_eq!(err.to_string(), "not enough bytes in the buffer to read");
+        assert!(err.source().is_none());
+    }
+
     #[test]
     fn test_from_buf_reader() -> Result<(), String> {
         let input_tx_id = [0; 32];
@@ -1,26 +1,31 @@
 use crate::error::EbxError;
+use crate::network_params::NetworkParams;
 use crate::script::Script;
 use crate::tx::Tx;
 use crate::tx_in::TxIn;
 use crate::tx_out::TxOut;
 use crate::tx_out_bn_map::TxOutBnMap;
 
-pub struct TxBuilder {
-    input_tx_out_bn_map: TxOutBnMap,
+// the inputs are chosen from the outputs the caller can spend, e.g. the
+// wallet's, rather than from the whole utxo set
+pub struct TxBuilder<'a> {
+    input_tx_out_bn_map: &'a TxOutBnMap,
     tx: Tx,
     change_script: Script,
     input_amount: u64,
     lock_abs: u32,
+    pub params: NetworkParams, // for the lock-rels of the pkhx outputs
 }
 
-impl TxBuilder {
-    pub fn new(input_tx_out_bn_map: &TxOutBnMap, change_script: Script, lock_abs: u32) -> Self {
+impl<'a> TxBuilder<'a> {
+    pub fn new(input_tx_out_bn_map: &'a TxOutBnMap, change_script: Script, lock_abs: u32) -> Self {
         Self {
             tx: Tx::new(0, vec![], vec![], 0),
-            input_tx_out_bn_map: input_tx_out_bn_map.clone(),
+            input_tx_out_bn_map,
             change_script,
             input_amount: 0,
             lock_abs,
+            params: NetworkParams::MAINNET,
         }
     }
 
@@ -43,30 +48,31 @@ impl TxBuilder {
         let mut change_amount = 0;
         let mut input_amount = self.input_amount;
 
-        // sort by block number first, but if those are the same, sort by the id
-        // of the tx_out, which is tx_id plus tx_out_num together in a string.
-        // this logic means we use the "most confirmed" outputs first, which is
-        // what we want, and then we have a deterministic way to sort the UTXOs
-        // in the same block.
+        // sort by block number first, but if those are the same, sort by the
+        // out point, which is tx_id and then tx_out_num. this logic means we
+        // use the "most confirmed" outputs first, which is what we want, and
+        // then we have a deterministic way to sort the UTXOs in the same block.
         let mut sorted_tx_out_bns: Vec<_> = self.input_tx_out_bn_map.map.iter().collect();
         sorted_tx_out_bns
             .sort_by(|a, b| a.1.block_num.cmp(&b.1.block_num).then_with(|| a.0.cmp(b.0)));
 
-        for (tx_out_id, tx_out_bn) in sorted_tx_out_bns {
+        for (out_point, tx_out_bn) in sorted_tx_out_bns {
             if input_amount >= total_spend_amount {
                 change_amount = input_amount - total_spend_amount;
                 break;
             }
             let tx_out = &tx_out_bn.tx_out;
-            let tx_id: [u8; 32] = TxOutBnMap::name_to_tx_id(tx_out_id).try_into().unwrap();
-            let tx_out_num = TxOutBnMap::name_to_tx_out_num(tx_out_id);
+            let tx_id = out_point.tx_id;
+            let tx_out_num = out_point.tx_out_num;
 
             let input_script: Script = if tx_out.script.is_pkh_output() {
                 Script::from_pkh_input_placeholder()
-            } else if tx_out.script.is_pkhx_90d_output() || tx_out.script.is_pkhx_1h_output() {
+            } else if tx_out.script.is_pkhx_90d_output(&self.params)
+                || tx_out.script.is_pkhx_1h_output(&self.params)
+            {
                 Script::from_unexpired_pkhx_input_placeholder()
-            } else if tx_out.script.is_pkhxr_90d_60d_output()
-                || tx_out.script.is_pkhxr_1h_40m_output()
+            } else if tx_out.script.is_pkhxr_90d_60d_output(&self.params)
+                || tx_out.script.is_pkhxr_1h_40m_output(&self.params)
             {
                 Script::from_unexpired_pkhxr_input_placeholder()
             } else {
@@ -96,8 +102,7 @@ mod tests {
     use crate::pkh::Pkh;
     use crate::script::Script;
 
-    fn setup() -> TxBuilder {
-        let mut tx_out_bn_map = TxOutBnMap::new();
+    fn setup(tx_out_bn_map: &mut TxOutBnMap) -> TxBuilder<'_> {
         let change_script = Script::from_strict_str("");
 
         for i in 0..5 {
@@ -109,12 +114,13 @@ mod tests {
             tx_out_bn_map.add(&[0; 32], i, tx_out, block_num);
         }
 
-        TxBuilder::new(&tx_out_bn_map, change_script.unwrap(), 0)
+        TxBuilder::new(tx_out_bn_map, change_script.unwrap(), 0)
     }
 
     #[test]
     fn test_build_valid_tx_when_input_is_enough_to_cover_output() {
-        let mut tx_builder = setup();
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let mut tx_builder = setup(&mut tx_out_bn_map);
         let tx_out = TxOut::new(50, Script::from_empty());
         tx_builder.add_output(tx_out);
 
@@ -127,7 +133,8 @@ mod tests {
 
     #[test]
     fn test_build_invalid_tx_when_input_is_insufficient_to_cover_output() {
-        let mut tx_builder = setup();
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let mut tx_builder = setup(&mut tx_out_bn_map);
         let tx_out = TxOut::new(10000, Script::from_empty());
         tx_builder.add_output(tx_out);
 
@@ -138,4 +145,25 @@ mod tests {
         assert_eq!(tx_builder.input_amount, 500);
         assert_eq!(tx.outputs[0].value, 10000);
     }
+
+    #[test]
+    fn test_pkhx_output_of_params() {
+        let params = NetworkParams::REGTEST;
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let tx_out = TxOut::new(100, Script::from_pkhx_90d_output(&[1; 32], &params));
+        tx_out_bn_map.add(&[0; 32], 0, tx_out, 0);
+
+        let mut tx_builder = TxBuilder::new(&tx_out_bn_map, Script::from_empty(), 0);
+        tx_builder.add_output(TxOut::new(100, Script::from_empty()));
+        assert!(tx_builder.build().is_err());
+
+        let mut tx_builder = TxBuilder::new(&tx_out_bn_map, Script::from_empty(), 0);
+        tx_builder.params = params;
+        tx_builder.add_output(TxOut::new(100, Script::from_empty()));
+        let tx = tx_builder.build().unwrap();
+        assert_eq!(
+            tx.inputs[0].script,
+            Script::from_unexpired_pkhx_input_placeholder()
+        );
+    }
 }
@@ -5,7 +5,7 @@ use crate::script::Script;
 use crate::var_int::VarInt;
 
 // add clone support
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct TxIn {
     pub input_tx_id: [u8; 32],
     pub input_tx_out_num: u32,
@@ -30,20 +30,20 @@ impl TxIn {
 
     pub fn from_buf(buf: Vec<u8>) -> Result<Self, EbxError> {
         let mut reader = BufReader::new(buf);
-        let input_tx_id: [u8; 32] = reader.read(32)?.try_into().unwrap();
-        let input_tx_index = reader.read_u32_be()?;
-        let size = reader.read_u8()? as usize;
-        let script = Script::from_buf(reader.read(size)?.as_slice())?;
-        let lock_rel = reader.read_u32_be()?;
-        Ok(Self::new(input_tx_id, input_tx_index, script, lock_rel))
+        Self::from_buf_reader(&mut reader)
     }
 
     pub fn from_buf_reader(reader: &mut BufReader) -> Result<Self, EbxError> {
-        let input_tx_id: [u8; 32] = reader.read(32)?.try_into().unwrap();
-        let input_tx_index = reader.read_u32_be()?;
-        let size = reader.read_var_int()? as usize;
-        let script = Script::from_buf(reader.read(size)?.as_slice())?;
-        let lock_rel = reader.read_u32_be()?;
+        let input_tx_id: [u8; 32] = reader
+            .read_field("input_tx_id", |reader| reader.read(32))?
+            .try_into()
+            .unwrap();
+        let input_tx_index = reader.read_field("input_tx_out_num", BufReader::read_u32_be)?;
+        let script = reader.read_field("script", |reader| {
+            let size = reader.read_var_int()? as usize;
+            Script::from_buf(reader.read(size)?.as_slice())
+        })?;
+        let lock_rel = reader.read_field("lock_rel", BufReader::read_u32_be)?;
         Ok(Self::new(input_tx_id, input_tx_index, script, lock_rel))
     }
 
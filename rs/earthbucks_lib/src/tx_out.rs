@@ -17,24 +17,16 @@ impl TxOut {
 
     pub fn from_buf(buf: Vec<u8>) -> Result<Self, EbxError> {
         let mut reader = BufReader::new(buf);
-        let value = reader.read_u64_be()?;
-        let script_len = reader.read_var_int()? as usize;
-        let script_arr = reader.read(script_len)?;
-        let script = match Script::from_buf(&script_arr[..]) {
-            Ok(script) => script,
-            Err(e) => return Err(e),
-        };
-        Ok(Self::new(value, script))
+        Self::from_buf_reader(&mut reader)
     }
 
     pub fn from_buf_reader(reader: &mut BufReader) -> Result<Self, EbxError> {
-        let value = reader.read_u64_be()?;
-        let script_len = reader.read_var_int()? as usize;
-        let script_arr = reader.read(script_len)?;
-        let script = match Script::from_buf(&script_arr[..]) {
-            Ok(script) => script,
-            Err(e) => return Err(e),
-        };
+        let value = reader.read_field("value", BufReader::read_u64_be)?;
+        let script = reader.read_field("script", |reader| {
+            let script_len = reader.read_var_int()? as usize;
+            let script_arr = reader.read(script_len)?;
+            Script::from_buf(&script_arr[..])
+        })?;
         Ok(Self::new(value, script))
     }
 
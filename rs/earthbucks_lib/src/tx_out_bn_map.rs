@@ -1,12 +1,15 @@
 use crate::buf::EbxBuf;
+use crate::error::EbxError;
+use crate::out_point::OutPoint;
 use crate::tx::Tx;
 use crate::tx_out::TxOut;
 use crate::tx_out_bn::TxOutBn;
+use crate::utxo_store::UtxoStore;
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, Default)]
 pub struct TxOutBnMap {
-    pub map: HashMap<String, TxOutBn>,
+    pub map: HashMap<OutPoint, TxOutBn>,
 }
 
 impl TxOutBnMap {
@@ -17,7 +20,7 @@ impl TxOutBnMap {
     }
 
     pub fn name_from_output(tx_id: &[u8; 32], tx_out_num: u32) -> String {
-        format!("{}:{}", tx_id.to_strict_hex(), tx_out_num)
+        OutPoint::new(*tx_id, tx_out_num).to_strict_str()
     }
 
     pub fn name_to_tx_id(name: &str) -> Vec<u8> {
@@ -29,22 +32,22 @@ impl TxOutBnMap {
     }
 
     pub fn add(&mut self, tx_id: &[u8; 32], tx_out_num: u32, tx_out: TxOut, block_num: u32) {
-        let name = Self::name_from_output(tx_id, tx_out_num);
+        let out_point = OutPoint::new(*tx_id, tx_out_num);
         let tx_out_bn = TxOutBn {
             tx_out: tx_out.clone(),
             block_num,
         };
-        self.map.insert(name, tx_out_bn);
+        self.map.insert(out_point, tx_out_bn);
     }
 
     pub fn remove(&mut self, tx_id: &[u8; 32], tx_out_num: u32) {
-        let name = Self::name_from_output(tx_id, tx_out_num);
-        self.map.remove(&name);
+        let out_point = OutPoint::new(*tx_id, tx_out_num);
+        self.map.remove(&out_point);
     }
 
     pub fn get(&self, tx_id: &[u8; 32], tx_out_num: u32) -> Option<&TxOutBn> {
-        let name = Self::name_from_output(tx_id, tx_out_num);
-        self.map.get(&name)
+        let out_point = OutPoint::new(*tx_id, tx_out_num);
+        self.map.get(&out_point)
     }
 
     pub fn values(&self) -> Vec<&TxOutBn> {
@@ -58,6 +61,29 @@ impl TxOutBnMap {
     }
 }
 
+impl UtxoStore for TxOutBnMap {
+    fn get_tx_out_bn(&self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+        Ok(self.map.get(out_point).cloned())
+    }
+
+    fn add_tx_out_bn(&mut self, out_point: OutPoint, tx_out_bn: TxOutBn) -> Result<(), EbxError> {
+        self.map.insert(out_point, tx_out_bn);
+        Ok(())
+    }
+
+    fn remove_tx_out_bn(&mut self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+        Ok(self.map.remove(out_point))
+    }
+
+    fn get_all_tx_out_bns(&self) -> Result<Vec<(OutPoint, TxOutBn)>, EbxError> {
+        Ok(self
+            .map
+            .iter()
+            .map(|(out_point, tx_out_bn)| (*out_point, tx_out_bn.clone()))
+            .collect())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -162,4 +188,31 @@ mod tests {
         assert!(values.contains(&&tx_out_bn1));
         assert!(values.contains(&&tx_out_bn2));
     }
+
+    #[test]
+    fn test_utxo_store() {
+        let mut tx_out_map = TxOutBnMap::new();
+        let out_point = OutPoint::new([1; 32], 2);
+        let tx_out_bn = TxOutBn {
+            tx_out: TxOut::new(100, Script::from_empty()),
+            block_num: 3,
+        };
+        tx_out_map
+            .add_tx_out_bn(out_point, tx_out_bn.clone())
+            .unwrap();
+        assert_eq!(tx_out_map.get(&[1; 32], 2), Some(&tx_out_bn));
+        assert_eq!(
+            tx_out_map.get_tx_out_bn(&out_point).unwrap(),
+            Some(tx_out_bn.clone())
+        );
+        assert_eq!(
+            tx_out_map.get_all_tx_out_bns().unwrap(),
+            vec![(out_point, tx_out_bn.clone())]
+        );
+        assert_eq!(
+            tx_out_map.remove_tx_out_bn(&out_point).unwrap(),
+            Some(tx_out_bn)
+        );
+        assert!(!tx_out_map.contains_tx_out_bn(&out_point).unwrap());
+    }
 }
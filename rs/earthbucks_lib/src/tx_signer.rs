@@ -1,29 +1,28 @@
 use crate::error::EbxError;
+use crate::network_params::NetworkParams;
+use crate::out_point::OutPoint;
 use crate::pkh_key_map::PkhKeyMap;
 use crate::script::Script;
 use crate::tx::Tx;
-use crate::tx_out_bn_map::TxOutBnMap;
 use crate::tx_signature::TxSignature;
+use crate::utxo_store::UtxoStore;
 
-pub struct TxSigner {
+pub struct TxSigner<'a, S: UtxoStore> {
     pub tx: Tx,
     pub pkh_key_map: PkhKeyMap,
-    pub tx_out_bn_map: TxOutBnMap,
+    pub utxo_store: &'a S,
     pub working_block_num: u32,
+    pub params: NetworkParams,
 }
 
-impl TxSigner {
-    pub fn new(
-        tx: Tx,
-        tx_out_bn_map: &TxOutBnMap,
-        pkh_key_map: &PkhKeyMap,
-        working_block_num: u32,
-    ) -> Self {
+impl<'a, S: UtxoStore> TxSigner<'a, S> {
+    pub fn new(tx: Tx, utxo_store: &'a S, pkh_key_map: &PkhKeyMap, working_block_num: u32) -> Self {
         Self {
             tx,
-            tx_out_bn_map: tx_out_bn_map.clone(),
+            utxo_store,
             pkh_key_map: pkh_key_map.clone(),
             working_block_num,
+            params: NetworkParams::MAINNET,
         }
     }
 
@@ -31,10 +30,9 @@ impl TxSigner {
         let mut tx_clone = self.tx.clone();
 
         let tx_input = &mut self.tx.inputs[n_in];
-        let tx_out_hash: &[u8; 32] = &tx_input.input_tx_id.clone();
-        let output_index = tx_input.input_tx_out_num;
-        let tx_out_bn = match self.tx_out_bn_map.get(tx_out_hash, output_index) {
-            Some(tx_out_bn) => tx_out_bn.clone(),
+        let out_point = OutPoint::new(tx_input.input_tx_id, tx_input.input_tx_out_num);
+        let tx_out_bn = match self.utxo_store.get_tx_out_bn(&out_point)? {
+            Some(tx_out_bn) => tx_out_bn,
             None => {
                 return Err(EbxError::GenericError {
                     source: None,
@@ -83,14 +81,15 @@ impl TxSigner {
 
             input_script.chunks[0].buffer = Some(sig_buf.to_vec());
             input_script.chunks[1].buffer = Some(pub_key_buf.clone());
-        } else if tx_out.script.is_pkhx_1h_output() {
+        } else if tx_out.script.is_pkhx_1h_output(&self.params) {
             let pkh_buf: [u8; 32] = tx_out.script.chunks[3]
                 .buffer
                 .clone()
                 .expect("pkh not found")
                 .try_into()
                 .unwrap();
-            let expired = Script::is_pkhx_1h_expired(self.working_block_num, prev_block_num);
+            let expired =
+                Script::is_pkhx_1h_expired(self.working_block_num, prev_block_num, &self.params);
             let input_script = &mut tx_input.script;
             if expired {
                 if input_script.is_expired_pkhx_input() {
@@ -133,14 +132,15 @@ impl TxSigner {
 
             input_script.chunks[0].buffer = Some(sig_buf.to_vec());
             input_script.chunks[1].buffer = Some(pub_key_buf.clone());
-        } else if tx_out.script.is_pkhx_90d_output() {
+        } else if tx_out.script.is_pkhx_90d_output(&self.params) {
             let pkh_buf: [u8; 32] = tx_out.script.chunks[3]
                 .buffer
                 .clone()
                 .expect("pkh not found")
                 .try_into()
                 .unwrap();
-            let expired = Script::is_pkhx_90d_expired(self.working_block_num, prev_block_num);
+            let expired =
+                Script::is_pkhx_90d_expired(self.working_block_num, prev_block_num, &self.params);
             let input_script = &mut tx_input.script;
             if expired {
                 if input_script.is_expired_pkhx_input() {
@@ -183,7 +183,7 @@ impl TxSigner {
 
             input_script.chunks[0].buffer = Some(sig_buf.to_vec());
             input_script.chunks[1].buffer = Some(pub_key_buf.clone());
-        } else if tx_out.script.is_pkhxr_1h_40m_output() {
+        } else if tx_out.script.is_pkhxr_1h_40m_output(&self.params) {
             let pkh_buf: [u8; 32] = tx_out.script.chunks[3]
                 .buffer
                 .clone()
@@ -196,7 +196,11 @@ impl TxSigner {
                 .expect("rpkh not found")
                 .try_into()
                 .unwrap();
-            let expired = Script::is_pkhxr_1h_40m_expired(self.working_block_num, prev_block_num);
+            let expired = Script::is_pkhxr_1h_40m_expired(
+                self.working_block_num,
+                prev_block_num,
+                &self.params,
+            );
             let input_script = &mut tx_input.script;
             if expired {
                 if input_script.is_expired_pkhxr_input() {
@@ -211,8 +215,11 @@ impl TxSigner {
             }
 
             let key_pair = if input_script.is_recovery_pkhxr_input() {
-                let recoverable =
-                    Script::is_pkhxr_1h_40m_recoverable(self.working_block_num, prev_block_num);
+                let recoverable = Script::is_pkhxr_1h_40m_recoverable(
+                    self.working_block_num,
+                    prev_block_num,
+                    &self.params,
+                );
                 if !recoverable {
                     return Err(EbxError::GenericError {
                         source: None,
@@ -260,7 +267,7 @@ impl TxSigner {
 
             input_script.chunks[0].buffer = Some(sig_buf.to_vec());
             input_script.chunks[1].buffer = Some(pub_key_buf.clone());
-        } else if tx_out.script.is_pkhxr_90d_60d_output() {
+        } else if tx_out.script.is_pkhxr_90d_60d_output(&self.params) {
             let pkh_buf: [u8; 32] = tx_out.script.chunks[3]
                 .buffer
                 .clone()
@@ -273,7 +280,11 @@ impl TxSigner {
                 .expect("rpkh not found")
                 .try_into()
                 .unwrap();
-            let expired = Script::is_pkhxr_90d_60d_expired(self.working_block_num, prev_block_num);
+            let expired = Script::is_pkhxr_90d_60d_expired(
+                self.working_block_num,
+                prev_block_num,
+                &self.params,
+            );
             let input_script = &mut tx_input.script;
             if expired {
                 if input_script.is_expired_pkhxr_input() {
@@ -288,8 +299,11 @@ impl TxSigner {
             }
 
             let key_pair = if input_script.is_recovery_pkhxr_input() {
-                let recoverable =
-                    Script::is_pkhxr_90d_60d_recoverable(self.working_block_num, prev_block_num);
+                let recoverable = Script::is_pkhxr_90d_60d_recoverable(
+                    self.working_block_num,
+                    prev_block_num,
+                    &self.params,
+                );
                 if !recoverable {
                     return Err(EbxError::GenericError {
                         source: None,
@@ -1,158 +1,215 @@
-use crate::script_interpreter::ScriptInterpreter;
+use crate::error::{ScriptLimitError, TxError};
+use crate::out_point::OutPoint;
+use crate::script_interpreter::{ScriptInterpreter, ScriptLimits};
 use crate::tx::{HashCache, Tx};
-use crate::tx_out_bn_map::TxOutBnMap;
+use crate::tx_out_bn::TxOutBn;
+use crate::utxo_store::UtxoStore;
 
-pub struct TxVerifier<'a> {
+pub struct TxVerifier<'a, S: UtxoStore> {
     tx: Tx,
-    tx_out_bn_map: &'a TxOutBnMap,
+    utxo_store: &'a S,
     hash_cache: HashCache,
     block_num: u32,
+    pub script_limits: ScriptLimits,
+    pub require_minimal: bool, // pushes and numbers, against malleability
 }
 
-impl<'a> TxVerifier<'a> {
-    pub fn new(tx: Tx, tx_out_bn_map: &'a TxOutBnMap, block_num: u32) -> Self {
+impl<'a, S: UtxoStore> TxVerifier<'a, S> {
+    pub fn new(tx: Tx, utxo_store: &'a S, block_num: u32) -> Self {
         let hash_cache = HashCache::new();
         Self {
             tx,
-            tx_out_bn_map,
+            utxo_store,
             hash_cache,
             block_num,
+            script_limits: ScriptLimits::DEFAULT,
+            require_minimal: false,
         }
     }
 
-    pub fn verify_input_script(&mut self, n_in: usize) -> bool {
+    // the output spent by input n_in. a storage error is not a missing output,
+    // because it says nothing about whether the tx is valid.
+    fn get_input_tx_out_bn(&self, n_in: usize) -> Result<Option<TxOutBn>, TxError> {
         let tx_input = &self.tx.inputs[n_in];
-        let tx_out_hash: &[u8; 32] = &tx_input.input_tx_id.clone();
-        let output_index = tx_input.input_tx_out_num;
-        let tx_out = self.tx_out_bn_map.get(tx_out_hash, output_index);
-        match tx_out {
-            None => false,
-            Some(tx_out_bn) => {
-                let output_script = &tx_out_bn.tx_out.script;
-                let input_script = &tx_input.script;
-                if !input_script.is_push_only() {
-                    return false;
-                }
-                let stack: Vec<Vec<u8>> = input_script
-                    .chunks
-                    .iter()
-                    .map(|chunk| chunk.get_data().unwrap())
-                    .collect();
-                let mut script_interpreter = ScriptInterpreter::from_output_script_tx(
-                    output_script.clone(),
-                    self.tx.clone(),
-                    n_in,
-                    stack,
-                    tx_out_bn.tx_out.value,
-                    &mut self.hash_cache,
-                );
-                script_interpreter.eval_script()
+        let out_point = OutPoint::new(tx_input.input_tx_id, tx_input.input_tx_out_num);
+        self.utxo_store
+            .get_tx_out_bn(&out_point)
+            .map_err(|e| TxError::UtxoStore {
+                n_in,
+                message: e.to_string(),
+            })
+    }
+
+    fn get_spent_tx_out_bn(&self, n_in: usize) -> Result<TxOutBn, TxError> {
+        self.get_input_tx_out_bn(n_in)?
+            .ok_or(TxError::MissingInput { n_in })
+    }
+
+    // the outputs spent by all inputs, each read from the store once. fails on
+    // the first input whose output is missing or spent by an earlier input.
+    fn get_spent_tx_out_bns(&self) -> Result<Vec<TxOutBn>, TxError> {
+        let mut spent_out_points = Vec::new();
+        let mut tx_out_bns = Vec::new();
+        for (n_in, input) in self.tx.inputs.iter().enumerate() {
+            tx_out_bns.push(self.get_spent_tx_out_bn(n_in)?);
+            let out_point = OutPoint::new(input.input_tx_id, input.input_tx_out_num);
+            if spent_out_points.contains(&out_point) {
+                return Err(TxError::DuplicateInput { n_in });
             }
+            spent_out_points.push(out_point);
         }
+        Ok(tx_out_bns)
     }
 
-    pub fn verify_input_lock_rel(&mut self, n_in: usize) -> bool {
+    pub fn verify_input_script(&mut self, n_in: usize) -> Result<(), TxError> {
+        let tx_out_bn = self.get_spent_tx_out_bn(n_in)?;
+        self.verify_input_script_spending(n_in, &tx_out_bn)
+    }
+
+    fn verify_input_script_spending(
+        &mut self,
+        n_in: usize,
+        tx_out_bn: &TxOutBn,
+    ) -> Result<(), TxError> {
         let tx_input = &self.tx.inputs[n_in];
-        let tx_id: &[u8; 32] = &tx_input.input_tx_id.clone();
-        let tx_out_num = tx_input.input_tx_out_num;
-        let tx_out = self.tx_out_bn_map.get(tx_id, tx_out_num);
-        match tx_out {
-            None => false,
-            Some(tx_out_bn) => {
-                let lock_rel = tx_input.lock_rel;
-                let prev_block_num = tx_out_bn.block_num;
-                self.block_num >= prev_block_num + lock_rel
+        let output_script = &tx_out_bn.tx_out.script;
+        let input_script = &tx_input.script;
+        if !input_script.is_push_only() {
+            return Err(TxError::NonPushOnlyInput { n_in });
+        }
+        if self.require_minimal && !input_script.chunks.iter().all(|c| c.is_minimal_push()) {
+            return Err(TxError::NonMinimalInput { n_in });
+        }
+        // the output script is checked by the interpreter
+        if input_script.to_buf().len() > self.script_limits.max_script_size {
+            return Err(TxError::ScriptLimitExceeded {
+                n_in,
+                error: ScriptLimitError::ScriptSize,
+            });
+        }
+        let stack: Vec<Vec<u8>> = input_script
+            .chunks
+            .iter()
+            .map(|chunk| chunk.get_data().unwrap())
+            .collect();
+        let mut script_interpreter = ScriptInterpreter::from_output_script_tx(
+            output_script.clone(),
+            self.tx.clone(),
+            n_in,
+            stack,
+            tx_out_bn.tx_out.value,
+            &mut self.hash_cache,
+        );
+        script_interpreter.limits = self.script_limits.clone();
+        script_interpreter.require_minimal_push = self.require_minimal;
+        script_interpreter.require_minimal_num = self.require_minimal;
+        if !script_interpreter.eval_script() {
+            if let Some(error) = script_interpreter.limit_error {
+                return Err(TxError::ScriptLimitExceeded { n_in, error });
             }
+            let message = if script_interpreter.err_str.is_empty() {
+                "script evaluated to false".to_string()
+            } else {
+                script_interpreter.err_str
+            };
+            return Err(TxError::ScriptFailed { n_in, message });
         }
+        Ok(())
     }
 
-    pub fn verify_inputs(&mut self) -> bool {
-        for i in 0..self.tx.inputs.len() {
-            if !self.verify_input_script(i) {
-                return false;
-            }
-            if !self.verify_input_lock_rel(i) {
-                return false;
-            }
+    pub fn verify_input_lock_rel(&mut self, n_in: usize) -> Result<(), TxError> {
+        let tx_out_bn = self.get_spent_tx_out_bn(n_in)?;
+        self.verify_input_lock_rel_spending(n_in, &tx_out_bn)
+    }
+
+    fn verify_input_lock_rel_spending(
+        &self,
+        n_in: usize,
+        tx_out_bn: &TxOutBn,
+    ) -> Result<(), TxError> {
+        let lock_rel = self.tx.inputs[n_in].lock_rel;
+        let prev_block_num = tx_out_bn.block_num;
+        if self.block_num < prev_block_num + lock_rel {
+            return Err(TxError::LockRelNotReached { n_in });
         }
-        true
-    }
-
-    pub fn verify_no_double_spend(&self) -> bool {
-        let mut spent_outputs = Vec::new();
-        for input in &self.tx.inputs {
-            let tx_out = self
-                .tx_out_bn_map
-                .get(&input.input_tx_id.clone(), input.input_tx_out_num);
-            match tx_out {
-                None => return false,
-                Some(tx_out) => {
-                    if spent_outputs.contains(&tx_out) {
-                        return false;
-                    }
-                    spent_outputs.push(tx_out);
-                }
-            }
+        Ok(())
+    }
+
+    pub fn verify_inputs(&mut self) -> Result<(), TxError> {
+        for n_in in 0..self.tx.inputs.len() {
+            let tx_out_bn = self.get_spent_tx_out_bn(n_in)?;
+            self.verify_input_script_spending(n_in, &tx_out_bn)?;
+            self.verify_input_lock_rel_spending(n_in, &tx_out_bn)?;
         }
-        true
+        Ok(())
+    }
+
+    pub fn verify_no_double_spend(&self) -> Result<(), TxError> {
+        self.get_spent_tx_out_bns()?;
+        Ok(())
     }
 
-    pub fn verify_output_values(&self) -> bool {
+    pub fn verify_output_values(&self) -> Result<(), TxError> {
+        let mut tx_out_bns = Vec::new();
+        for n_in in 0..self.tx.inputs.len() {
+            tx_out_bns.push(self.get_spent_tx_out_bn(n_in)?);
+        }
+        self.verify_output_values_spending(&tx_out_bns)
+    }
+
+    fn verify_output_values_spending(&self, tx_out_bns: &[TxOutBn]) -> Result<(), TxError> {
         let mut total_output_value = 0;
         for output in &self.tx.outputs {
             total_output_value += output.value;
         }
         let mut total_input_value = 0;
-        for input in &self.tx.inputs {
-            let tx_out_bn = self
-                .tx_out_bn_map
-                .get(&input.input_tx_id.clone(), input.input_tx_out_num);
-            match tx_out_bn {
-                None => return false,
-                Some(tx_out_bn) => total_input_value += tx_out_bn.tx_out.value,
-            }
+        for tx_out_bn in tx_out_bns {
+            total_input_value += tx_out_bn.tx_out.value;
+        }
+        if total_input_value != total_output_value {
+            return Err(TxError::ValueMismatch {
+                input_value: total_input_value,
+                output_value: total_output_value,
+            });
         }
-        total_input_value == total_output_value
+        Ok(())
     }
 
-    pub fn verify_is_not_coinbase(&self) -> bool {
+    pub fn verify_is_not_coinbase(&self) -> Result<(), TxError> {
         // TODO: Allow coinbases to have multiple inputs
         if self.tx.inputs.len() == 1 && self.tx.inputs[0].is_coinbase() {
-            return false;
+            return Err(TxError::Coinbase);
         }
-        true
+        Ok(())
     }
 
-    pub fn verify_lock_abs(&self) -> bool {
+    pub fn verify_lock_abs(&self) -> Result<(), TxError> {
         if self.tx.lock_abs > self.block_num {
-            return false;
+            return Err(TxError::LockAbsNotReached);
         }
-        true
+        Ok(())
     }
 
-    pub fn verify(&mut self) -> bool {
-        if !self.verify_lock_abs() {
-            return false;
-        }
-        if !self.verify_is_not_coinbase() {
-            return false;
-        }
-        if !self.verify_no_double_spend() {
-            return false;
-        }
-        if !self.verify_inputs() {
-            return false;
+    pub fn verify(&mut self) -> Result<(), TxError> {
+        self.verify_lock_abs()?;
+        self.verify_is_not_coinbase()?;
+        // every check after this uses the outputs read here
+        let tx_out_bns = self.get_spent_tx_out_bns()?;
+        for (n_in, tx_out_bn) in tx_out_bns.iter().enumerate() {
+            self.verify_input_script_spending(n_in, tx_out_bn)?;
+            self.verify_input_lock_rel_spending(n_in, tx_out_bn)?;
         }
-        if !self.verify_output_values() {
-            return false;
-        }
-        true
+        self.verify_output_values_spending(&tx_out_bns)?;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::error::EbxError;
     use crate::key_pair::KeyPair;
+    use crate::network_params::NetworkParams;
+    use crate::opcode::Opcode;
     use crate::pkh::Pkh;
     use crate::pkh_key_map::PkhKeyMap;
     use crate::script::Script;
@@ -200,19 +257,19 @@ mod tests {
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
-        assert!(verified_input_script);
+        assert!(verified_input_script.is_ok());
 
         let verified_input_lock_rel = tx_verifier.verify_input_lock_rel(0);
-        assert!(verified_input_lock_rel);
+        assert!(verified_input_lock_rel.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
     }
 
     #[test]
@@ -250,16 +307,16 @@ mod tests {
 
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
         let verified_input = tx_verifier.verify_input_script(0);
-        assert!(verified_input);
+        assert!(verified_input.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(!verified);
+        assert_eq!(verified, Err(TxError::LockAbsNotReached));
     }
 
     #[test]
@@ -300,18 +357,68 @@ mod tests {
 
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
         let verified_input1 = tx_verifier.verify_input_script(0);
-        assert!(verified_input1);
+        assert!(verified_input1.is_ok());
         let verified_input2 = tx_verifier.verify_input_script(1);
-        assert!(verified_input2);
+        assert!(verified_input2.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
+    }
+
+    #[test]
+    fn should_report_the_failing_input() {
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let mut pkh_key_map = PkhKeyMap::new();
+        for i in 0..2 {
+            let key = KeyPair::from_random();
+            let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
+            pkh_key_map.add(key, &pkh.buf);
+            let output = TxOut::new(100, Script::from_pkh_output(&pkh.buf));
+            tx_out_bn_map.add(&[0; 32], i, output, 0);
+        }
+
+        let mut tx_builder = TxBuilder::new(&tx_out_bn_map, Script::from_empty(), 0);
+        tx_builder.add_output(TxOut::new(200, Script::from_empty()));
+        let tx = tx_builder.build().unwrap();
+
+        let mut tx_signer = TxSigner::new(tx, &tx_out_bn_map, &pkh_key_map, 0);
+        tx_signer.sign_input(0).unwrap();
+        tx_signer.sign_input(1).unwrap();
+        let signed_tx = tx_signer.tx;
+
+        // a bad signature on the second input
+        let mut bad_sig_tx = signed_tx.clone();
+        let sig = bad_sig_tx.inputs[1].script.chunks[0]
+            .buffer
+            .as_mut()
+            .unwrap();
+        sig[10] ^= 0xff;
+        let mut tx_verifier = TxVerifier::new(bad_sig_tx, &tx_out_bn_map, 0);
+        assert!(matches!(
+            tx_verifier.verify(),
+            Err(TxError::ScriptFailed { n_in: 1, .. })
+        ));
+
+        // the second input spends an output that does not exist
+        let mut missing_input_tx = signed_tx.clone();
+        missing_input_tx.inputs[1].input_tx_out_num = 5;
+        let mut tx_verifier = TxVerifier::new(missing_input_tx, &tx_out_bn_map, 0);
+        assert_eq!(tx_verifier.verify(), Err(TxError::MissingInput { n_in: 1 }));
+
+        // both inputs spend the same output
+        let mut double_spend_tx = signed_tx;
+        double_spend_tx.inputs[1].input_tx_out_num = 0;
+        let mut tx_verifier = TxVerifier::new(double_spend_tx, &tx_out_bn_map, 0);
+        assert_eq!(
+            tx_verifier.verify(),
+            Err(TxError::DuplicateInput { n_in: 1 })
+        );
     }
 
     #[test]
@@ -323,7 +430,7 @@ mod tests {
             let key = KeyPair::from_random();
             let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
             pkh_key_map.add(key, &pkh.buf);
-            let script = Script::from_pkhx_1h_output(&pkh.buf);
+            let script = Script::from_pkhx_1h_output(&pkh.buf, &NetworkParams::MAINNET);
             let output = TxOut::new(100, script);
             let block_num = 0;
             tx_out_bn_map.add(&[0; 32], i, output.clone(), block_num);
@@ -350,32 +457,32 @@ mod tests {
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
-        assert!(verified_input_script);
+        assert!(verified_input_script.is_ok());
 
         let verified_input_lock_rel = tx_verifier.verify_input_lock_rel(0);
-        assert!(verified_input_lock_rel);
+        assert!(verified_input_lock_rel.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
     }
 
     #[test]
     fn should_sign_and_verify_expired_pkhx_1h() {
         let mut tx_out_bn_map = TxOutBnMap::new();
         let mut pkh_key_map = PkhKeyMap::new();
-        let working_block_num: u32 = Script::PKHX_1H_LOCK_REL;
+        let working_block_num: u32 = NetworkParams::MAINNET.pkhx_1h_lock_rel;
         // generate 5 keys, 5 outputs, and add them to the tx_out_map
         for i in 0..5 {
             let key = KeyPair::from_random();
             let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
             pkh_key_map.add(key, &pkh.buf);
-            let script = Script::from_pkhx_1h_output(&pkh.buf);
+            let script = Script::from_pkhx_1h_output(&pkh.buf, &NetworkParams::MAINNET);
             let output = TxOut::new(100, script);
             let block_num = 0;
             tx_out_bn_map.add(&[0; 32], i, output.clone(), block_num);
@@ -385,7 +492,12 @@ mod tests {
         let mut tx_builder = TxBuilder::new(&tx_out_bn_map, change_script, 0);
 
         let expired_input_script = Script::from_expired_pkhx_input();
-        let tx_in = TxIn::new([0; 32], 0, expired_input_script, Script::PKHX_1H_LOCK_REL);
+        let tx_in = TxIn::new(
+            [0; 32],
+            0,
+            expired_input_script,
+            NetworkParams::MAINNET.pkhx_1h_lock_rel,
+        );
         tx_builder.add_input(tx_in, 100);
 
         let tx_out = TxOut::new(50, Script::from_empty());
@@ -409,19 +521,19 @@ mod tests {
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
-        assert!(verified_input_script);
+        assert!(verified_input_script.is_ok());
 
         let verified_input_lock_rel = tx_verifier.verify_input_lock_rel(0);
-        assert!(verified_input_lock_rel);
+        assert!(verified_input_lock_rel.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
     }
 
     #[test]
@@ -433,7 +545,7 @@ mod tests {
             let key = KeyPair::from_random();
             let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
             pkh_key_map.add(key, &pkh.buf);
-            let script = Script::from_pkhx_90d_output(&pkh.buf);
+            let script = Script::from_pkhx_90d_output(&pkh.buf, &NetworkParams::MAINNET);
             let output = TxOut::new(100, script);
             let block_num = 0;
             tx_out_bn_map.add(&[0; 32], i, output.clone(), block_num);
@@ -460,32 +572,32 @@ mod tests {
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
-        assert!(verified_input_script);
+        assert!(verified_input_script.is_ok());
 
         let verified_input_lock_rel = tx_verifier.verify_input_lock_rel(0);
-        assert!(verified_input_lock_rel);
+        assert!(verified_input_lock_rel.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
     }
 
     #[test]
     fn should_sign_and_verify_expired_pkhx_90d() {
         let mut tx_out_bn_map = TxOutBnMap::new();
         let mut pkh_key_map = PkhKeyMap::new();
-        let working_block_num: u32 = Script::PKHX_90D_LOCK_REL;
+        let working_block_num: u32 = NetworkParams::MAINNET.pkhx_90d_lock_rel;
         // generate 5 keys, 5 outputs, and add them to the tx_out_map
         for i in 0..5 {
             let key = KeyPair::from_random();
             let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
             pkh_key_map.add(key, &pkh.buf);
-            let script = Script::from_pkhx_90d_output(&pkh.buf);
+            let script = Script::from_pkhx_90d_output(&pkh.buf, &NetworkParams::MAINNET);
             let output = TxOut::new(100, script);
             let block_num = 0;
             tx_out_bn_map.add(&[0; 32], i, output.clone(), block_num);
@@ -495,7 +607,12 @@ mod tests {
         let mut tx_builder = TxBuilder::new(&tx_out_bn_map, change_script, 0);
 
         let expired_input_script = Script::from_expired_pkhx_input();
-        let tx_in = TxIn::new([0; 32], 0, expired_input_script, Script::PKHX_90D_LOCK_REL);
+        let tx_in = TxIn::new(
+            [0; 32],
+            0,
+            expired_input_script,
+            NetworkParams::MAINNET.pkhx_90d_lock_rel,
+        );
         tx_builder.add_input(tx_in, 100);
 
         let tx_out = TxOut::new(50, Script::from_empty());
@@ -519,19 +636,19 @@ mod tests {
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
-        assert!(verified_input_script);
+        assert!(verified_input_script.is_ok());
 
         let verified_input_lock_rel = tx_verifier.verify_input_lock_rel(0);
-        assert!(verified_input_lock_rel);
+        assert!(verified_input_lock_rel.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
     }
 
     #[test]
@@ -543,7 +660,8 @@ mod tests {
             let key = KeyPair::from_random();
             let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
             pkh_key_map.add(key, &pkh.buf);
-            let script = Script::from_pkhxr_1h_40m_output(&pkh.buf, &pkh.buf);
+            let script =
+                Script::from_pkhxr_1h_40m_output(&pkh.buf, &pkh.buf, &NetworkParams::MAINNET);
             let output = TxOut::new(100, script);
             let block_num = 0;
             tx_out_bn_map.add(&[0; 32], i, output.clone(), block_num);
@@ -570,32 +688,33 @@ mod tests {
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
-        assert!(verified_input_script);
+        assert!(verified_input_script.is_ok());
 
         let verified_input_lock_rel = tx_verifier.verify_input_lock_rel(0);
-        assert!(verified_input_lock_rel);
+        assert!(verified_input_lock_rel.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
     }
 
     #[test]
     fn should_sign_and_verify_recoverable_pkhxr_1h_40m() {
         let mut tx_out_bn_map = TxOutBnMap::new();
         let mut pkh_key_map = PkhKeyMap::new();
-        let working_block_num: u32 = Script::PKHXR_1H_40M_R_LOCK_REL;
+        let working_block_num: u32 = NetworkParams::MAINNET.pkhxr_1h_40m_r_lock_rel;
         // generate 5 keys, 5 outputs, and add them to the tx_out_map
         for i in 0..5 {
             let key = KeyPair::from_random();
             let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
             pkh_key_map.add(key, &pkh.buf);
-            let script = Script::from_pkhxr_1h_40m_output(&[0; 32], &pkh.buf);
+            let script =
+                Script::from_pkhxr_1h_40m_output(&[0; 32], &pkh.buf, &NetworkParams::MAINNET);
             let output = TxOut::new(100, script);
             let block_num = 0;
             tx_out_bn_map.add(&[0; 32], i, output.clone(), block_num);
@@ -609,7 +728,7 @@ mod tests {
             [0; 32],
             0,
             recovery_input_script,
-            Script::PKHXR_1H_40M_R_LOCK_REL,
+            NetworkParams::MAINNET.pkhxr_1h_40m_r_lock_rel,
         );
         tx_builder.add_input(tx_in, 100);
 
@@ -632,32 +751,33 @@ mod tests {
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
-        assert!(verified_input_script);
+        assert!(verified_input_script.is_ok());
 
         let verified_input_lock_rel = tx_verifier.verify_input_lock_rel(0);
-        assert!(verified_input_lock_rel);
+        assert!(verified_input_lock_rel.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
     }
 
     #[test]
     fn should_sign_and_verify_expired_pkhxr_1h_40m() {
         let mut tx_out_bn_map = TxOutBnMap::new();
         let mut pkh_key_map = PkhKeyMap::new();
-        let working_block_num: u32 = Script::PKHXR_1H_40M_X_LOCK_REL;
+        let working_block_num: u32 = NetworkParams::MAINNET.pkhxr_1h_40m_x_lock_rel;
         // generate 5 keys, 5 outputs, and add them to the tx_out_map
         for i in 0..5 {
             let key = KeyPair::from_random();
             let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
             pkh_key_map.add(key, &pkh.buf);
-            let script = Script::from_pkhxr_1h_40m_output(&[0; 32], &pkh.buf);
+            let script =
+                Script::from_pkhxr_1h_40m_output(&[0; 32], &pkh.buf, &NetworkParams::MAINNET);
             let output = TxOut::new(100, script);
             let block_num = 0;
             tx_out_bn_map.add(&[0; 32], i, output.clone(), block_num);
@@ -671,7 +791,7 @@ mod tests {
             [0; 32],
             0,
             recovery_input_script,
-            Script::PKHXR_1H_40M_X_LOCK_REL,
+            NetworkParams::MAINNET.pkhxr_1h_40m_x_lock_rel,
         );
         tx_builder.add_input(tx_in, 100);
 
@@ -694,19 +814,19 @@ mod tests {
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
-        assert!(verified_input_script);
+        assert!(verified_input_script.is_ok());
 
         let verified_input_lock_rel = tx_verifier.verify_input_lock_rel(0);
-        assert!(verified_input_lock_rel);
+        assert!(verified_input_lock_rel.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
     }
 
     #[test]
@@ -718,7 +838,8 @@ mod tests {
             let key = KeyPair::from_random();
             let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
             pkh_key_map.add(key, &pkh.buf);
-            let script = Script::from_pkhxr_90d_60d_output(&pkh.buf, &pkh.buf);
+            let script =
+                Script::from_pkhxr_90d_60d_output(&pkh.buf, &pkh.buf, &NetworkParams::MAINNET);
             let output = TxOut::new(100, script);
             let block_num = 0;
             tx_out_bn_map.add(&[0; 32], i, output.clone(), block_num);
@@ -745,32 +866,33 @@ mod tests {
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
-        assert!(verified_input_script);
+        assert!(verified_input_script.is_ok());
 
         let verified_input_lock_rel = tx_verifier.verify_input_lock_rel(0);
-        assert!(verified_input_lock_rel);
+        assert!(verified_input_lock_rel.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
     }
 
     #[test]
     fn should_sign_and_verify_recoverable_pkhxr_90d_60d() {
         let mut tx_out_bn_map = TxOutBnMap::new();
         let mut pkh_key_map = PkhKeyMap::new();
-        let working_block_num: u32 = Script::PKHXR_90D_60D_R_LOCK_REL;
+        let working_block_num: u32 = NetworkParams::MAINNET.pkhxr_90d_60d_r_lock_rel;
         // generate 5 keys, 5 outputs, and add them to the tx_out_map
         for i in 0..5 {
             let key = KeyPair::from_random();
             let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
             pkh_key_map.add(key, &pkh.buf);
-            let script = Script::from_pkhxr_90d_60d_output(&[0; 32], &pkh.buf);
+            let script =
+                Script::from_pkhxr_90d_60d_output(&[0; 32], &pkh.buf, &NetworkParams::MAINNET);
             let output = TxOut::new(100, script);
             let block_num = 0;
             tx_out_bn_map.add(&[0; 32], i, output.clone(), block_num);
@@ -784,7 +906,7 @@ mod tests {
             [0; 32],
             0,
             recovery_input_script,
-            Script::PKHXR_90D_60D_R_LOCK_REL,
+            NetworkParams::MAINNET.pkhxr_90d_60d_r_lock_rel,
         );
         tx_builder.add_input(tx_in, 100);
 
@@ -807,32 +929,33 @@ mod tests {
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
-        assert!(verified_input_script);
+        assert!(verified_input_script.is_ok());
 
         let verified_input_lock_rel = tx_verifier.verify_input_lock_rel(0);
-        assert!(verified_input_lock_rel);
+        assert!(verified_input_lock_rel.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
     }
 
     #[test]
     fn should_sign_and_verify_expired_pkhxr_90d_60d() {
         let mut tx_out_bn_map = TxOutBnMap::new();
         let mut pkh_key_map = PkhKeyMap::new();
-        let working_block_num: u32 = Script::PKHXR_90D_60D_X_LOCK_REL;
+        let working_block_num: u32 = NetworkParams::MAINNET.pkhxr_90d_60d_x_lock_rel;
         // generate 5 keys, 5 outputs, and add them to the tx_out_map
         for i in 0..5 {
             let key = KeyPair::from_random();
             let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
             pkh_key_map.add(key, &pkh.buf);
-            let script = Script::from_pkhxr_90d_60d_output(&[0; 32], &pkh.buf);
+            let script =
+                Script::from_pkhxr_90d_60d_output(&[0; 32], &pkh.buf, &NetworkParams::MAINNET);
             let output = TxOut::new(100, script);
             let block_num = 0;
             tx_out_bn_map.add(&[0; 32], i, output.clone(), block_num);
@@ -846,7 +969,7 @@ mod tests {
             [0; 32],
             0,
             recovery_input_script,
-            Script::PKHXR_90D_60D_X_LOCK_REL,
+            NetworkParams::MAINNET.pkhxr_90d_60d_x_lock_rel,
         );
         tx_builder.add_input(tx_in, 100);
 
@@ -869,18 +992,133 @@ mod tests {
         let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
-        assert!(verified_input_script);
+        assert!(verified_input_script.is_ok());
 
         let verified_input_lock_rel = tx_verifier.verify_input_lock_rel(0);
-        assert!(verified_input_lock_rel);
+        assert!(verified_input_lock_rel.is_ok());
 
         let verified_scripts = tx_verifier.verify_inputs();
-        assert!(verified_scripts);
+        assert!(verified_scripts.is_ok());
 
         let verified_output_values = tx_verifier.verify_output_values();
-        assert!(verified_output_values);
+        assert!(verified_output_values.is_ok());
 
         let verified = tx_verifier.verify();
-        assert!(verified);
+        assert!(verified.is_ok());
+    }
+
+    // a tx with one input spending a pkh output
+    fn signed_pkh_tx() -> (Tx, TxOutBnMap) {
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let mut pkh_key_map = PkhKeyMap::new();
+        let key = KeyPair::from_random();
+        let pkh = Pkh::from_pub_key_buffer(key.clone().pub_key.buf.to_vec());
+        pkh_key_map.add(key, &pkh.buf);
+        let output = TxOut::new(100, Script::from_pkh_output(&pkh.buf));
+        tx_out_bn_map.add(&[0; 32], 0, output, 0);
+
+        let tx_in = TxIn::new([0; 32], 0, Script::from_pkh_input_placeholder(), 0);
+        let tx = Tx::new(
+            0,
+            vec![tx_in],
+            vec![TxOut::new(100, Script::from_empty())],
+            0,
+        );
+        let mut tx_signer = TxSigner::new(tx, &tx_out_bn_map, &pkh_key_map, 0);
+        tx_signer.sign_input(0).unwrap();
+        (tx_signer.tx, tx_out_bn_map)
+    }
+
+    #[test]
+    fn should_enforce_script_limits() {
+        let (signed_tx, tx_out_bn_map) = signed_pkh_tx();
+
+        let mut tx_verifier = TxVerifier::new(signed_tx.clone(), &tx_out_bn_map, 0);
+        assert_eq!(tx_verifier.verify(), Ok(()));
+
+        // the input script is too large
+        let input_script_size = signed_tx.inputs[0].script.to_buf().len();
+        tx_verifier.script_limits.max_script_size = input_script_size - 1;
+        assert_eq!(
+            tx_verifier.verify(),
+            Err(TxError::ScriptLimitExceeded {
+                n_in: 0,
+                error: ScriptLimitError::ScriptSize
+            })
+        );
+
+        // the signature and pub key pushed by the input script are too large
+        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
+        tx_verifier.script_limits.max_element_size = 31;
+        let error = tx_verifier.verify().unwrap_err();
+        assert_eq!(
+            error,
+            TxError::ScriptLimitExceeded {
+                n_in: 0,
+                error: ScriptLimitError::ElementSize
+            }
+        );
+        assert_eq!(error.to_string(), "input 0: stack element too large");
+    }
+
+    #[test]
+    fn should_require_minimal_pushes() {
+        let (signed_tx, tx_out_bn_map) = signed_pkh_tx();
+        let mut tx_verifier = TxVerifier::new(signed_tx.clone(), &tx_out_bn_map, 0);
+        tx_verifier.require_minimal = true;
+        assert_eq!(tx_verifier.verify(), Ok(()));
+
+        // the same pub key pushed with PUSHDATA2 is a different tx with the
+        // same signature
+        let mut malleated_tx = signed_tx;
+        malleated_tx.inputs[0].script.chunks[1].opcode = Opcode::OP_PUSHDATA2;
+        let mut tx_verifier = TxVerifier::new(malleated_tx.clone(), &tx_out_bn_map, 0);
+        assert_eq!(tx_verifier.verify(), Ok(()));
+        tx_verifier.require_minimal = true;
+        assert_eq!(
+            tx_verifier.verify(),
+            Err(TxError::NonMinimalInput { n_in: 0 })
+        );
+    }
+
+    // counts the reads, like the seeks of a FileUtxoStore
+    struct CountingUtxoStore {
+        tx_out_bn_map: TxOutBnMap,
+        n_reads: std::cell::Cell<usize>,
+    }
+
+    impl UtxoStore for CountingUtxoStore {
+        fn get_tx_out_bn(&self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+            self.n_reads.set(self.n_reads.get() + 1);
+            self.tx_out_bn_map.get_tx_out_bn(out_point)
+        }
+
+        fn add_tx_out_bn(
+            &mut self,
+            out_point: OutPoint,
+            tx_out_bn: TxOutBn,
+        ) -> Result<(), EbxError> {
+            self.tx_out_bn_map.add_tx_out_bn(out_point, tx_out_bn)
+        }
+
+        fn remove_tx_out_bn(&mut self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+            self.tx_out_bn_map.remove_tx_out_bn(out_point)
+        }
+
+        fn get_all_tx_out_bns(&self) -> Result<Vec<(OutPoint, TxOutBn)>, EbxError> {
+            self.tx_out_bn_map.get_all_tx_out_bns()
+        }
+    }
+
+    #[test]
+    fn should_read_each_input_once() {
+        let (signed_tx, tx_out_bn_map) = signed_pkh_tx();
+        let store = CountingUtxoStore {
+            tx_out_bn_map,
+            n_reads: std::cell::Cell::new(0),
+        };
+        let mut tx_verifier = TxVerifier::new(signed_tx, &store, 0);
+        assert_eq!(tx_verifier.verify(), Ok(()));
+        assert_eq!(store.n_reads.get(), 1);
     }
 }
@@ -0,0 +1,376 @@
+use crate::block::Block;
+use crate::buf_reader::BufReader;
+use crate::buf_writer::BufWriter;
+use crate::error::EbxError;
+use crate::out_point::OutPoint;
+use crate::tx::Tx;
+use crate::tx_out::TxOut;
+use crate::tx_out_bn::TxOutBn;
+use crate::tx_out_bn_map::TxOutBnMap;
+use crate::utxo_store::UtxoStore;
+
+// the outputs spent by a block, in the order they were spent. the outpoints
+// themselves are not stored because they are the inputs of the block's txs.
+// one entry per tx, including an empty entry for the coinbase tx.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BlockUndo {
+    pub tx_undos: Vec<Vec<TxOutBn>>,
+}
+
+impl BlockUndo {
+    pub fn new(tx_undos: Vec<Vec<TxOutBn>>) -> Self {
+        Self { tx_undos }
+    }
+
+    pub fn to_buf(&self) -> Vec<u8> {
+        let mut bw = BufWriter::new();
+        bw.write_var_int(self.tx_undos.len() as u64);
+        for tx_undo in &self.tx_undos {
+            bw.write_var_int(tx_undo.len() as u64);
+            for tx_out_bn in tx_undo {
+                bw.write_u32_be(tx_out_bn.block_num);
+                bw.write(tx_out_bn.tx_out.to_buf());
+            }
+        }
+        bw.to_buf()
+    }
+
+    pub fn from_buf(buf: Vec<u8>) -> Result<Self, EbxError> {
+        let mut br = BufReader::new(buf);
+        Self::from_buf_reader(&mut br)
+    }
+
+    pub fn from_buf_reader(br: &mut BufReader) -> Result<Self, EbxError> {
+        let tx_count = br.read_var_int()? as usize;
+        let mut tx_undos = Vec::new();
+        for _ in 0..tx_count {
+            let input_count = br.read_var_int()? as usize;
+            let mut tx_undo = Vec::new();
+            for _ in 0..input_count {
+                let block_num = br.read_u32_be()?;
+                let tx_out = TxOut::from_buf_reader(br)?;
+                tx_undo.push(TxOutBn { tx_out, block_num });
+            }
+            tx_undos.push(tx_undo);
+        }
+        Ok(Self::new(tx_undos))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UtxoSet<S: UtxoStore = TxOutBnMap> {
+    pub utxo_store: S,
+}
+
+impl<S: UtxoStore> UtxoSet<S> {
+    pub fn new(utxo_store: S) -> Self {
+        Self { utxo_store }
+    }
+
+    pub fn get(&self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+        self.utxo_store.get_tx_out_bn(out_point)
+    }
+
+    // spend the inputs and add the outputs of one tx. either all of it is
+    // applied or none of it is. if the store fails partway through, what was
+    // already applied is undone, which only fails if the store fails again.
+    pub fn connect_tx(&mut self, tx: &Tx, block_num: u32) -> Result<Vec<TxOutBn>, EbxError> {
+        let tx_id = tx.id();
+        for tx_out_num in 0..tx.outputs.len() as u32 {
+            if self
+                .utxo_store
+                .contains_tx_out_bn(&OutPoint::new(tx_id, tx_out_num))?
+            {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "tx output already exists".to_string(),
+                });
+            }
+        }
+        let mut tx_undo = Vec::new();
+        if !tx.is_coinbase() {
+            for (i, tx_in) in tx.inputs.iter().enumerate() {
+                let out_point = OutPoint::new(tx_in.input_tx_id, tx_in.input_tx_out_num);
+                let is_duplicate = tx.inputs[..i].iter().any(|prev| {
+                    OutPoint::new(prev.input_tx_id, prev.input_tx_out_num) == out_point
+                });
+                if is_duplicate {
+                    return Err(EbxError::GenericError {
+                        source: None,
+                        message: "tx spends the same output twice".to_string(),
+                    });
+                }
+                // outputs created later in the same block have not been added
+                // yet, so spending them fails here
+                match self.utxo_store.get_tx_out_bn(&out_point)? {
+                    Some(tx_out_bn) => tx_undo.push(tx_out_bn),
+                    None => {
+                        return Err(EbxError::GenericError {
+                            source: None,
+                            message: "tx input spends missing or spent output".to_string(),
+                        })
+                    }
+                }
+            }
+        }
+        let mut n_spent = 0;
+        if let Err(e) = self.apply_tx(tx, block_num, &mut n_spent) {
+            for tx_out_num in 0..tx.outputs.len() as u32 {
+                let _ = self
+                    .utxo_store
+                    .remove_tx_out_bn(&OutPoint::new(tx_id, tx_out_num));
+            }
+            for (tx_in, tx_out_bn) in tx.inputs.iter().zip(&tx_undo).take(n_spent) {
+                let out_point = OutPoint::new(tx_in.input_tx_id, tx_in.input_tx_out_num);
+                let _ = self.utxo_store.add_tx_out_bn(out_point, tx_out_bn.clone());
+            }
+            return Err(e);
+        }
+        Ok(tx_undo)
+    }
+
+    // the store changes of connect_tx, once the tx has been checked
+    fn apply_tx(&mut self, tx: &Tx, block_num: u32, n_spent: &mut usize) -> Result<(), EbxError> {
+        if !tx.is_coinbase() {
+            for tx_in in &tx.inputs {
+                let out_point = OutPoint::new(tx_in.input_tx_id, tx_in.input_tx_out_num);
+                self.utxo_store.remove_tx_out_bn(&out_point)?;
+                *n_spent += 1;
+            }
+        }
+        self.utxo_store.add_tx_outputs(tx, block_num)
+    }
+
+    pub fn disconnect_tx(&mut self, tx: &Tx, tx_undo: &[TxOutBn]) -> Result<(), EbxError> {
+        let tx_id = tx.id();
+        for tx_out_num in 0..tx.outputs.len() as u32 {
+            if !self
+                .utxo_store
+                .contains_tx_out_bn(&OutPoint::new(tx_id, tx_out_num))?
+            {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "tx output to disconnect is missing or spent".to_string(),
+                });
+            }
+        }
+        let spent_count = if tx.is_coinbase() { 0 } else { tx.inputs.len() };
+        if tx_undo.len() != spent_count {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "undo data does not match tx".to_string(),
+            });
+        }
+        for tx_out_num in 0..tx.outputs.len() as u32 {
+            self.utxo_store
+                .remove_tx_out_bn(&OutPoint::new(tx_id, tx_out_num))?;
+        }
+        for (tx_in, tx_out_bn) in tx.inputs.iter().zip(tx_undo) {
+            let out_point = OutPoint::new(tx_in.input_tx_id, tx_in.input_tx_out_num);
+            self.utxo_store
+                .add_tx_out_bn(out_point, tx_out_bn.clone())?;
+        }
+        Ok(())
+    }
+
+    pub fn connect_block(&mut self, block: &Block) -> Result<BlockUndo, EbxError> {
+        let block_num = block.header.block_num;
+        let mut tx_undos = Vec::new();
+        for tx in &block.txs {
+            match self.connect_tx(tx, block_num) {
+                Ok(tx_undo) => tx_undos.push(tx_undo),
+                Err(e) => {
+                    // roll back the txs that were already connected
+                    let connected_txs = &block.txs[..tx_undos.len()];
+                    for (tx, tx_undo) in connected_txs.iter().zip(&tx_undos).rev() {
+                        self.disconnect_tx(tx, tx_undo)?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(BlockUndo::new(tx_undos))
+    }
+
+    pub fn disconnect_block(
+        &mut self,
+        block: &Block,
+        block_undo: BlockUndo,
+    ) -> Result<(), EbxError> {
+        if block_undo.tx_undos.len() != block.txs.len() {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "undo data does not match block".to_string(),
+            });
+        }
+        for (tx, tx_undo) in block.txs.iter().zip(&block_undo.tx_undos).rev() {
+            self.disconnect_tx(tx, tx_undo)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Header;
+    use crate::network_params::NetworkParams;
+    use crate::script::Script;
+    use crate::tx_in::TxIn;
+
+    fn coinbase_tx(block_num: u32) -> Tx {
+        Tx::from_coinbase(
+            Script::from_empty(),
+            Script::from_pkh_output(&[block_num as u8; 32]),
+            100,
+            block_num,
+        )
+    }
+
+    fn spend_tx(tx_id: [u8; 32], tx_out_num: u32, value: u64) -> Tx {
+        let tx_in = TxIn::new(tx_id, tx_out_num, Script::from_empty(), 0);
+        let tx_out = TxOut::new(value, Script::from_pkh_output(&[9; 32]));
+        Tx::new(1, vec![tx_in], vec![tx_out], 0)
+    }
+
+    fn block(block_num: u32, txs: Vec<Tx>) -> Block {
+        let mut header = Header::from_genesis(0, &NetworkParams::MAINNET);
+        header.block_num = block_num;
+        Block::new(header, txs)
+    }
+
+    #[test]
+    fn test_connect_and_disconnect_block() {
+        let mut utxo_set: UtxoSet = UtxoSet::default();
+        let coinbase0 = coinbase_tx(0);
+        let block0 = block(0, vec![coinbase0.clone()]);
+        let undo0 = utxo_set.connect_block(&block0).unwrap();
+        assert_eq!(undo0, BlockUndo::new(vec![vec![]]));
+        let before = utxo_set.utxo_store.clone();
+
+        // spend the coinbase, then spend that output in the same block
+        let tx1 = spend_tx(coinbase0.id(), 0, 100);
+        let tx2 = spend_tx(tx1.id(), 0, 100);
+        let block1 = block(1, vec![coinbase_tx(1), tx1.clone(), tx2.clone()]);
+        let undo1 = utxo_set.connect_block(&block1).unwrap();
+        assert!(utxo_set
+            .get(&OutPoint::new(coinbase0.id(), 0))
+            .unwrap()
+            .is_none());
+        assert!(utxo_set.get(&OutPoint::new(tx1.id(), 0)).unwrap().is_none());
+        assert_eq!(
+            utxo_set
+                .get(&OutPoint::new(tx2.id(), 0))
+                .unwrap()
+                .unwrap()
+                .block_num,
+            1
+        );
+        assert_eq!(
+            undo1.tx_undos[1],
+            vec![before.get(&coinbase0.id(), 0).unwrap().clone()]
+        );
+
+        let undo1 = BlockUndo::from_buf(undo1.to_buf()).unwrap();
+        utxo_set.disconnect_block(&block1, undo1).unwrap();
+        assert_eq!(utxo_set.utxo_store.map, before.map);
+    }
+
+    #[test]
+    fn test_reject_spend_of_later_output_in_same_block() {
+        let mut utxo_set: UtxoSet = UtxoSet::default();
+        let coinbase0 = coinbase_tx(0);
+        utxo_set
+            .connect_block(&block(0, vec![coinbase0.clone()]))
+            .unwrap();
+        let before = utxo_set.utxo_store.clone();
+
+        let tx1 = spend_tx(coinbase0.id(), 0, 100);
+        let tx2 = spend_tx(tx1.id(), 0, 100);
+        let block1 = block(1, vec![coinbase_tx(1), tx2, tx1]);
+        assert!(utxo_set.connect_block(&block1).is_err());
+        // a failed connect leaves the set unchanged
+        assert_eq!(utxo_set.utxo_store.map, before.map);
+    }
+
+    #[test]
+    fn test_reject_double_spend() {
+        let mut utxo_set: UtxoSet = UtxoSet::default();
+        let coinbase0 = coinbase_tx(0);
+        utxo_set
+            .connect_block(&block(0, vec![coinbase0.clone()]))
+            .unwrap();
+
+        let tx1 = spend_tx(coinbase0.id(), 0, 100);
+        let tx2 = spend_tx(coinbase0.id(), 0, 50);
+        let block1 = block(1, vec![coinbase_tx(1), tx1, tx2]);
+        assert!(utxo_set.connect_block(&block1).is_err());
+        assert!(utxo_set
+            .get(&OutPoint::new(coinbase0.id(), 0))
+            .unwrap()
+            .is_some());
+    }
+
+    // a store whose next add fails, like a full disk
+    #[derive(Default)]
+    struct FailingUtxoStore {
+        tx_out_bn_map: TxOutBnMap,
+        fail_next_add: bool,
+    }
+
+    impl UtxoStore for FailingUtxoStore {
+        fn get_tx_out_bn(&self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+            self.tx_out_bn_map.get_tx_out_bn(out_point)
+        }
+
+        fn add_tx_out_bn(
+            &mut self,
+            out_point: OutPoint,
+            tx_out_bn: TxOutBn,
+        ) -> Result<(), EbxError> {
+            if self.fail_next_add {
+                self.fail_next_add = false;
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "disk full".to_string(),
+                });
+            }
+            self.tx_out_bn_map.add_tx_out_bn(out_point, tx_out_bn)
+        }
+
+        fn remove_tx_out_bn(&mut self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError> {
+            self.tx_out_bn_map.remove_tx_out_bn(out_point)
+        }
+
+        fn get_all_tx_out_bns(&self) -> Result<Vec<(OutPoint, TxOutBn)>, EbxError> {
+            self.tx_out_bn_map.get_all_tx_out_bns()
+        }
+    }
+
+    #[test]
+    fn test_store_error_leaves_set_unchanged() {
+        let mut utxo_set = UtxoSet::new(FailingUtxoStore::default());
+        let coinbase0 = coinbase_tx(0);
+        utxo_set
+            .connect_block(&block(0, vec![coinbase0.clone()]))
+            .unwrap();
+        let before = utxo_set.utxo_store.tx_out_bn_map.map.clone();
+
+        // the input is spent before adding the output fails
+        utxo_set.utxo_store.fail_next_add = true;
+        let tx1 = spend_tx(coinbase0.id(), 0, 100);
+        let error = utxo_set.connect_tx(&tx1, 1).unwrap_err();
+        assert_eq!(error.to_string(), "ebx error: disk full");
+        assert_eq!(utxo_set.utxo_store.tx_out_bn_map.map, before);
+    }
+
+    #[test]
+    fn test_reject_mismatched_undo() {
+        let mut utxo_set: UtxoSet = UtxoSet::default();
+        let block0 = block(0, vec![coinbase_tx(0)]);
+        utxo_set.connect_block(&block0).unwrap();
+        assert!(utxo_set
+            .disconnect_block(&block0, BlockUndo::default())
+            .is_err());
+    }
+}
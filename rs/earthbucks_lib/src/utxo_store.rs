@@ -0,0 +1,33 @@
+use crate::error::EbxError;
+use crate::out_point::OutPoint;
+use crate::tx::Tx;
+use crate::tx_out_bn::TxOutBn;
+
+// storage for unspent tx outputs. TxOutBnMap keeps everything in memory and
+// FileUtxoStore keeps the outputs on disk with only an index in memory.
+pub trait UtxoStore {
+    fn get_tx_out_bn(&self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError>;
+
+    fn add_tx_out_bn(&mut self, out_point: OutPoint, tx_out_bn: TxOutBn) -> Result<(), EbxError>;
+
+    fn remove_tx_out_bn(&mut self, out_point: &OutPoint) -> Result<Option<TxOutBn>, EbxError>;
+
+    fn get_all_tx_out_bns(&self) -> Result<Vec<(OutPoint, TxOutBn)>, EbxError>;
+
+    fn contains_tx_out_bn(&self, out_point: &OutPoint) -> Result<bool, EbxError> {
+        Ok(self.get_tx_out_bn(out_point)?.is_some())
+    }
+
+    fn add_tx_outputs(&mut self, tx: &Tx, block_num: u32) -> Result<(), EbxError> {
+        let tx_id = tx.id();
+        for (tx_out_num, tx_out) in tx.outputs.iter().enumerate() {
+            let out_point = OutPoint::new(tx_id, tx_out_num as u32);
+            let tx_out_bn = TxOutBn {
+                tx_out: tx_out.clone(),
+                block_num,
+            };
+            self.add_tx_out_bn(out_point, tx_out_bn)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,19 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use earthbucks_pow5::{get_work_par, get_work_par_reference};
+
+const HEADER_SIZE: usize = 217;
+
+// with the "fast" feature, get_work_par uses the blake3 crate. compare with:
+// cargo bench -p earthbucks_pow5 --features fast
+fn bench_get_work_par(c: &mut Criterion) {
+    let header = vec![0x11; HEADER_SIZE];
+    c.bench_function("get_work_par_reference", |b| {
+        b.iter(|| get_work_par_reference(black_box(header.clone())).unwrap())
+    });
+    c.bench_function("get_work_par", |b| {
+        b.iter(|| get_work_par(black_box(header.clone())).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_get_work_par);
+criterion_main!(benches);
@@ -22,11 +22,35 @@ const WORK_PAR_END: usize = 1 + 32 + 32 + 8 + 8 + 4 + 32 + 32 + 2 + 32 + 2 + 32;
 //   the "parallel work" or "work_par"
 #[wasm_bindgen]
 pub fn get_work_par(header: Vec<u8>) -> Result<Vec<u8>, String> {
+    get_work_par_with_hash(header, blake3_hash)
+}
+
+// always uses the reference blake3, even with the "fast" feature enabled
+pub fn get_work_par_reference(header: Vec<u8>) -> Result<Vec<u8>, String> {
+    get_work_par_with_hash(header, blake3_reference_hash)
+}
+
+// with the "fast" feature, blake3 is computed with the blake3 crate, which is
+// much faster than the reference implementation and gives identical results
+#[cfg(feature = "fast")]
+fn blake3_hash(input: Vec<u8>) -> Vec<u8> {
+    blake3::hash(&input).as_bytes().to_vec()
+}
+
+#[cfg(not(feature = "fast"))]
+fn blake3_hash(input: Vec<u8>) -> Vec<u8> {
+    blake3_reference_hash(input)
+}
+
+fn get_work_par_with_hash(
+    header: Vec<u8>,
+    blake3_hash: fn(Vec<u8>) -> Vec<u8>,
+) -> Result<Vec<u8>, String> {
     if header.len() != HEADER_SIZE {
         return Err("header is not the correct size".to_string());
     }
     // first, hash the header with blake3
-    let matrix_a_row_1 = blake3_reference_hash(header.clone());
+    let matrix_a_row_1 = blake3_hash(header.clone());
 
     // next, we will do the following. we will hash this hash over and over, 32
     // times. we will then multiply and add (similar to matmul) each value of
@@ -37,7 +61,7 @@ pub fn get_work_par(header: Vec<u8>) -> Result<Vec<u8>, String> {
     #[allow(clippy::needless_range_loop)]
     for i in 0..32 {
         // now, hash the working column to get a new matrix_B_working_column
-        matrix_c_working_column = blake3_reference_hash(matrix_c_working_column.to_vec());
+        matrix_c_working_column = blake3_hash(matrix_c_working_column.to_vec());
 
         // the working column has been updated. now we "multiply and add" it
         // against the header hash.
@@ -61,7 +85,7 @@ pub fn get_work_par(header: Vec<u8>) -> Result<Vec<u8>, String> {
 
     // we have now produced the first row of a matrix C via a matmul-esque operation. we will now
     // hash this row to get the "parallel work" or "work_par".
-    let work_par = blake3_reference_hash(final_pre_hash.to_vec());
+    let work_par = blake3_hash(final_pre_hash.to_vec());
 
     Ok(work_par.to_vec())
 }
@@ -82,10 +106,10 @@ pub fn elementary_iteration(header: Vec<u8>) -> Result<Vec<u8>, String> {
     }
 
     // now we need to hash the header
-    let hash_1 = blake3_reference_hash(working_header);
+    let hash_1 = blake3_hash(working_header);
 
     // now we need to hash it again because the "id" is actually the hash of the hash
-    let hash_2 = blake3_reference_hash(hash_1);
+    let hash_2 = blake3_hash(hash_1);
 
     Ok(hash_2)
 }
@@ -129,6 +153,33 @@ mod tests {
         assert_eq!(hex::encode(result), expect_hex);
     }
 
+    #[test]
+    fn test_get_work_par_reference() {
+        let expect_hex = "6fe9eddc39bb4183c44853c41876801be94a138ea9adea89f40a08442d2f79b8";
+        let header_all_zeroes = vec![0; HEADER_SIZE];
+        let result = get_work_par_reference(header_all_zeroes).unwrap();
+        assert_eq!(hex::encode(result), expect_hex);
+    }
+
+    #[cfg(feature = "fast")]
+    #[test]
+    fn test_fast_matches_reference() {
+        for i in 0..64u32 {
+            let header: Vec<u8> = (0..HEADER_SIZE)
+                .map(|j| (j as u32).wrapping_mul(31).wrapping_add(i * 7) as u8)
+                .collect();
+            let header = insert_nonce(header, i.wrapping_mul(0x9e3779b9)).unwrap();
+            assert_eq!(
+                get_work_par(header.clone()).unwrap(),
+                get_work_par_reference(header.clone()).unwrap()
+            );
+            assert_eq!(
+                blake3_hash(header.clone()),
+                blake3_reference_hash(header.clone())
+            );
+        }
+    }
+
     #[test]
     fn test_work() {
         let expect_hex = "00000004f0ac89d75f135f184abbf0a82fad1e07fb4a29adb159648d70adf474";